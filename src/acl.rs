@@ -0,0 +1,577 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::error::Error;
+use crate::types::TopicName;
+
+/// principal of a rule that matches every connection, authenticated or not
+pub const ANY_PRINCIPAL: &str = "*";
+
+/// name of file, under data root, holding rules granted and revoked by admins
+pub const ACL_CHANGES_FILE_NAME: &str = "__acls.toml";
+
+/// operation on a topic guarded by access control lists
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AclOperation {
+    Publish,
+    Subscribe,
+    Create,
+    Delete,
+    /// alter config of an existing topic
+    Alter,
+}
+
+/// exact topic name, or a prefix followed by `*` matching every topic
+/// starting with it (`*` alone matches every topic)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct TopicPattern(String);
+
+/// allows `principal` to perform `operation` on topics matching `topic`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct AclRule {
+    principal: String,
+    operation: AclOperation,
+    topic: TopicPattern,
+}
+
+/// ACL settings, presence of this section in server config enables authorization
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AclConfig {
+    /// principals allowed every operation, only they can manage rules
+    super_users: Vec<String>,
+    rules: Vec<AclRule>,
+}
+
+/// rules granted and revoked through admin commands, relative to rules of
+/// server config. kept apart from them so that edits to server config still
+/// take effect on restart.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+struct AclChanges {
+    granted: Vec<AclRule>,
+    /// rules of server config that were revoked
+    revoked: Vec<AclRule>,
+}
+
+/// access control lists shared by every connection handler, anything not
+/// allowed by a rule is denied.
+///
+/// rules granted or revoked through admin commands live in memory only,
+/// unless store is loaded with a path to persist them at.
+pub struct AclStore {
+    super_users: Vec<String>,
+    config_rules: Vec<AclRule>,
+    state: RwLock<(Vec<AclRule>, AclChanges)>, // effective rules and changes they are made of
+    changes_path: Option<PathBuf>,
+}
+
+impl AclOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Publish => "publish",
+            Self::Subscribe => "subscribe",
+            Self::Create => "create",
+            Self::Delete => "delete",
+            Self::Alter => "alter",
+        }
+    }
+}
+
+impl std::str::FromStr for AclOperation {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "publish" => Ok(Self::Publish),
+            "subscribe" => Ok(Self::Subscribe),
+            "create" => Ok(Self::Create),
+            "delete" => Ok(Self::Delete),
+            "alter" => Ok(Self::Alter),
+            _ => Err(Error::InvalidArgument(format!("unknown ACL operation `{value}`"))),
+        }
+    }
+}
+
+impl fmt::Display for AclOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl TopicPattern {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn matches(&self, topic_name: &TopicName) -> bool {
+        match self.0.strip_suffix('*') {
+            Some(prefix) => topic_name.as_str().starts_with(prefix),
+            None => topic_name.as_str() == self.0,
+        }
+    }
+}
+
+impl TryFrom<String> for TopicPattern {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let is_valid = match value.strip_suffix('*') {
+            Some(prefix) => prefix.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')),
+            None => TopicName::try_from(value.as_str()).is_ok(),
+        };
+
+        match is_valid {
+            true => Ok(Self(value)),
+            false => Err(Error::InvalidArgument(format!("invalid topic pattern `{value}`"))),
+        }
+    }
+}
+
+impl From<TopicPattern> for String {
+    fn from(value: TopicPattern) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Display for TopicPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AclRule {
+    pub fn new(principal: String, operation: AclOperation, topic: TopicPattern) -> Self {
+        Self { principal, operation, topic }
+    }
+
+    pub fn principal(&self) -> &str {
+        &self.principal
+    }
+
+    pub fn operation(&self) -> &AclOperation {
+        &self.operation
+    }
+
+    pub fn topic(&self) -> &TopicPattern {
+        &self.topic
+    }
+
+    fn allows(&self, principal: Option<&str>, operation: AclOperation, topic_name: &TopicName) -> bool {
+        let principal_matches = self.principal == ANY_PRINCIPAL || principal == Some(self.principal.as_str());
+
+        principal_matches && self.operation == operation && self.topic.matches(topic_name)
+    }
+}
+
+/// `<operation> <topic pattern> <principal>`, principal comes last as it
+/// may contain spaces (e.g. subject of a client certificate)
+impl fmt::Display for AclRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.operation, self.topic, self.principal)
+    }
+}
+
+/// parses `<operation> <topic pattern> <principal>`, as formatted by `Display`
+impl std::str::FromStr for AclRule {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidArgument(format!("invalid ACL rule `{value}`"));
+
+        let (operation, rule) = value.split_once(' ').ok_or_else(invalid)?;
+        let (topic, principal) = rule.split_once(' ').ok_or_else(invalid)?;
+        if principal.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self::new(principal.to_owned(), operation.parse()?, topic.to_owned().try_into()?))
+    }
+}
+
+impl AclConfig {
+    pub fn new(super_users: Vec<String>, rules: Vec<AclRule>) -> Self {
+        Self { super_users, rules }
+    }
+
+    pub fn super_users(&self) -> &[String] {
+        &self.super_users
+    }
+
+    pub fn rules(&self) -> &[AclRule] {
+        &self.rules
+    }
+}
+
+impl AclChanges {
+    /// rules of server config, minus revoked ones, plus granted ones
+    fn apply(&self, config_rules: &[AclRule]) -> Vec<AclRule> {
+        let mut rules: Vec<_> = config_rules.iter().filter(|rule| !self.revoked.contains(rule)).cloned().collect();
+        for rule in self.granted.iter() {
+            if !rules.contains(rule) {
+                rules.push(rule.clone());
+            }
+        }
+
+        rules
+    }
+}
+
+impl AclStore {
+    pub fn new(acl_config: AclConfig) -> Self {
+        Self {
+            super_users: acl_config.super_users,
+            state: RwLock::new((acl_config.rules.clone(), AclChanges::default())),
+            config_rules: acl_config.rules,
+            changes_path: None,
+        }
+    }
+
+    /// same as `new`, but rules granted or revoked by admins are persisted
+    /// at `changes_path` and restored from it
+    pub async fn load<T: AsRef<Path>>(acl_config: AclConfig, changes_path: T) -> Result<Self, Error> {
+        let changes: AclChanges = match tokio::fs::read_to_string(changes_path.as_ref()).await {
+            Ok(changes) => toml::from_str(&changes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => AclChanges::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut acl_store = Self::new(acl_config);
+        acl_store.state = RwLock::new((changes.apply(&acl_store.config_rules), changes));
+        acl_store.changes_path = Some(changes_path.as_ref().to_owned());
+
+        Ok(acl_store)
+    }
+
+    /// makes `changes` effective once they are persisted
+    async fn update(&self, state: &mut (Vec<AclRule>, AclChanges), changes: AclChanges) -> Result<(), Error> {
+        if let Some(ref changes_path) = self.changes_path {
+            if let Some(dir_path) = changes_path.parent() {
+                tokio::fs::create_dir_all(dir_path).await?;
+            }
+            crate::topic::write_atomically(changes_path, toml::to_string(&changes)?.as_bytes(), true).await?;
+        }
+
+        *state = (changes.apply(&self.config_rules), changes);
+
+        Ok(())
+    }
+
+    pub fn is_super_user(&self, principal: Option<&str>) -> bool {
+        principal.is_some_and(|principal| self.super_users.iter().any(|super_user| super_user == principal))
+    }
+
+    pub async fn authorize(&self, principal: Option<&str>, operation: AclOperation, topic_name: &TopicName) -> Result<(), Error> {
+        if self.is_super_user(principal) {
+            return Ok(());
+        }
+
+        match self.state.read().await.0.iter().any(|rule| rule.allows(principal, operation, topic_name)) {
+            true => Ok(()),
+            false => Err(Error::NotAuthorized(format!(
+                "{} is not allowed to {operation} topic `{topic_name}`",
+                principal.map_or("anonymous".to_owned(), |principal| format!("`{principal}`")),
+            ))),
+        }
+    }
+
+    /// allows principals that are allowed at least one operation on topic,
+    /// e.g. to list or describe it
+    pub async fn authorize_any(&self, principal: Option<&str>, topic_name: &TopicName) -> Result<(), Error> {
+        if self.is_super_user(principal) {
+            return Ok(());
+        }
+
+        match self.state.read().await.0.iter().any(|rule| rule.allows(principal, rule.operation, topic_name)) {
+            true => Ok(()),
+            false => Err(Error::NotAuthorized(format!(
+                "{} is not allowed any operation on topic `{topic_name}`",
+                principal.map_or("anonymous".to_owned(), |principal| format!("`{principal}`")),
+            ))),
+        }
+    }
+
+    /// only super users can manage rules
+    pub fn authorize_management(&self, principal: Option<&str>) -> Result<(), Error> {
+        match self.is_super_user(principal) {
+            true => Ok(()),
+            false => Err(Error::NotAuthorized("only super users can manage ACLs".to_owned())),
+        }
+    }
+
+    pub async fn grant(&self, rule: AclRule) -> Result<(), Error> {
+        let mut state = self.state.write().await;
+        if state.0.contains(&rule) {
+            return Ok(());
+        }
+
+        let mut changes = state.1.clone();
+        match self.config_rules.contains(&rule) {
+            true => changes.revoked.retain(|revoked| revoked != &rule),
+            false => changes.granted.push(rule),
+        }
+
+        self.update(&mut state, changes).await
+    }
+
+    /// returns `false` if there was no such rule
+    pub async fn revoke(&self, rule: &AclRule) -> Result<bool, Error> {
+        let mut state = self.state.write().await;
+        if !state.0.contains(rule) {
+            return Ok(false);
+        }
+
+        let mut changes = state.1.clone();
+        changes.granted.retain(|granted| granted != rule);
+        if self.config_rules.contains(rule) {
+            changes.revoked.push(rule.clone());
+        }
+
+        self.update(&mut state, changes).await?;
+
+        Ok(true)
+    }
+
+    pub async fn rules(&self) -> Vec<AclRule> {
+        self.state.read().await.0.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::test;
+
+    fn rule(principal: &str, operation: AclOperation, topic: &str) -> AclRule {
+        AclRule::new(principal.to_owned(), operation, topic.to_owned().try_into().unwrap())
+    }
+
+    #[test]
+    async fn topic_pattern_test_01() {
+        let pattern = TopicPattern::try_from("orders.*".to_owned()).unwrap();
+        assert!(pattern.matches(&"orders.eu".parse().unwrap()));
+        assert!(!pattern.matches(&"order".parse().unwrap()));
+
+        assert!(TopicPattern::try_from("*".to_owned()).unwrap().matches(&"foo".parse().unwrap()));
+        assert!(TopicPattern::try_from("foo".to_owned()).unwrap().matches(&"foo".parse().unwrap()));
+
+        for pattern in ["", "fo*o", "f/o*", "**"] {
+            assert!(TopicPattern::try_from(pattern.to_owned()).is_err());
+        }
+    }
+
+    #[test]
+    async fn acl_rule_parse_test_01() {
+        let parsed: AclRule = "publish orders.* CN=alice, O=acme".parse().unwrap();
+        assert_eq!(parsed, rule("CN=alice, O=acme", AclOperation::Publish, "orders.*"));
+        assert_eq!(parsed.to_string().parse::<AclRule>().unwrap(), parsed);
+
+        for rule in ["", "publish orders.*", "publish orders.* ", "read orders.* alice", "publish a/b alice"] {
+            assert!(matches!(rule.parse::<AclRule>(), Err(Error::InvalidArgument(_))));
+        }
+    }
+
+    #[test]
+    async fn acl_store_test_01() {
+        let acl_store = AclStore::new(AclConfig::new(
+            vec!["admin".to_owned()],
+            vec![rule("alice", AclOperation::Publish, "orders.*"), rule("*", AclOperation::Subscribe, "public")],
+        ));
+        let orders: TopicName = "orders.eu".parse().unwrap();
+        let public: TopicName = "public".parse().unwrap();
+
+        assert!(acl_store.authorize(Some("alice"), AclOperation::Publish, &orders).await.is_ok());
+        assert!(acl_store.authorize(Some("alice"), AclOperation::Subscribe, &public).await.is_ok());
+        assert!(acl_store.authorize(None, AclOperation::Subscribe, &public).await.is_ok());
+        assert!(acl_store.authorize(Some("admin"), AclOperation::Delete, &orders).await.is_ok());
+
+        assert!(matches!(
+            acl_store.authorize(Some("bob"), AclOperation::Publish, &orders).await,
+            Err(Error::NotAuthorized(_))
+        ));
+        assert!(acl_store.authorize(Some("alice"), AclOperation::Subscribe, &orders).await.is_err());
+
+        assert!(acl_store.authorize_any(Some("alice"), &orders).await.is_ok());
+        assert!(acl_store.authorize_any(Some("admin"), &orders).await.is_ok());
+        assert!(acl_store.authorize_any(None, &public).await.is_ok());
+        assert!(matches!(acl_store.authorize_any(None, &orders).await, Err(Error::NotAuthorized(_))));
+
+        acl_store.grant(rule("bob", AclOperation::Publish, "*")).await.unwrap();
+        assert!(acl_store.authorize(Some("bob"), AclOperation::Publish, &orders).await.is_ok());
+        assert!(acl_store.revoke(&rule("bob", AclOperation::Publish, "*")).await.unwrap());
+        assert!(!acl_store.revoke(&rule("bob", AclOperation::Publish, "*")).await.unwrap());
+        assert!(acl_store.authorize(Some("bob"), AclOperation::Publish, &orders).await.is_err());
+
+        assert!(acl_store.authorize_management(Some("admin")).is_ok());
+        assert!(acl_store.authorize_management(Some("alice")).is_err());
+    }
+
+    #[test]
+    async fn acl_config_deserialize_test_01() {
+        let acl_config: AclConfig = toml::from_str(
+            r#"
+            super_users = ["admin"]
+
+            [[rules]]
+            principal = "CN=alice, O=acme"
+            operation = "publish"
+            topic = "orders.*"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(acl_config.rules(), &[rule("CN=alice, O=acme", AclOperation::Publish, "orders.*")]);
+        assert_eq!(acl_config.rules()[0].to_string(), "publish orders.* CN=alice, O=acme");
+
+        assert!(toml::from_str::<AclConfig>("[[rules]]\nprincipal = \"a\"\noperation = \"read\"\ntopic = \"*\"").is_err());
+        assert!(toml::from_str::<AclConfig>("[[rules]]\nprincipal = \"a\"\noperation = \"publish\"\ntopic = \"a/b\"").is_err());
+    }
+
+    #[test]
+    async fn acl_enforcement_test_01() {
+        use crate::broker::{Authenticator, Broker, CredentialStore, MemoryListener, SimpleAdminConnectionHandler};
+        use crate::topic::TopicRegistry;
+        use std::sync::Arc;
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let root_path = "./acl_enforcement_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let mut credential_store = CredentialStore::default();
+        credential_store.add_token("admin".to_owned(), "admin-token");
+        credential_store.add_token("alice".to_owned(), "alice-token");
+
+        let acl_store = Arc::new(AclStore::new(AclConfig::new(vec!["admin".to_owned()], vec![])));
+        let handler = SimpleAdminConnectionHandler::new(Arc::new(TopicRegistry::new(root_path)))
+            .with_authenticator(Arc::new(Authenticator::new(credential_store)))
+            .with_acl_store(acl_store);
+
+        let (mut listener, connector) = MemoryListener::new(1024);
+        let mut broker = Broker::new();
+        let termination_signal_sender = broker.get_termination_signal_sender().await;
+        let broker = tokio::spawn(async move { broker.serve(&mut listener, Arc::new(handler)).await });
+
+        let mut alice = BufReader::new(connector.connect().await.unwrap());
+        let mut admin = BufReader::new(connector.connect().await.unwrap());
+
+        let mut responses = String::new();
+        alice.write_all(b":token alice-token\n#orders.eu\n/list\n").await.unwrap();
+        for _ in 0..3 {
+            alice.read_line(&mut responses).await.unwrap();
+        }
+        assert_eq!(
+            responses,
+            "+alice\n-NotAuthorized not authorized: `alice` is not allowed to create topic `orders.eu`\n\
+             -NotAuthorized not authorized: only super users can manage ACLs\n"
+        );
+
+        let mut responses = String::new();
+        admin.write_all(b":token admin-token\n/grant create orders.* alice\n/list\n").await.unwrap();
+        for _ in 0..4 {
+            admin.read_line(&mut responses).await.unwrap();
+        }
+        assert_eq!(responses, "+admin\n+\n*1 0\ncreate orders.* alice\n");
+
+        let mut responses = String::new();
+        alice.write_all(b"#orders.eu\n").await.unwrap();
+        alice.read_line(&mut responses).await.unwrap();
+        assert_eq!(responses, "+\n");
+
+        let mut responses = String::new();
+        admin.write_all(b"/revoke create orders.* alice\n/revoke create orders.* alice\n").await.unwrap();
+        admin.read_line(&mut responses).await.unwrap();
+        admin.read_line(&mut responses).await.unwrap();
+        assert_eq!(responses, "+\n-InvalidArgument invalid argument: no such ACL rule `create orders.* alice`\n");
+
+        termination_signal_sender.send(()).unwrap();
+        broker.await.unwrap();
+    }
+
+    #[test]
+    async fn acl_enforcement_test_02() {
+        use crate::broker::{Authenticator, Broker, CredentialStore, MemoryListener, SimplePublisherConnectionHandler};
+        use crate::topic::TopicRegistry;
+        use crate::types::TopicMetaData;
+        use std::sync::Arc;
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let root_path = "./acl_enforcement_test_02";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = Arc::new(TopicRegistry::new(root_path));
+        registry.create_topic(TopicMetaData::new_with_few_defaults(registry.topic("orders".parse().unwrap()))).await.unwrap();
+
+        let mut credential_store = CredentialStore::default();
+        credential_store.add_token("alice".to_owned(), "alice-token");
+
+        let publish_orders = rule("alice", AclOperation::Publish, "orders");
+        let acl_store = Arc::new(AclStore::new(AclConfig::new(vec![], vec![publish_orders.clone()])));
+        let handler = SimplePublisherConnectionHandler::new(registry)
+            .with_authenticator(Arc::new(Authenticator::new(credential_store)))
+            .with_acl_store(acl_store.clone());
+
+        let (mut listener, connector) = MemoryListener::new(1024);
+        let mut broker = Broker::new();
+        let termination_signal_sender = broker.get_termination_signal_sender().await;
+        let broker = tokio::spawn(async move { broker.serve(&mut listener, Arc::new(handler)).await });
+
+        let mut alice = BufReader::new(connector.connect().await.unwrap());
+        let mut responses = String::new();
+        alice.write_all(b":token alice-token\n@orders\n>hello\n").await.unwrap();
+        for _ in 0..3 {
+            alice.read_line(&mut responses).await.unwrap();
+        }
+        assert_eq!(responses, "+alice\n+\n+0\n");
+
+        // revoking a rule affects connections that already selected the topic
+        assert!(acl_store.revoke(&publish_orders).await.unwrap());
+        let mut responses = String::new();
+        alice.write_all(b">hello\n").await.unwrap();
+        alice.read_line(&mut responses).await.unwrap();
+        assert_eq!(responses, "-NotAuthorized not authorized: `alice` is not allowed to publish topic `orders`\n");
+
+        termination_signal_sender.send(()).unwrap();
+        broker.await.unwrap();
+    }
+
+    #[test]
+    async fn acl_store_persistence_test_01() {
+        let root_path = "./acl_store_persistence_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+        let changes_path = Path::new(root_path).join(ACL_CHANGES_FILE_NAME);
+        let orders: TopicName = "orders".parse().unwrap();
+
+        let acl_config = AclConfig::new(vec![], vec![rule("alice", AclOperation::Publish, "orders")]);
+        let acl_store = AclStore::load(acl_config.clone(), &changes_path).await.unwrap();
+        acl_store.grant(rule("bob", AclOperation::Publish, "orders")).await.unwrap();
+        assert!(acl_store.revoke(&rule("alice", AclOperation::Publish, "orders")).await.unwrap());
+
+        // admin changes survive a restart, rules revoked from server config stay revoked
+        let acl_store = AclStore::load(acl_config, &changes_path).await.unwrap();
+        assert!(acl_store.authorize(Some("bob"), AclOperation::Publish, &orders).await.is_ok());
+        assert!(acl_store.authorize(Some("alice"), AclOperation::Publish, &orders).await.is_err());
+
+        // while rules added to server config in the meantime take effect
+        let acl_config = AclConfig::new(
+            vec![],
+            vec![rule("alice", AclOperation::Publish, "orders"), rule("carol", AclOperation::Publish, "orders")],
+        );
+        let acl_store = AclStore::load(acl_config.clone(), &changes_path).await.unwrap();
+        assert!(acl_store.authorize(Some("carol"), AclOperation::Publish, &orders).await.is_ok());
+        assert!(acl_store.authorize(Some("alice"), AclOperation::Publish, &orders).await.is_err());
+
+        // granting a revoked rule of server config again restores it
+        acl_store.grant(rule("alice", AclOperation::Publish, "orders")).await.unwrap();
+        let acl_store = AclStore::load(acl_config, &changes_path).await.unwrap();
+        assert!(acl_store.authorize(Some("alice"), AclOperation::Publish, &orders).await.is_ok());
+
+        tokio::fs::write(&changes_path, "granted = 1").await.unwrap();
+        assert!(AclStore::load(AclConfig::default(), &changes_path).await.is_err());
+    }
+}
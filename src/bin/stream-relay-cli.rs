@@ -0,0 +1,464 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use bytes::Bytes;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use futures::StreamExt;
+use rustls::pki_types::ServerName;
+use rustls::RootCertStore;
+use stream_relay::client::{AdminClient, ClientOptions, Consumer, Producer, TcpConnector};
+use stream_relay::sesp::{ConfigOptions, Credentials};
+use stream_relay::topic::IsolationLevel;
+use stream_relay::types::{Message, TopicName};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+
+/// command-line client of stream-relay server
+#[derive(Parser, Debug)]
+#[command(name = "stream-relay-cli", version)]
+struct Cli {
+    #[command(flatten)]
+    connection: ConnectionArgs,
+    #[command(subcommand)]
+    command: CliCommand,
+}
+
+#[derive(Args, Debug)]
+struct ConnectionArgs {
+    #[arg(long, env = "STREAM_RELAY_ADMIN_ADDR", default_value = "127.0.0.1:7070")]
+    admin_addr: String,
+    #[arg(long, env = "STREAM_RELAY_PUBLISHER_ADDR", default_value = "127.0.0.1:7071")]
+    publisher_addr: String,
+    #[arg(long, env = "STREAM_RELAY_SUBSCRIBER_ADDR", default_value = "127.0.0.1:7072")]
+    subscriber_addr: String,
+    /// authenticate with a static API token
+    #[arg(long, env = "STREAM_RELAY_TOKEN", conflicts_with = "username")]
+    token: Option<String>,
+    /// authenticate as user, requires `--password`
+    #[arg(long, env = "STREAM_RELAY_USERNAME", requires = "password")]
+    username: Option<String>,
+    #[arg(long, env = "STREAM_RELAY_PASSWORD", requires = "username")]
+    password: Option<String>,
+    /// connect over TLS, trusting CA certificates of this PEM file
+    #[arg(long)]
+    tls_ca: Option<PathBuf>,
+    /// name server certificate is verified against, host of address by default
+    #[arg(long, requires = "tls_ca")]
+    tls_server_name: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum CliCommand {
+    /// manage topics
+    #[command(subcommand)]
+    Topic(TopicCommand),
+    /// publish messages read from stdin or a file, on an invalid message
+    /// every message before it is published and none after it
+    Produce(ProduceArgs),
+    /// print messages of a topic
+    Consume(ConsumeArgs),
+}
+
+#[derive(Subcommand, Debug)]
+enum TopicCommand {
+    /// create a topic, options not given fall back to server defaults
+    Create {
+        topic: TopicName,
+        /// config options, e.g. `num_of_segments=4`
+        #[arg(value_parser = parse_config_option)]
+        options: Vec<(String, String)>,
+    },
+    Delete {
+        topic: TopicName,
+    },
+    /// list topics, one per line
+    List,
+    /// print config options and last flushed offset of a topic
+    Describe {
+        topic: TopicName,
+    },
+}
+
+#[derive(Args, Debug)]
+struct ProduceArgs {
+    topic: TopicName,
+    /// read messages from this file instead of stdin
+    #[arg(long, short)]
+    file: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = InputFormat::Lines)]
+    format: InputFormat,
+    /// max number of messages published as one batch
+    #[arg(long, default_value_t = stream_relay::sesp::MAX_BATCH_LEN)]
+    batch_len: usize,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum InputFormat {
+    /// one message per line
+    Lines,
+    /// every message is preceded by its length as a 4 byte big-endian integer
+    LengthDelimited,
+}
+
+#[derive(Args, Debug)]
+struct ConsumeArgs {
+    topic: TopicName,
+    /// offset of first message to print
+    #[arg(long, default_value_t = 0, conflicts_with = "from_timestamp")]
+    offset: usize,
+    /// start from first message that may have been published at or after
+    /// this unix timestamp in milliseconds
+    #[arg(long)]
+    from_timestamp: Option<u64>,
+    /// keep waiting for new messages once caught up, until Ctrl-C
+    #[arg(long)]
+    follow: bool,
+    /// stop after printing this many messages
+    #[arg(long)]
+    max_messages: Option<usize>,
+    /// skip messages of aborted and ongoing transactions
+    #[arg(long)]
+    read_committed: bool,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Value)]
+    output: OutputFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum OutputFormat {
+    /// value of every message on its own line
+    Value,
+    /// offset and value of every message separated by a tab
+    OffsetValue,
+    /// `{"offset":<offset>,"value":<value>}` per line
+    Json,
+}
+
+fn parse_config_option(option: &str) -> Result<(String, String), String> {
+    match option.split_once('=') {
+        Some((key, value)) if !key.is_empty() && !value.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => Err(format!("expected `key=value`, got `{option}`")),
+    }
+}
+
+impl ConnectionArgs {
+    fn options(&self) -> ClientOptions {
+        let options = ClientOptions::new("stream-relay-cli");
+
+        match (&self.token, &self.username, &self.password) {
+            (Some(token), _, _) => options.with_credentials(Credentials::Token(token.clone())),
+            (None, Some(username), Some(password)) => options.with_credentials(Credentials::Password {
+                username: username.clone(),
+                password: password.clone(),
+            }),
+            _ => options,
+        }
+    }
+
+    async fn connector(&self, addr: &str) -> Result<TcpConnector, Box<dyn Error>> {
+        let connector = TcpConnector::new(addr);
+        let ca_path = match self.tls_ca {
+            Some(ref ca_path) => ca_path,
+            None => return Ok(connector),
+        };
+
+        let server_name = match self.tls_server_name {
+            Some(ref server_name) => server_name.clone(),
+            None => addr.rsplit_once(':').map_or(addr, |(host, _)| host).to_owned(),
+        };
+
+        Ok(connector.with_tls(tls_config(ca_path).await?, ServerName::try_from(server_name)?))
+    }
+}
+
+async fn tls_config(ca_path: &Path) -> Result<Arc<rustls::ClientConfig>, Box<dyn Error>> {
+    let pem = tokio::fs::read(ca_path).await?;
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut &pem[..]) {
+        roots.add(cert?)?;
+    }
+    if roots.is_empty() {
+        return Err(format!("no certificate found in {}", ca_path.display()).into());
+    }
+
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    Ok(Arc::new(config))
+}
+
+async fn topic(connection: &ConnectionArgs, command: TopicCommand) -> Result<(), Box<dyn Error>> {
+    let mut admin_client = AdminClient::new(connection.connector(&connection.admin_addr).await?, connection.options());
+
+    match command {
+        TopicCommand::Create { topic, options } => admin_client.create_topic(&topic, options).await?,
+        TopicCommand::Delete { topic } => admin_client.delete_topic(&topic).await?,
+        TopicCommand::List => {
+            for topic_name in admin_client.list_topics().await? {
+                println!("{topic_name}");
+            }
+        }
+        TopicCommand::Describe { topic } => {
+            let options: ConfigOptions = admin_client.describe_topic(&topic).await?;
+            for (key, value) in options {
+                println!("{key}={value}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// next message of `input`, `None` at end of input. messages longer than
+/// `max_len` bytes, not counting a trailing line terminator, are rejected
+/// before being read in full, as are length-delimited ones containing a
+/// line terminator other than a trailing one.
+async fn read_message<R: AsyncRead + Unpin>(input: &mut BufReader<R>, format: InputFormat, max_len: usize) -> std::io::Result<Option<Bytes>> {
+    let invalid_data = |reason: String| std::io::Error::new(std::io::ErrorKind::InvalidData, reason);
+    let too_long = || invalid_data(format!("message exceeds max length of {max_len} bytes"));
+    let line_len = |value: &[u8]| value.strip_suffix(b"\n").unwrap_or(value).len();
+
+    match format {
+        InputFormat::Lines => {
+            // reads one byte past a line of max length and its terminator, enough to tell it's too long
+            let mut line = vec![];
+            match (&mut *input).take(max_len as u64 + 2).read_until(b'\n', &mut line).await? {
+                0 => Ok(None),
+                _ if line_len(&line) > max_len => Err(too_long()),
+                _ => Ok(Some(Bytes::from(line))),
+            }
+        }
+        InputFormat::LengthDelimited => {
+            // input may only end right before a length prefix
+            let mut prefix = [0; 4];
+            if input.read(&mut prefix[..1]).await? == 0 {
+                return Ok(None);
+            }
+            match input.read_exact(&mut prefix[1..]).await {
+                Ok(_) => (),
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Err(invalid_data("input ends within a length prefix".to_owned())),
+                Err(e) => return Err(e),
+            }
+
+            let len = u32::from_be_bytes(prefix) as usize;
+            if len > max_len + 1 {
+                return Err(too_long());
+            }
+
+            let mut value = vec![0; len];
+            input.read_exact(&mut value).await?;
+            if line_len(&value) > max_len {
+                return Err(too_long());
+            }
+            if value.strip_suffix(b"\n").unwrap_or(&value).contains(&b'\n') {
+                return Err(invalid_data("message can't contain a line terminator".to_owned()));
+            }
+
+            Ok(Some(Bytes::from(value)))
+        }
+    }
+}
+
+async fn produce(connection: &ConnectionArgs, args: ProduceArgs) -> Result<(), Box<dyn Error>> {
+    let input: Box<dyn AsyncRead + Unpin> = match args.file {
+        Some(ref path) => Box::new(tokio::fs::File::open(path).await?),
+        None => Box::new(tokio::io::stdin()),
+    };
+    let mut input = BufReader::new(input);
+
+    let connector = connection.connector(&connection.publisher_addr).await?;
+    let mut producer = Producer::new(connector, connection.options(), args.topic).with_max_batch_len(args.batch_len);
+    let max_len = producer.max_message_len();
+
+    // a message is counted once a batch holding it is published, not once it's buffered
+    let mut read = 0;
+    let mut published = 0;
+    let result = loop {
+        let value = match read_message(&mut input, args.format, max_len).await {
+            Ok(Some(value)) => value,
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(format!("message {} of input: {e}", read + 1)),
+        };
+        read += 1;
+
+        let buffered = producer.buffered();
+        match producer.send(value).await {
+            Ok(_) => published += buffered + 1 - producer.buffered(),
+            Err(e) => break Err(format!("publishing message {read} of input: {e}")),
+        }
+    };
+
+    // input is published as it is read, so messages before a failed one are published anyway
+    let buffered = producer.buffered();
+    let flushed = producer.flush().await.map(|_| published += buffered);
+    match result.and(flushed.map_err(|e| format!("publishing input: {e}"))) {
+        Ok(()) => eprintln!("published {published} messages"),
+        Err(e) => return Err(format!("{e}, published {published} messages").into()),
+    }
+
+    Ok(())
+}
+
+fn format_message(msg: &Message, output: OutputFormat) -> Vec<u8> {
+    let value = msg.value().strip_suffix(b"\n").unwrap_or(msg.value());
+    let offset = msg.offset().map_or(String::new(), |offset| offset.to_string());
+
+    let mut line = match output {
+        OutputFormat::Value => value.to_vec(),
+        OutputFormat::OffsetValue => [format!("{offset}\t").as_bytes(), value].concat(),
+        OutputFormat::Json => serde_json::json!({ "offset": msg.offset(), "value": String::from_utf8_lossy(value) })
+            .to_string()
+            .into_bytes(),
+    };
+    line.push(b'\n');
+
+    line
+}
+
+async fn consume(connection: &ConnectionArgs, args: ConsumeArgs) -> Result<(), Box<dyn Error>> {
+    let connector = connection.connector(&connection.subscriber_addr).await?;
+    let mut consumer = Consumer::new(connector, connection.options(), args.topic).with_offset(args.offset);
+    if args.read_committed {
+        consumer = consumer.with_isolation_level(IsolationLevel::ReadCommitted);
+    }
+    if let Some(max_messages) = args.max_messages {
+        consumer = consumer.with_max_messages(max_messages);
+    }
+    if let Some(timestamp_ms) = args.from_timestamp {
+        consumer.seek_to_timestamp(UNIX_EPOCH + Duration::from_millis(timestamp_ms)).await?;
+    }
+
+    let mut stdout = tokio::io::stdout();
+    let mut remaining = args.max_messages.unwrap_or(usize::MAX);
+
+    if !args.follow {
+        // stops once caught up with topic
+        while remaining > 0 {
+            let msgs = consumer.fetch().await?;
+            if msgs.is_empty() {
+                break;
+            }
+
+            for msg in msgs.iter().take(remaining) {
+                stdout.write_all(&format_message(msg, args.output)).await?;
+            }
+            remaining = remaining.saturating_sub(msgs.len());
+        }
+
+        stdout.flush().await?;
+        return Ok(());
+    }
+
+    let mut stream = Box::pin(consumer.into_stream());
+    while remaining > 0 {
+        let msg = tokio::select! {
+            msg = stream.next() => msg,
+            _ = tokio::signal::ctrl_c() => None,
+        };
+
+        match msg {
+            Some(msg) => stdout.write_all(&format_message(&msg?, args.output)).await?,
+            None => break,
+        }
+        stdout.flush().await?;
+        remaining -= 1;
+    }
+
+    Ok(())
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        CliCommand::Topic(command) => topic(&cli.connection, command).await,
+        CliCommand::Produce(args) => produce(&cli.connection, args).await,
+        CliCommand::Consume(args) => consume(&cli.connection, args).await,
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run(Cli::parse()).await {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_test_01() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from(["stream-relay-cli", "topic", "create", "orders", "num_of_segments=4"]).unwrap();
+        match cli.command {
+            CliCommand::Topic(TopicCommand::Create { topic, options }) => {
+                assert_eq!(topic.as_str(), "orders");
+                assert_eq!(options, vec![("num_of_segments".to_owned(), "4".to_owned())]);
+            }
+            command => panic!("unexpected command {command:?}"),
+        }
+
+        assert!(Cli::try_parse_from(["stream-relay-cli", "topic", "create", "orders", "num_of_segments"]).is_err());
+        assert!(Cli::try_parse_from(["stream-relay-cli", "consume", "orders", "--offset", "1", "--from-timestamp", "1"]).is_err());
+        assert!(Cli::try_parse_from(["stream-relay-cli", "--username", "alice", "topic", "list"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn read_message_test_01() {
+        let mut input = BufReader::new(&b"first\nsecond"[..]);
+        assert_eq!(read_message(&mut input, InputFormat::Lines, 16).await.unwrap(), Some(Bytes::from("first\n")));
+        assert_eq!(read_message(&mut input, InputFormat::Lines, 16).await.unwrap(), Some(Bytes::from("second")));
+        assert_eq!(read_message(&mut input, InputFormat::Lines, 16).await.unwrap(), None);
+
+        let mut input = BufReader::new(&b"\x00\x00\x00\x05first\x00\x00\x00\x09second"[..]);
+        assert_eq!(read_message(&mut input, InputFormat::LengthDelimited, 16).await.unwrap(), Some(Bytes::from("first")));
+        assert!(read_message(&mut input, InputFormat::LengthDelimited, 16).await.is_err());
+
+        let mut input = BufReader::new(&b"\x00\x00\x00\x06first\n"[..]);
+        assert_eq!(read_message(&mut input, InputFormat::LengthDelimited, 16).await.unwrap(), Some(Bytes::from("first\n")));
+        assert_eq!(read_message(&mut input, InputFormat::LengthDelimited, 16).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn read_message_test_02() {
+        let read_error = |input: &'static [u8]| async move {
+            read_message(&mut BufReader::new(input), InputFormat::LengthDelimited, 16).await.unwrap_err().to_string()
+        };
+
+        // rejected without allocating a buffer of the claimed length
+        assert_eq!(read_error(b"\xff\xff\xff\xffhello").await, "message exceeds max length of 16 bytes");
+        assert_eq!(read_error(b"\x00\x00\x00\x11seventeen bytes!!").await, "message exceeds max length of 16 bytes");
+        assert_eq!(read_error(b"\x00\x00").await, "input ends within a length prefix");
+        assert_eq!(read_error(b"\x00\x00\x00\x0bhello\nworld").await, "message can't contain a line terminator");
+    }
+
+    #[tokio::test]
+    async fn read_message_test_03() {
+        // a trailing line terminator doesn't count towards max length
+        let mut input = BufReader::new(&b"\x00\x00\x00\x11sixteen bytes!!!\n"[..]);
+        assert_eq!(read_message(&mut input, InputFormat::LengthDelimited, 16).await.unwrap(), Some(Bytes::from("sixteen bytes!!!\n")));
+
+        let mut input = BufReader::new(&b"sixteen bytes!!!\nseventeen bytes!!\nnever read"[..]);
+        assert_eq!(read_message(&mut input, InputFormat::Lines, 16).await.unwrap(), Some(Bytes::from("sixteen bytes!!!\n")));
+        let e = read_message(&mut input, InputFormat::Lines, 16).await.unwrap_err();
+        assert_eq!((e.kind(), e.to_string()), (std::io::ErrorKind::InvalidData, "message exceeds max length of 16 bytes".to_owned()));
+
+        // a line without a terminator is read no further than needed to reject it
+        let mut input = BufReader::new(&[b'x'; 1 << 20][..]);
+        assert!(read_message(&mut input, InputFormat::Lines, 16).await.is_err());
+        assert_eq!(input.get_ref().len() + input.buffer().len(), (1 << 20) - 18);
+    }
+
+    #[test]
+    fn format_message_test_01() {
+        let msg = Message::new(Bytes::from("hello \"world\"\n"), Some(7));
+        assert_eq!(format_message(&msg, OutputFormat::Value), b"hello \"world\"\n");
+        assert_eq!(format_message(&msg, OutputFormat::OffsetValue), b"7\thello \"world\"\n");
+        assert_eq!(format_message(&msg, OutputFormat::Json), br#"{"offset":7,"value":"hello \"world\""}"#.iter().chain(b"\n").copied().collect::<Vec<_>>());
+    }
+}
@@ -1,20 +1,280 @@
-use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
-use tokio::net::TcpStream;
+use bytes::Bytes;
+use tokio::select;
 use tokio::sync::broadcast;
+use tracing::{debug, instrument};
 
+use super::auth::{AuthSession, Authenticator};
+use super::{error_response, ConnectionHandler, ConnectionInfo, Transport};
+use crate::acl::{AclOperation, AclStore};
+use crate::error::Error;
+use crate::metrics::Metrics;
+use crate::sesp::{Command, ConfigOptions, Connection, Response, DEFAULT_MAX_FRAME_SIZE};
+use crate::topic::TopicRegistry;
+use crate::types::{Message, TopicMetaData, TopicName};
 
-use super::ConnectionHandler;
+pub struct SimpleAdminConnectionHandler {
+    registry: Arc<TopicRegistry>,
+    max_frame_size: usize,
+    idle_timeout: Option<Duration>,
+    authenticator: Option<Arc<Authenticator>>,
+    metrics: Arc<Metrics>,
+    acl_store: Option<Arc<AclStore>>,
+}
+
+impl SimpleAdminConnectionHandler {
+    pub fn new(registry: Arc<TopicRegistry>) -> Self {
+        Self {
+            registry,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            idle_timeout: None,
+            authenticator: None,
+            metrics: Arc::new(Metrics::new()),
+            acl_store: None,
+        }
+    }
+
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// records into `metrics` instead of a private instance
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// closes connections that send no command, not even a `Ping`, for `idle_timeout`
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// requires every connection to authenticate before any other command
+    pub fn with_authenticator(mut self, authenticator: Arc<Authenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    /// denies every topic operation not allowed by `acl_store` and lets
+    /// super users manage its rules
+    pub fn with_acl_store(mut self, acl_store: Arc<AclStore>) -> Self {
+        self.acl_store = Some(acl_store);
+        self
+    }
+
+    async fn authorize(&self, principal: Option<&str>, operation: AclOperation, topic_name: &TopicName) -> Result<(), Error> {
+        match self.acl_store {
+            Some(ref acl_store) => acl_store.authorize(principal, operation, topic_name).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn authorize_any(&self, principal: Option<&str>, topic_name: &TopicName) -> Result<(), Error> {
+        match self.acl_store {
+            Some(ref acl_store) => acl_store.authorize_any(principal, topic_name).await,
+            None => Ok(()),
+        }
+    }
+
+    fn acl_store(&self, principal: Option<&str>) -> Result<&AclStore, Error> {
+        let acl_store = self.acl_store.as_deref().ok_or(Error::NotSupported)?;
+        acl_store.authorize_management(principal)?;
+
+        Ok(acl_store)
+    }
+
+    /// one rule per line, offsets are indices of rules
+    async fn list_acls(&self, principal: Option<&str>) -> Result<Response, Error> {
+        let rules = self.acl_store(principal)?.rules().await;
+        let msgs = rules
+            .iter()
+            .enumerate()
+            .map(|(idx, rule)| Message::new(Bytes::from(rule.to_string()), Some(idx)))
+            .collect();
+
+        Ok(Response::Messages(msgs))
+    }
+
+    /// one topic per line, offsets are indices of topics. topics principal
+    /// isn't allowed any operation on are left out.
+    async fn list_topics(&self, principal: Option<&str>) -> Result<Response, Error> {
+        let mut topic_names = vec![];
+        for topic_name in self.registry.topics().await? {
+            if self.authorize_any(principal, &topic_name).await.is_ok() {
+                topic_names.push(topic_name);
+            }
+        }
+
+        let msgs = topic_names
+            .iter()
+            .enumerate()
+            .map(|(idx, topic_name)| Message::new(Bytes::from(topic_name.to_string()), Some(idx)))
+            .collect();
+
+        Ok(Response::Messages(msgs))
+    }
+
+    /// every config option of topic followed by its last flushed offset, e.g.
+    /// `num_of_msg_per_file=32 ... last_flushed_offset=1001`
+    async fn describe_topic(&self, principal: Option<&str>, topic_name: TopicName) -> Result<Response, Error> {
+        self.authorize_any(principal, &topic_name).await?;
+        let topic_metadata = self.registry.metadata(&topic_name).await?;
 
-pub struct SimpleAdminConnectionHandler;
+        let mut options = topic_metadata.config_options();
+        let last_flushed_offset = topic_metadata.last_flushed_offset().map_or("none".to_owned(), |offset| offset.to_string());
+        options.push(("last_flushed_offset".to_owned(), last_flushed_offset));
+
+        let options: Vec<_> = options.iter().map(|(key, value)| format!("{key}={value}")).collect();
+        Ok(Response::Positive(options.join(" ")))
+    }
+
+    async fn create_topic(&self, topic_name: TopicName, options: ConfigOptions) -> Result<(), Error> {
+        let mut topic_metadata = TopicMetaData::new_with_few_defaults(self.registry.topic(topic_name));
+        for (key, value) in options.iter() {
+            topic_metadata.set_config_option(key, value, false)?;
+        }
+
+        self.registry.create_topic(topic_metadata).await
+    }
+
+    async fn handle_command(&self, principal: Option<&str>, command: Command) -> Result<Response, Error> {
+        match command {
+            Command::CreateTopic(topic_name, options) => {
+                self.authorize(principal, AclOperation::Create, &topic_name).await?;
+                self.create_topic(topic_name, options).await?;
+                Ok(Response::Positive(String::new()))
+            }
+            Command::AlterTopicConfig(topic_name, options) => {
+                self.authorize(principal, AclOperation::Alter, &topic_name).await?;
+                self.registry.alter_topic_config(&topic_name, &options).await?;
+                Ok(Response::Positive(String::new()))
+            }
+            Command::DeleteTopic(topic_name) => {
+                self.authorize(principal, AclOperation::Delete, &topic_name).await?;
+                self.registry.delete_topic(&topic_name).await?;
+                Ok(Response::Positive(String::new()))
+            }
+            Command::ListTopics => self.list_topics(principal).await,
+            Command::DescribeTopic(topic_name) => self.describe_topic(principal, topic_name).await,
+            Command::GrantAcl(rule) => {
+                self.acl_store(principal)?.grant(rule).await?;
+                Ok(Response::Positive(String::new()))
+            }
+            Command::RevokeAcl(rule) => match self.acl_store(principal)?.revoke(&rule).await? {
+                true => Ok(Response::Positive(String::new())),
+                false => Err(Error::InvalidArgument(format!("no such ACL rule `{rule}`"))),
+            },
+            Command::ListAcls => self.list_acls(principal).await,
+            Command::InvalidTopicName(e) => Err(Error::InvalidTopicName(e)),
+            Command::InvalidCommand => Err(Error::Protocol("invalid command".to_owned())),
+            _ => Err(Error::Protocol("not an admin command".to_owned())),
+        }
+    }
+}
 
 
 #[async_trait]
 impl ConnectionHandler for SimpleAdminConnectionHandler {
-    async fn handle_connection(stream: TcpStream, addr: SocketAddr, termination_signal_recvr: broadcast::Receiver<()>) {
-        
+    #[instrument(name = "admin", skip_all, fields(principal))]
+    async fn handle_connection<S: Transport>(self: Arc<Self>, stream: S, info: ConnectionInfo, mut termination_signal_recvr: broadcast::Receiver<()>) {
+        let mut connection = Connection::with_max_frame_size(stream, self.max_frame_size);
+        connection.set_idle_timeout(self.idle_timeout);
+        connection.set_metrics(self.metrics.clone());
+        let mut auth_session = AuthSession::new(self.authenticator.clone(), &info);
+
+        loop {
+            let command = select! {
+                // command being handled, if any, has been answered by now
+                _ = termination_signal_recvr.recv() => return connection.shutdown().await,
+                command = connection.read_command() => command,
+            };
+
+            let response = match command {
+                Ok(Some(command)) => {
+                    let name = command.name();
+                    self.metrics.record_command(name);
+                    match auth_session.filter(command).await {
+                        Ok(command) => match self.handle_command(auth_session.principal(), command).await {
+                            Ok(response) => response,
+                            Err(e) => error_response(name, e),
+                        },
+                        Err(response) => response,
+                    }
+                }
+                Ok(None) => {
+                    debug!("peer closed connection");
+                    break;
+                }
+                Err(e) => {
+                    debug!(error = %e, "closing connection");
+                    break;
+                }
+            };
+
+            self.metrics.record_response(&response);
 
+            if connection.write_response(response).await.is_err() {
+                break;
+            }
+        }
+
+        connection.close().await;
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::{Broker, MemoryConnector, MemoryListener, SimplePublisherConnectionHandler};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    /// serves `handler` over in-memory connections until termination signal
+    async fn spawn_broker<T: ConnectionHandler>(handler: T) -> (MemoryConnector, broadcast::Sender<()>, tokio::task::JoinHandle<()>) {
+        let (mut listener, connector) = MemoryListener::new(1024);
+        let mut broker = Broker::new();
+        let termination_signal_sender = broker.get_termination_signal_sender().await;
+        let handle = tokio::spawn(async move { broker.serve(&mut listener, Arc::new(handler)).await });
+
+        (connector, termination_signal_sender, handle)
+    }
+
+    async fn request<T: AsyncBufReadExt + AsyncWriteExt + Unpin>(client: &mut T, command: &[u8]) -> String {
+        client.write_all(command).await.unwrap();
+        let mut response = String::new();
+        client.read_line(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn admin_delete_topic_test_01() {
+        let root_path = "./admin_delete_topic_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = Arc::new(TopicRegistry::new(root_path));
+        let (admin_connector, admin_termination, admin) = spawn_broker(SimpleAdminConnectionHandler::new(registry.clone())).await;
+        let (publisher_connector, publisher_termination, publisher) = spawn_broker(SimplePublisherConnectionHandler::new(registry.clone())).await;
+
+        let mut admin_client = BufReader::new(admin_connector.connect().await.unwrap());
+        let mut publisher_client = BufReader::new(publisher_connector.connect().await.unwrap());
+        assert_eq!(request(&mut admin_client, b"#foo\n").await, "+\n");
+        assert_eq!(request(&mut publisher_client, b"@foo\n").await, "+\n");
+        assert_eq!(request(&mut publisher_client, b">hello\n").await, "+0\n");
+
+        // publishers still connected to a deleted topic are told it's gone
+        assert_eq!(request(&mut admin_client, b"!foo\n").await, "+\n");
+        assert_eq!(request(&mut publisher_client, b">world\n").await, "-NoSuchTopicExists topic `foo` doesn't exist\n");
+        assert_eq!(request(&mut admin_client, b"!foo\n").await, "-NoSuchTopicExists topic `foo` doesn't exist\n");
+        assert!(registry.topics().await.unwrap().is_empty());
+
+        for termination_signal_sender in [admin_termination, publisher_termination] {
+            termination_signal_sender.send(()).unwrap();
+        }
+        admin.await.unwrap();
+        publisher.await.unwrap();
+    }
+}
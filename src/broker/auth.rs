@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn, Span};
+
+use super::{ConnectionInfo, PeerAddr};
+use crate::error::Error;
+use crate::sesp::{Command, Credentials, Response};
+
+/// failed attempts allowed per peer and per username within `FAILED_ATTEMPTS_WINDOW`
+pub const MAX_FAILED_ATTEMPTS: u32 = 5;
+
+const FAILED_ATTEMPTS_WINDOW: Duration = Duration::from_secs(60);
+
+/// PBKDF2 iterations used for passwords whose entry doesn't specify any
+pub const DEFAULT_PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// passwords of unknown users are hashed with this salt, so that response time doesn't reveal which users exist
+const DUMMY_SALT: &str = "00000000000000000000000000000000";
+
+/// users and API tokens allowed to connect, read from a TOML file:
+///
+/// ```toml
+/// [users.alice]
+/// salt = "<hex>"
+/// password_hash = "<hex of PBKDF2-HMAC-SHA256(password, salt)>"
+/// iterations = 100000 # optional
+///
+/// [tokens.ci-pipeline]
+/// token_hash = "<hex of SHA-256(token)>"
+/// ```
+///
+/// user and token names become principals of connections authenticated with them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CredentialStore {
+    users: HashMap<String, UserEntry>,
+    tokens: HashMap<String, TokenEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct UserEntry {
+    salt: String,
+    password_hash: String,
+    #[serde(default = "default_pbkdf2_iterations")]
+    iterations: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TokenEntry {
+    token_hash: String,
+}
+
+/// verifies credentials of `Auth` commands, shared by every connection handler
+pub struct Authenticator {
+    credential_store: Arc<CredentialStore>,
+    failed_attempts: Mutex<HashMap<String, FailedAttempts>>,
+}
+
+struct FailedAttempts {
+    count: u32,
+    window_start: Instant,
+}
+
+/// authentication state of a single connection
+pub(crate) struct AuthSession {
+    authenticator: Option<Arc<Authenticator>>,
+    peer_addr: PeerAddr,
+    principal: Option<String>,
+}
+
+fn default_pbkdf2_iterations() -> u32 {
+    DEFAULT_PBKDF2_ITERATIONS
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// compares in time independent of position of first mismatch
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn hash_password(password: &str, salt: &str, iterations: u32) -> String {
+    let mut hash = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt.as_bytes(), iterations, &mut hash);
+
+    to_hex(&hash)
+}
+
+fn hash_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+impl CredentialStore {
+    pub async fn load<T: AsRef<Path>>(path: T) -> Result<Self, Error> {
+        let credential_store = tokio::fs::read_to_string(path).await?;
+
+        Ok(toml::from_str(&credential_store)?)
+    }
+
+    /// adds (or replaces) user `username`, `salt` should be random and unique per user
+    pub fn add_user(&mut self, username: String, password: &str, salt: String, iterations: u32) {
+        let password_hash = hash_password(password, &salt, iterations);
+        self.users.insert(username, UserEntry { salt, password_hash, iterations });
+    }
+
+    /// adds (or replaces) token named `name`, only hash of token is stored
+    pub fn add_token(&mut self, name: String, token: &str) {
+        self.tokens.insert(name, TokenEntry { token_hash: hash_token(token) });
+    }
+
+    /// principal authenticated by `credentials`, `None` if they are invalid
+    fn verify(&self, credentials: &Credentials) -> Option<String> {
+        match credentials {
+            Credentials::Password { username, password } => {
+                let user = match self.users.get(username) {
+                    Some(user) => user,
+                    None => {
+                        // as slow as the slowest known user
+                        let iterations = self.users.values().map(|user| user.iterations).max().unwrap_or(DEFAULT_PBKDF2_ITERATIONS);
+                        let _ = hash_password(password, DUMMY_SALT, iterations);
+                        return None;
+                    }
+                };
+                let password_hash = hash_password(password, &user.salt, user.iterations);
+
+                constant_time_eq(password_hash.as_bytes(), user.password_hash.as_bytes()).then(|| username.clone())
+            }
+            Credentials::Token(token) => {
+                let token_hash = hash_token(token);
+
+                // every entry is compared so that time taken doesn't reveal a match
+                self.tokens
+                    .iter()
+                    .filter(|(_, entry)| constant_time_eq(token_hash.as_bytes(), entry.token_hash.as_bytes()))
+                    .map(|(name, _)| name.clone())
+                    .last()
+            }
+        }
+    }
+}
+
+impl Authenticator {
+    pub fn new(credential_store: CredentialStore) -> Self {
+        Self {
+            credential_store: Arc::new(credential_store),
+            failed_attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn load<T: AsRef<Path>>(path: T) -> Result<Self, Error> {
+        Ok(Self::new(CredentialStore::load(path).await?))
+    }
+
+    /// keys failed attempts are counted against, every peer host and every username
+    fn rate_limit_keys(credentials: &Credentials, peer_addr: &PeerAddr) -> Vec<String> {
+        let peer = match peer_addr {
+            PeerAddr::Tcp(addr) => format!("peer:{}", addr.ip()),
+            peer_addr => format!("peer:{peer_addr}"),
+        };
+
+        match credentials {
+            Credentials::Password { username, .. } => vec![peer, format!("user:{username}")],
+            Credentials::Token(_) => vec![peer],
+        }
+    }
+
+    /// returns principal authenticated by `credentials`
+    pub async fn authenticate(&self, credentials: Credentials, peer_addr: &PeerAddr) -> Result<String, Error> {
+        let keys = Self::rate_limit_keys(&credentials, peer_addr);
+
+        // attempt counts as failed until proven otherwise, so that parallel attempts can't all slip under the limit
+        let is_rate_limited = {
+            let mut failed_attempts = self.failed_attempts.lock().await;
+            failed_attempts.retain(|_, attempts| attempts.window_start.elapsed() < FAILED_ATTEMPTS_WINDOW);
+
+            let is_rate_limited = keys
+                .iter()
+                .any(|key| failed_attempts.get(key).is_some_and(|attempts| attempts.count >= MAX_FAILED_ATTEMPTS));
+            if !is_rate_limited {
+                for key in keys.iter() {
+                    let attempts = failed_attempts.entry(key.clone()).or_insert_with(|| FailedAttempts {
+                        count: 0,
+                        window_start: Instant::now(),
+                    });
+                    attempts.count += 1;
+                }
+            }
+
+            is_rate_limited
+        };
+
+        if is_rate_limited {
+            warn!(peer = %peer_addr, "rejected authentication attempt, too many failed attempts");
+            return Err(Error::AuthenticationFailed("too many failed attempts, retry later".to_owned()));
+        }
+
+        // hashing passwords is deliberately slow, keep it off the async workers
+        let credential_store = self.credential_store.clone();
+        let verify_credentials = credentials.clone();
+        let principal = match tokio::task::spawn_blocking(move || credential_store.verify(&verify_credentials)).await {
+            Ok(principal) => principal,
+            Err(e) => {
+                error!(peer = %peer_addr, error = %e, "failed to verify credentials");
+                return Err(Error::AuthenticationFailed("failed to verify credentials".to_owned()));
+            }
+        };
+
+        match principal {
+            Some(principal) => {
+                let mut failed_attempts = self.failed_attempts.lock().await;
+                for key in keys {
+                    if let Some(attempts) = failed_attempts.get_mut(&key) {
+                        attempts.count = attempts.count.saturating_sub(1);
+                    }
+                }
+
+                Ok(principal)
+            }
+            None => {
+                match credentials {
+                    Credentials::Password { username, .. } => {
+                        warn!(peer = %peer_addr, %username, "failed authentication attempt with password")
+                    }
+                    Credentials::Token(_) => warn!(peer = %peer_addr, "failed authentication attempt with token"),
+                }
+
+                Err(Error::AuthenticationFailed("invalid credentials".to_owned()))
+            }
+        }
+    }
+}
+
+impl AuthSession {
+    /// connections authenticated by a client certificate need no `Auth` command
+    pub(crate) fn new(authenticator: Option<Arc<Authenticator>>, info: &ConnectionInfo) -> Self {
+        if let Some(principal) = info.principal() {
+            Span::current().record("principal", principal);
+        }
+
+        Self {
+            authenticator,
+            peer_addr: info.peer_addr().clone(),
+            principal: info.principal().map(str::to_owned),
+        }
+    }
+
+    pub(crate) fn principal(&self) -> Option<&str> {
+        self.principal.as_deref()
+    }
+
+    /// answers `Auth` commands and, while authentication is enabled and
+    /// connection isn't authenticated yet, every other command. returns the
+    /// command back if it is for the caller to handle.
+    pub(crate) async fn filter(&mut self, command: Command) -> Result<Command, Response> {
+        match (command, &self.authenticator) {
+            (Command::Auth(credentials), Some(authenticator)) => match authenticator.authenticate(credentials, &self.peer_addr).await {
+                Ok(principal) => {
+                    Span::current().record("principal", principal.as_str());
+                    info!(principal = principal.as_str(), "authenticated");
+                    Err(Response::Positive(self.principal.insert(principal).clone()))
+                }
+                Err(e) => Err(Response::from(e)),
+            },
+            (Command::Auth(_), None) => Err(Response::from(Error::NotSupported)),
+            (_, Some(_)) if self.principal.is_none() => Err(Response::from(Error::NotAuthenticated)),
+            (command, _) => Ok(command),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::test;
+
+    fn authenticator() -> Authenticator {
+        let mut credential_store = CredentialStore::default();
+        credential_store.add_user("alice".to_owned(), "correct horse", "c0ffee".to_owned(), 16);
+        credential_store.add_token("ci".to_owned(), "s3cr3t");
+
+        Authenticator::new(credential_store)
+    }
+
+    fn password(username: &str, password: &str) -> Credentials {
+        Credentials::Password {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        }
+    }
+
+    #[test]
+    async fn authenticator_test_01() {
+        let authenticator = authenticator();
+        let peer_addr = PeerAddr::Memory(0);
+
+        assert_eq!(authenticator.authenticate(password("alice", "correct horse"), &peer_addr).await.unwrap(), "alice");
+        assert_eq!(authenticator.authenticate(Credentials::Token("s3cr3t".to_owned()), &peer_addr).await.unwrap(), "ci");
+
+        for credentials in [password("alice", "wrong"), password("bob", "correct horse"), Credentials::Token("wrong".to_owned())] {
+            assert!(matches!(authenticator.authenticate(credentials, &peer_addr).await, Err(Error::AuthenticationFailed(_))));
+        }
+    }
+
+    #[test]
+    async fn authenticator_rate_limit_test_01() {
+        let authenticator = authenticator();
+
+        for i in 0..MAX_FAILED_ATTEMPTS as u64 {
+            let peer_addr = PeerAddr::Memory(i);
+            assert!(authenticator.authenticate(password("alice", "wrong"), &peer_addr).await.is_err());
+        }
+
+        // username is locked out even from a fresh peer and with right password
+        let result = authenticator.authenticate(password("alice", "correct horse"), &PeerAddr::Memory(42)).await;
+        assert!(matches!(result, Err(Error::AuthenticationFailed(reason)) if reason.contains("too many")));
+
+        // other users aren't affected
+        let result = authenticator.authenticate(Credentials::Token("s3cr3t".to_owned()), &PeerAddr::Memory(42)).await;
+        assert_eq!(result.unwrap(), "ci");
+    }
+
+    #[test]
+    async fn authenticator_rate_limit_test_02() {
+        let authenticator = Arc::new(authenticator());
+
+        // parallel attempts can't all be checked before any of them is counted
+        let attempts = (0..4 * MAX_FAILED_ATTEMPTS as u64).map(|i| {
+            let authenticator = authenticator.clone();
+            tokio::spawn(async move { authenticator.authenticate(password("alice", "wrong"), &PeerAddr::Memory(i)).await })
+        });
+
+        let mut verified = 0;
+        for attempt in attempts {
+            match attempt.await.unwrap() {
+                Err(Error::AuthenticationFailed(reason)) if reason.contains("too many") => (),
+                Err(Error::AuthenticationFailed(_)) => verified += 1,
+                result => panic!("unexpected result {result:?}"),
+            }
+        }
+        assert_eq!(verified, MAX_FAILED_ATTEMPTS);
+
+        // successful attempts don't count against the limit
+        let authenticator = self::authenticator();
+        for _ in 0..2 * MAX_FAILED_ATTEMPTS {
+            assert!(authenticator.authenticate(Credentials::Token("s3cr3t".to_owned()), &PeerAddr::Memory(0)).await.is_ok());
+        }
+    }
+
+    #[test]
+    async fn auth_session_test_01() {
+        let info = ConnectionInfo::new(PeerAddr::Memory(0), None);
+        let mut auth_session = AuthSession::new(Some(Arc::new(authenticator())), &info);
+
+        assert!(matches!(auth_session.filter(Command::ReadMessage).await, Err(Response::Negative(data)) if data.starts_with("NotAuthenticated")));
+        assert!(matches!(
+            auth_session.filter(Command::Auth(Credentials::Token("s3cr3t".to_owned()))).await,
+            Err(Response::Positive(data)) if data == "ci"
+        ));
+        assert!(matches!(auth_session.filter(Command::ReadMessage).await, Ok(Command::ReadMessage)));
+        assert_eq!(auth_session.principal(), Some("ci"));
+
+        // authentication disabled
+        let mut auth_session = AuthSession::new(None, &info);
+        assert!(matches!(auth_session.filter(Command::ReadMessage).await, Ok(Command::ReadMessage)));
+
+        // client certificate
+        let info = ConnectionInfo::new(PeerAddr::Memory(0), Some("CN=alice".to_owned()));
+        let mut auth_session = AuthSession::new(Some(Arc::new(authenticator())), &info);
+        assert!(matches!(auth_session.filter(Command::ReadMessage).await, Ok(Command::ReadMessage)));
+    }
+
+    #[test]
+    async fn credential_store_deserialize_test_01() {
+        let credential_store: CredentialStore = toml::from_str(&format!(
+            r#"
+            [users.alice]
+            salt = "c0ffee"
+            password_hash = "{}"
+            iterations = 16
+
+            [tokens.ci]
+            token_hash = "{}"
+            "#,
+            hash_password("correct horse", "c0ffee", 16),
+            hash_token("s3cr3t"),
+        ))
+        .unwrap();
+
+        assert_eq!(credential_store.verify(&password("alice", "correct horse")), Some("alice".to_owned()));
+        assert_eq!(credential_store.verify(&Credentials::Token("s3cr3t".to_owned())), Some("ci".to_owned()));
+        assert_eq!(credential_store.verify(&password("bob", "correct horse")), None);
+    }
+}
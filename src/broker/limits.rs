@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use super::PeerAddr;
+
+/// counts open connections of a listener, in total and per source ip
+#[derive(Debug)]
+pub(crate) struct ConnectionLimiter {
+    max_connections: Option<usize>,
+    max_connections_per_ip: Option<usize>,
+    open_connections: Mutex<OpenConnections>,
+}
+
+#[derive(Debug, Default)]
+struct OpenConnections {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+/// held by a connection while it is open, releases its slot on drop
+#[derive(Debug)]
+pub(crate) struct ConnectionPermit {
+    limiter: Arc<ConnectionLimiter>,
+    ip: Option<IpAddr>,
+}
+
+impl ConnectionLimiter {
+    pub(crate) fn new(max_connections: Option<usize>, max_connections_per_ip: Option<usize>) -> Self {
+        Self {
+            max_connections,
+            max_connections_per_ip,
+            open_connections: Mutex::new(OpenConnections::default()),
+        }
+    }
+
+    /// `None` if accepting a connection from `peer_addr` would exceed a limit,
+    /// only TCP peers are limited per ip
+    pub(crate) fn try_acquire(self: &Arc<Self>, peer_addr: &PeerAddr) -> Option<ConnectionPermit> {
+        let ip = match peer_addr {
+            PeerAddr::Tcp(addr) => Some(addr.ip()),
+            _ => None,
+        };
+
+        let mut open_connections = self.open_connections.lock().unwrap();
+        if self.max_connections.is_some_and(|max_connections| open_connections.total >= max_connections) {
+            return None;
+        }
+
+        if let Some(ip) = ip {
+            let per_ip = open_connections.per_ip.get(&ip).copied().unwrap_or(0);
+            if self.max_connections_per_ip.is_some_and(|max_connections| per_ip >= max_connections) {
+                return None;
+            }
+
+            open_connections.per_ip.insert(ip, per_ip + 1);
+        }
+
+        open_connections.total += 1;
+
+        Some(ConnectionPermit { limiter: self.clone(), ip })
+    }
+
+    #[cfg(test)]
+    fn open_connections(&self) -> usize {
+        self.open_connections.lock().unwrap().total
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut open_connections = self.limiter.open_connections.lock().unwrap();
+        open_connections.total -= 1;
+
+        if let Some(ip) = self.ip {
+            if let Some(per_ip) = open_connections.per_ip.get_mut(&ip) {
+                *per_ip -= 1;
+                if *per_ip == 0 {
+                    open_connections.per_ip.remove(&ip);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_limiter_test_01() {
+        let limiter = Arc::new(ConnectionLimiter::new(Some(3), Some(2)));
+        let alice = PeerAddr::Tcp("10.0.0.1:4000".parse().unwrap());
+        let bob = PeerAddr::Tcp("10.0.0.2:4000".parse().unwrap());
+
+        let first = limiter.try_acquire(&alice).unwrap();
+        let _second = limiter.try_acquire(&alice).unwrap();
+        assert!(limiter.try_acquire(&alice).is_none());
+
+        let _third = limiter.try_acquire(&bob).unwrap();
+        assert!(limiter.try_acquire(&bob).is_none());
+        assert!(limiter.try_acquire(&PeerAddr::Memory(0)).is_none());
+
+        drop(first);
+        assert_eq!(limiter.open_connections(), 2);
+        let _fourth = limiter.try_acquire(&alice).unwrap();
+
+        let limiter = Arc::new(ConnectionLimiter::new(None, Some(1)));
+        let _permits: Vec<_> = (0..8).map(|id| limiter.try_acquire(&PeerAddr::Memory(id)).unwrap()).collect();
+        assert!(limiter.try_acquire(&alice).is_some());
+    }
+
+    #[tokio::test]
+    async fn broker_max_connections_test_01() {
+        use crate::broker::{Broker, MemoryListener, SimpleAdminConnectionHandler};
+        use crate::topic::TopicRegistry;
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        let root_path = "./broker_max_connections_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let (mut listener, connector) = MemoryListener::new(1024);
+        let handler = Arc::new(SimpleAdminConnectionHandler::new(Arc::new(TopicRegistry::new(root_path))));
+
+        let mut broker = Broker::new();
+        broker.set_max_connections(Some(1));
+        let termination_signal_sender = broker.get_termination_signal_sender().await;
+        let broker = tokio::spawn(async move { broker.serve(&mut listener, handler).await });
+
+        let mut first = BufReader::new(connector.connect().await.unwrap());
+        first.write_all(b".\n").await.unwrap();
+        let mut response = String::new();
+        first.read_line(&mut response).await.unwrap();
+        assert_eq!(response, "+PONG\n");
+
+        // answered and closed right after being accepted
+        let mut second = connector.connect().await.unwrap();
+        let mut response = String::new();
+        second.read_to_string(&mut response).await.unwrap();
+        assert_eq!(response, "-TooManyConnections too many open connections, retry later\n");
+
+        // slot is released once handler notices first connection was closed
+        drop(first);
+        let ping = || async {
+            let mut client = BufReader::new(connector.connect().await?);
+            client.write_all(b".\n").await?;
+            let mut response = String::new();
+            client.read_line(&mut response).await?;
+            std::io::Result::Ok(response)
+        };
+        let mut response = ping().await.unwrap_or_default();
+        for _ in 0..100 {
+            if response == "+PONG\n" {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            response = ping().await.unwrap_or_default();
+        }
+        assert_eq!(response, "+PONG\n");
+
+        termination_signal_sender.send(()).unwrap();
+        broker.await.unwrap();
+    }
+}
@@ -1,24 +1,69 @@
 mod admin;
+mod auth;
+mod limits;
 mod publisher;
 mod subscriber;
+mod tls;
+mod transport;
 
+pub use self::admin::SimpleAdminConnectionHandler;
+pub use self::auth::{Authenticator, CredentialStore, DEFAULT_PBKDF2_ITERATIONS, MAX_FAILED_ATTEMPTS};
+pub use self::publisher::SimplePublisherConnectionHandler;
+pub use self::subscriber::SimpleSubscriberConnectionHandler;
+pub use self::transport::{ConnectionInfo, Listener, MemoryConnector, MemoryListener, PeerAddr, Transport};
 
 use std::error::Error;
-use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
-use tokio::net::{ToSocketAddrs, TcpStream, TcpListener};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{ToSocketAddrs, TcpListener};
 use tokio::sync::broadcast;
 use tokio::select;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::task::TaskTracker;
+use tracing::{debug, info, info_span, warn, Instrument};
 
+use crate::config::TlsConfig;
+use crate::metrics::Metrics;
+use crate::sesp::Response;
+use self::limits::ConnectionLimiter;
+
+/// max time a client may take to complete TLS handshake
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// pause before accepting again after an accept error that may go away on
+/// its own, e.g. running out of file descriptors
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// max time `Broker::serve` waits for open connections to finish after
+/// termination signal unless configured otherwise
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// negative response to a command that failed with `e`, failures caused by
+/// server rather than by client are logged as errors
+pub(crate) fn error_response(command: &'static str, e: crate::error::Error) -> Response {
+    match e {
+        crate::error::Error::Storage(_) => tracing::error!(command, error = %e, "command failed"),
+        _ => debug!(command, code = e.code(), error = %e, "command failed"),
+    }
+
+    Response::from(e)
+}
 
 #[async_trait]
-pub trait ConnectionHandler {
-    async fn handle_connection(stream: TcpStream, addr: SocketAddr, termination_signal_recvr: broadcast::Receiver<()>);
+pub trait ConnectionHandler: Send + Sync + 'static {
+    async fn handle_connection<S: Transport>(self: Arc<Self>, stream: S, info: ConnectionInfo, termination_signal_recvr: broadcast::Receiver<()>);
 }
 
 pub struct Broker {
     listner: Option<TcpListener>,
+    tls_acceptor: Option<TlsAcceptor>,
+    max_connections: Option<usize>,
+    max_connections_per_ip: Option<usize>,
+    shutdown_timeout: Duration,
+    metrics: Arc<Metrics>,
     termination_signal_sender: broadcast::Sender<()>,
     termination_signal_recvr: broadcast::Receiver<()>,
 }
@@ -28,42 +73,153 @@ impl Broker {
         let (tx, rx) = broadcast::channel(1);
         Self {
             listner: None,
+            tls_acceptor: None,
+            max_connections: None,
+            max_connections_per_ip: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            metrics: Arc::new(Metrics::new()),
             termination_signal_sender: tx,
             termination_signal_recvr: rx,
         }
     }
 
     pub async fn bind<A: ToSocketAddrs + Send>(&mut self, addr: A) -> Result<(), Box<dyn Error>> {
-        self.listner = Some(TcpListener::bind(addr).await?);
+        let listner = TcpListener::bind(addr).await?;
+        info!(addr = %listner.local_addr()?, "listening");
+        self.listner = Some(listner);
 
         Ok(())
     }
 
+    /// terminates TLS on every connection served from now on, peers
+    /// authenticated by a client certificate get its subject as principal
+    pub async fn enable_tls(&mut self, tls_config: &TlsConfig) -> Result<(), crate::error::Error> {
+        self.tls_acceptor = Some(tls::tls_acceptor(tls_config).await?);
+
+        Ok(())
+    }
+
+    /// connections accepted while `max_connections` connections of a listener
+    /// are open are closed right away, `None` means unlimited
+    pub fn set_max_connections(&mut self, max_connections: Option<usize>) {
+        self.max_connections = max_connections;
+    }
+
+    /// same as `set_max_connections` but counting only connections from the
+    /// same ip, applies to TCP connections only
+    pub fn set_max_connections_per_ip(&mut self, max_connections_per_ip: Option<usize>) {
+        self.max_connections_per_ip = max_connections_per_ip;
+    }
+
+    /// max time to wait for open connections to finish the command they are
+    /// handling after termination signal
+    pub fn set_shutdown_timeout(&mut self, shutdown_timeout: Duration) {
+        self.shutdown_timeout = shutdown_timeout;
+    }
+
+    /// records connection metrics into `metrics` instead of a private instance
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = metrics;
+    }
+
     /// panic! if called before calling `self.bind` on self
-    pub async fn run<T: ConnectionHandler>(&mut self) {
-        match self.listner {
-            Some(ref listner) => {
-                loop {
-                    let termination_future = self.termination_signal_recvr.recv();
-                    let connection_future = listner.accept();
-                    select! {
-                        _ = termination_future => {
-                            break;
+    pub async fn run<T: ConnectionHandler>(&mut self, handler: Arc<T>) {
+        match self.listner.take() {
+            Some(mut listner) => {
+                self.serve(&mut listner, handler).await;
+                self.listner = Some(listner);
+            },
+            None => panic!("called `self.run()` before calling self.bind on self"),
+        }
+    }
+
+    /// serves connections accepted by any `listener` (e.g. a `UnixListener`
+    /// or a `MemoryListener`) until termination signal is received or
+    /// listener fails for good (e.g. every `MemoryConnector` is dropped).
+    ///
+    /// on termination signal, stops accepting and waits (upto shutdown
+    /// timeout) for every connection to answer the command it is handling
+    /// and to be closed by its handler. open connections are told to
+    /// terminate the same way when listener fails.
+    pub async fn serve<L: Listener, T: ConnectionHandler>(&mut self, listener: &mut L, handler: Arc<T>) {
+        let limiter = Arc::new(ConnectionLimiter::new(self.max_connections, self.max_connections_per_ip));
+        let tracker = TaskTracker::new();
+
+        loop {
+            let termination_future = self.termination_signal_recvr.recv();
+            let connection_future = listener.accept();
+            select! {
+                _ = termination_future => {
+                    break;
+                }
+                connection = connection_future => {
+                    let (connection, addr) = match connection {
+                        Ok(connection) => connection,
+                        Err(e) => match AcceptError::from(&e) {
+                            AcceptError::Connection => {
+                                debug!(error = %e, "failed to accept connection");
+                                continue;
+                            }
+                            AcceptError::Transient => {
+                                warn!(error = %e, "failed to accept connection, backing off");
+                                select! {
+                                    _ = self.termination_signal_recvr.recv() => break,
+                                    _ = tokio::time::sleep(ACCEPT_BACKOFF) => continue,
+                                }
+                            }
+                            AcceptError::Fatal => {
+                                tracing::error!(error = %e, "listener failed, no longer accepting connections");
+                                let _ = self.termination_signal_sender.send(()); // ignore result
+                                break;
+                            }
+                        },
+                    };
+
+                    let permit = match limiter.try_acquire(&addr) {
+                        Some(permit) => permit,
+                        None => {
+                            self.metrics.record_rejected_connection();
+                            warn!(peer = %addr, "rejected connection, too many open connections");
+                            tracker.spawn(reject_connection(connection, self.tls_acceptor.clone()));
+                            continue;
                         }
-                        connection = connection_future => {
-                            if let Ok((connection, addr)) = connection {
-                                let termination_signal_recvr = self.termination_signal_sender.subscribe();
+                    };
+
+                    let span = info_span!("connection", peer = %addr);
+
+                    let termination_signal_recvr = self.termination_signal_sender.subscribe();
+                    let handler = handler.clone();
+                    let tls_acceptor = self.tls_acceptor.clone();
+                    let open_connection = self.metrics.open_connection();
 
-                                tokio::spawn (async move {
-                                    T::handle_connection(connection, addr, termination_signal_recvr).await
-                                });
+                    tracker.spawn(async move {
+                        let _permit = permit; // released once connection is closed
+                        let _open_connection = open_connection;
+                        debug!("accepted connection");
+                        match tls_acceptor {
+                            // handshake happens here so that a slow client can't stall accepting others
+                            Some(tls_acceptor) => {
+                                let connection = match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, tls_acceptor.accept(connection)).await {
+                                    Ok(Ok(connection)) => connection,
+                                    Ok(Err(e)) => return debug!(error = %e, "TLS handshake failed"),
+                                    Err(_) => return debug!("TLS handshake timed out"),
+                                };
+
+                                let principal = tls::peer_principal(connection.get_ref().1);
+                                handler.handle_connection(connection, ConnectionInfo::new(addr, principal), termination_signal_recvr).await
                             }
+                            None => handler.handle_connection(connection, ConnectionInfo::new(addr, None), termination_signal_recvr).await,
                         }
-                    }
+                        debug!("closed connection");
+                    }.instrument(span));
                 }
-                
-            },
-            None => panic!("called `self.run()` before calling self.bind on self"),
+            }
+        }
+
+        tracker.close();
+        info!(open_connections = tracker.len(), "shutting down");
+        if tokio::time::timeout(self.shutdown_timeout, tracker.wait()).await.is_err() {
+            warn!(open_connections = tracker.len(), "connections still open after shutdown timeout");
         }
     }
 
@@ -72,3 +228,141 @@ impl Broker {
     }
 }
 
+/// answers `-TooManyConnections` before closing `connection`, never takes
+/// longer than a TLS handshake may take
+async fn reject_connection<S: Transport>(connection: S, tls_acceptor: Option<TlsAcceptor>) {
+    async fn write_response<S: Transport>(mut connection: S) {
+        let response = Response::from(crate::error::Error::TooManyConnections).as_vec_of_u8();
+        let _ = connection.write_all(&response).await; // ignore result
+        let _ = connection.shutdown().await; // ignore result
+    }
+
+    let reject = async move {
+        match tls_acceptor {
+            Some(tls_acceptor) => {
+                if let Ok(connection) = tls_acceptor.accept(connection).await {
+                    write_response(connection).await;
+                }
+            }
+            None => write_response(connection).await,
+        }
+    };
+
+    let _ = tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, reject).await; // ignore result
+}
+
+/// how `Broker::serve` reacts to an accept error
+enum AcceptError {
+    /// only the connection being accepted is affected
+    Connection,
+    /// listener may recover, e.g. once some connections are closed
+    Transient,
+    /// listener will never accept again
+    Fatal,
+}
+
+impl From<&std::io::Error> for AcceptError {
+    fn from(e: &std::io::Error) -> Self {
+        use std::io::ErrorKind::*;
+
+        match e.kind() {
+            ConnectionAborted | ConnectionReset | ConnectionRefused | Interrupted | WouldBlock | TimedOut => Self::Connection,
+            NotConnected | InvalidInput | Unsupported | PermissionDenied => Self::Fatal,
+            _ => Self::Transient, // e.g. EMFILE, ENFILE, ENOBUFS
+        }
+    }
+}
+
+impl Default for Broker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topic::TopicRegistry;
+    use crate::types::{TopicMetaData, TopicName};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    #[tokio::test]
+    async fn broker_shutdown_test_01() {
+        let root_path = "./broker_shutdown_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = Arc::new(TopicRegistry::new(root_path));
+        let topic_name: TopicName = "foo".parse().unwrap();
+        registry.create_topic(TopicMetaData::new_with_few_defaults(registry.topic(topic_name.clone()))).await.unwrap();
+
+        let (mut listener, connector) = MemoryListener::new(1024);
+        let handler = Arc::new(SimplePublisherConnectionHandler::new(registry.clone()));
+        let mut broker = Broker::new();
+        let termination_signal_sender = broker.get_termination_signal_sender().await;
+        let broker = tokio::spawn(async move { broker.serve(&mut listener, handler).await });
+
+        let mut client = BufReader::new(connector.connect().await.unwrap());
+        client.write_all(b"@foo\n>hello\n").await.unwrap();
+        let mut responses = String::new();
+        client.read_line(&mut responses).await.unwrap();
+        client.read_line(&mut responses).await.unwrap();
+        assert_eq!(responses, "+\n+0\n");
+
+        // broker returns only once every connection has been told and closed
+        termination_signal_sender.send(()).unwrap();
+        broker.await.unwrap();
+
+        let mut responses = String::new();
+        client.read_to_string(&mut responses).await.unwrap();
+        assert_eq!(responses, "-ShuttingDown server is shutting down\n");
+
+        registry.flush().await.unwrap();
+        assert_eq!(registry.metadata(&topic_name).await.unwrap().last_flushed_offset(), &Some(0));
+    }
+
+    #[tokio::test]
+    async fn broker_shutdown_timeout_test_01() {
+        /// never returns from handling a connection
+        struct StuckHandler;
+
+        #[async_trait]
+        impl ConnectionHandler for StuckHandler {
+            async fn handle_connection<S: Transport>(self: Arc<Self>, _stream: S, _info: ConnectionInfo, _termination_signal_recvr: broadcast::Receiver<()>) {
+                std::future::pending::<()>().await;
+            }
+        }
+
+        let (mut listener, connector) = MemoryListener::new(1024);
+        let mut broker = Broker::new();
+        broker.set_shutdown_timeout(Duration::from_millis(50));
+        let termination_signal_sender = broker.get_termination_signal_sender().await;
+        let broker = tokio::spawn(async move { broker.serve(&mut listener, Arc::new(StuckHandler)).await });
+
+        let _client = connector.connect().await.unwrap();
+        termination_signal_sender.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), broker).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn broker_accept_error_test_01() {
+        let root_path = "./broker_accept_error_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = Arc::new(TopicRegistry::new(root_path));
+        let (mut listener, connector) = MemoryListener::new(1024);
+        let handler = Arc::new(SimplePublisherConnectionHandler::new(registry));
+        let mut broker = Broker::new();
+        let broker = tokio::spawn(async move { broker.serve(&mut listener, handler).await });
+
+        let mut client = connector.connect().await.unwrap();
+        client.write_all(b"@foo\n").await.unwrap();
+
+        // listener can never accept again once every connector is dropped, open connections are terminated
+        drop(connector);
+        tokio::time::timeout(Duration::from_secs(5), broker).await.unwrap().unwrap();
+
+        let mut responses = String::new();
+        client.read_to_string(&mut responses).await.unwrap();
+        assert!(responses.ends_with("-ShuttingDown server is shutting down\n"), "{responses}");
+    }
+}
@@ -1,20 +1,376 @@
-use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
-use tokio::net::TcpStream;
-use tokio::sync::broadcast;
+use tokio::select;
+use tokio::sync::{broadcast, Mutex};
+use tracing::{debug, instrument, Span};
 
+use super::auth::{AuthSession, Authenticator};
+use super::{error_response, ConnectionHandler, ConnectionInfo, Transport};
+use crate::acl::{AclOperation, AclStore};
+use crate::error::Error;
+use crate::metrics::Metrics;
+use crate::quota::QuotaManager;
+use crate::sesp::{Command, Connection, Response, DEFAULT_MAX_FRAME_SIZE};
+use crate::topic::{SimpleDiskTopicWriter, TopicRegistry, TopicWriter, Transaction};
+use crate::types::{Message, TopicName};
 
-use super::ConnectionHandler;
+pub struct SimplePublisherConnectionHandler {
+    registry: Arc<TopicRegistry>,
+    max_frame_size: usize,
+    idle_timeout: Option<Duration>,
+    authenticator: Option<Arc<Authenticator>>,
+    metrics: Arc<Metrics>,
+    acl_store: Option<Arc<AclStore>>,
+    quota_manager: Option<Arc<QuotaManager>>,
+}
+
+/// per connection state of publisher
+#[derive(Default)]
+struct PublisherSession {
+    topic_name: Option<TopicName>,
+    writer: Option<Arc<Mutex<SimpleDiskTopicWriter>>>,
+    transaction: Option<Transaction>,
+    /// how long response to last command must be delayed by
+    throttle_time: Option<Duration>,
+}
+
+impl SimplePublisherConnectionHandler {
+    pub fn new(registry: Arc<TopicRegistry>) -> Self {
+        Self {
+            registry,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            idle_timeout: None,
+            authenticator: None,
+            metrics: Arc::new(Metrics::new()),
+            acl_store: None,
+            quota_manager: None,
+        }
+    }
+
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// records into `metrics` instead of a private instance
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// closes connections that send no command, not even a `Ping`, for `idle_timeout`
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// requires every connection to authenticate before any other command
+    pub fn with_authenticator(mut self, authenticator: Arc<Authenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    /// denies publishing to topics not allowed by `acl_store`
+    pub fn with_acl_store(mut self, acl_store: Arc<AclStore>) -> Self {
+        self.acl_store = Some(acl_store);
+        self
+    }
+
+    /// delays responses to publishers exceeding their quota or quota of the topic
+    pub fn with_quota_manager(mut self, quota_manager: Arc<QuotaManager>) -> Self {
+        self.quota_manager = Some(quota_manager);
+        self
+    }
+
+    async fn authorize(&self, principal: Option<&str>, topic_name: &TopicName) -> Result<(), Error> {
+        match self.acl_store {
+            Some(ref acl_store) => acl_store.authorize(principal, AclOperation::Publish, topic_name).await,
+            None => Ok(()),
+        }
+    }
 
-pub struct SimplePublisherConnectionHandler;
+    /// topic, number of messages and number of bytes `command` would publish
+    fn published(session: &PublisherSession, command: &Command) -> Option<(TopicName, u64, u64)> {
+        let (topic_name, msgs, bytes) = match command {
+            Command::PublishMessage(msg) | Command::PublishSequenced(_, _, msg) => {
+                (session.topic_name.clone()?, 1, msg.value().len())
+            }
+            Command::PublishBatch(msgs) => {
+                (session.topic_name.clone()?, msgs.len(), msgs.iter().map(|msg| msg.value().len()).sum())
+            }
+            Command::TransactionalPublish(topic_name, msg) => (topic_name.clone(), 1, msg.value().len()),
+            _ => return None,
+        };
+
+        Some((topic_name, msgs as u64, bytes as u64))
+    }
+
+    async fn throttle(&self, session: &mut PublisherSession, principal: Option<&str>, topic_name: &TopicName, msgs: u64, bytes: u64) {
+        if let Some(ref quota_manager) = self.quota_manager {
+            let throttle_time = quota_manager.record_publish(principal, topic_name, msgs, bytes).await;
+            session.throttle_time = Some(throttle_time).filter(|throttle_time| !throttle_time.is_zero());
+        }
+    }
+
+    async fn flush(&self, writer: &mut SimpleDiskTopicWriter) -> Result<(), Error> {
+        let start = Instant::now();
+        writer.flush_topic_metadata().await?;
+        self.metrics.record_flush(start.elapsed());
+
+        Ok(())
+    }
+
+    fn writer(session: &PublisherSession) -> Result<&Arc<Mutex<SimpleDiskTopicWriter>>, Error> {
+        match session.writer {
+            Some(ref writer) => Ok(writer),
+            None => Err(Error::Protocol("no topic selected".to_owned())),
+        }
+    }
+
+    async fn publish(&self, session: &PublisherSession, msg: Message) -> Result<Response, Error> {
+        let mut writer = Self::writer(session)?.lock().await;
+        let offset = writer.write(msg).await?;
+        self.flush(&mut writer).await?;
+
+        Ok(Response::Positive(offset.to_string()))
+    }
+
+    async fn publish_batch(&self, session: &PublisherSession, msgs: Vec<Message>) -> Result<Response, Error> {
+        let mut writer = Self::writer(session)?.lock().await;
+        let base_offset = writer.write_batch(msgs).await?;
+        self.flush(&mut writer).await?;
+
+        Ok(Response::Positive(base_offset.to_string()))
+    }
+
+    async fn publish_sequenced(&self, session: &PublisherSession, producer_id: u64, sequence: u64, msg: Message) -> Result<Response, Error> {
+        let mut writer = Self::writer(session)?.lock().await;
+        let offset = writer.write_sequenced(producer_id, sequence, msg).await?;
+        self.flush(&mut writer).await?;
+
+        Ok(Response::Positive(offset.to_string()))
+    }
+
+    fn transaction(session: &mut PublisherSession) -> Result<&mut Transaction, Error> {
+        match session.transaction {
+            Some(ref mut transaction) => Ok(transaction),
+            None => Err(Error::Protocol("no transaction in progress".to_owned())),
+        }
+    }
+
+    /// buffers `msg` in ongoing transaction, `topic_name` defaults to selected topic
+    fn publish_transactional(session: &mut PublisherSession, topic_name: Option<TopicName>, msg: Message) -> Result<Response, Error> {
+        let topic_name = match topic_name.or_else(|| session.topic_name.clone()) {
+            Some(topic_name) => topic_name,
+            None => return Err(Error::Protocol("no topic selected".to_owned())),
+        };
+
+        Self::transaction(session)?.add(topic_name, msg)?;
+
+        Ok(Response::Positive(String::new()))
+    }
+
+    async fn commit_transaction(&self, session: &mut PublisherSession) -> Result<Response, Error> {
+        let transaction = match session.transaction.take() {
+            Some(transaction) => transaction,
+            None => return Err(Error::Protocol("no transaction in progress".to_owned())),
+        };
+
+        self.registry.commit_transaction(transaction).await?;
+
+        Ok(Response::Positive(String::new()))
+    }
+
+    async fn handle_command(&self, session: &mut PublisherSession, principal: Option<&str>, command: Command) -> Result<Response, Error> {
+        // rules may have been revoked since topic was selected
+        let published = Self::published(session, &command);
+        if let Some((ref topic_name, ..)) = published {
+            self.authorize(principal, topic_name).await?;
+        }
+
+        let response = match command {
+            Command::SelectTopic(_) if session.writer.is_some() => {
+                Err(Error::Protocol("topic can be selected only once per connection".to_owned()))
+            }
+            Command::SelectTopic(topic_name) => {
+                self.authorize(principal, &topic_name).await?;
+                session.writer = Some(self.registry.writer(&topic_name).await?);
+                Span::current().record("topic", topic_name.as_str());
+                session.topic_name = Some(topic_name);
+                Ok(Response::Positive(String::new()))
+            }
+            Command::BeginTransaction if session.transaction.is_some() => {
+                Err(Error::Protocol("transaction already in progress".to_owned()))
+            }
+            Command::BeginTransaction => {
+                let transaction = Transaction::begin();
+                let txn_id = transaction.txn_id();
+                session.transaction = Some(transaction);
+                Ok(Response::Positive(txn_id.to_string()))
+            }
+            Command::CommitTransaction => self.commit_transaction(session).await,
+            Command::AbortTransaction => {
+                Self::transaction(session)?;
+                session.transaction = None;
+                Ok(Response::Positive(String::new()))
+            }
+            Command::TransactionalPublish(topic_name, msg) => Self::publish_transactional(session, Some(topic_name), msg),
+            Command::PublishMessage(msg) if session.transaction.is_some() => Self::publish_transactional(session, None, msg),
+            Command::PublishBatch(_) | Command::PublishSequenced(..) if session.transaction.is_some() => {
+                Err(Error::Protocol("command not allowed inside a transaction".to_owned()))
+            }
+            Command::PublishMessage(msg) => self.publish(session, msg).await,
+            Command::PublishBatch(msgs) => self.publish_batch(session, msgs).await,
+            Command::PublishSequenced(producer_id, sequence, msg) => {
+                self.publish_sequenced(session, producer_id, sequence, msg).await
+            }
+            Command::InvalidTopicName(e) => Err(Error::InvalidTopicName(e)),
+            Command::InvalidCommand => Err(Error::Protocol("invalid command".to_owned())),
+            _ => Err(Error::Protocol("not a publisher command".to_owned())),
+        }?;
+
+        // delay response of successful publishes only, writer locks are released by now
+        if let Some((topic_name, msgs, bytes)) = published {
+            self.metrics.record_publish(&topic_name, msgs, bytes);
+            self.throttle(session, principal, &topic_name, msgs, bytes).await;
+        }
+
+        Ok(response)
+    }
+}
 
 
 #[async_trait]
 impl ConnectionHandler for SimplePublisherConnectionHandler {
-    async fn handle_connection(stream: TcpStream, addr: SocketAddr, termination_signal_recvr: broadcast::Receiver<()>) {
-        
+    #[instrument(name = "publisher", skip_all, fields(principal, topic))]
+    async fn handle_connection<S: Transport>(self: Arc<Self>, stream: S, info: ConnectionInfo, mut termination_signal_recvr: broadcast::Receiver<()>) {
+        let mut connection = Connection::with_max_frame_size(stream, self.max_frame_size);
+        connection.set_idle_timeout(self.idle_timeout);
+        connection.set_metrics(self.metrics.clone());
+        let mut auth_session = AuthSession::new(self.authenticator.clone(), &info);
+        let mut session = PublisherSession::default();
+
+        loop {
+            let command = select! {
+                // command being handled, if any, has been answered by now
+                _ = termination_signal_recvr.recv() => return connection.shutdown().await,
+                command = connection.read_command() => command,
+            };
+
+            let response = match command {
+                Ok(Some(command)) => {
+                    let name = command.name();
+                    self.metrics.record_command(name);
+                    match auth_session.filter(command).await {
+                        Ok(command) => self.handle_command(&mut session, auth_session.principal(), command).await.unwrap_or_else(|e| error_response(name, e)),
+                        Err(response) => response,
+                    }
+                }
+                Ok(None) => {
+                    debug!("peer closed connection");
+                    break;
+                }
+                Err(e) => {
+                    debug!(error = %e, "closing connection");
+                    break;
+                }
+            };
+
+            self.metrics.record_response(&response);
+
+            // throttled response is delayed, but never past termination signal
+            if let Some(throttle_time) = session.throttle_time.take() {
+                select! {
+                    _ = termination_signal_recvr.recv() => {
+                        let _ = connection.write_response(response).await; // ignore result
+                        return connection.shutdown().await;
+                    }
+                    _ = tokio::time::sleep(throttle_time) => (),
+                }
+            }
 
+            if connection.write_response(response).await.is_err() {
+                break;
+            }
+        }
+
+        connection.close().await;
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::{Broker, MemoryListener};
+    use crate::quota::{Quota, QuotaConfig, MAX_THROTTLE_TIME};
+    use crate::types::TopicMetaData;
+    use std::collections::HashMap;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    #[tokio::test]
+    async fn publisher_throttle_test_01() {
+        let root_path = "./publisher_throttle_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = Arc::new(TopicRegistry::new(root_path));
+        let topic_name: TopicName = "foo".parse().unwrap();
+        registry.create_topic(TopicMetaData::new_with_few_defaults(registry.topic(topic_name))).await.unwrap();
+
+        let quota_config = QuotaConfig::new(Some(Quota::new(None, Some(10), None)), HashMap::new(), HashMap::new());
+        let handler = SimplePublisherConnectionHandler::new(registry).with_quota_manager(Arc::new(QuotaManager::new(quota_config)));
+
+        let (mut listener, connector) = MemoryListener::new(1024);
+        let mut broker = Broker::new();
+        let termination_signal_sender = broker.get_termination_signal_sender().await;
+        let broker = tokio::spawn(async move { broker.serve(&mut listener, Arc::new(handler)).await });
+
+        let mut client = BufReader::new(connector.connect().await.unwrap());
+        let mut responses = String::new();
+        client.write_all(b"@foo\n").await.unwrap();
+        client.read_line(&mut responses).await.unwrap();
+
+        // 15 messages at 10 messages/sec leave the bucket 5 messages in debt
+        let start = Instant::now();
+        client.write_all(format!("*15\n{}", "hello\n".repeat(15)).as_bytes()).await.unwrap();
+        client.read_line(&mut responses).await.unwrap();
+        assert_eq!(responses, "+\n+0\n");
+        assert!(start.elapsed() >= Duration::from_millis(400));
+
+        termination_signal_sender.send(()).unwrap();
+        broker.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn publisher_throttle_test_02() {
+        let root_path = "./publisher_throttle_test_02";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = Arc::new(TopicRegistry::new(root_path));
+        let topic_name: TopicName = "foo".parse().unwrap();
+        registry.create_topic(TopicMetaData::new_with_few_defaults(registry.topic(topic_name))).await.unwrap();
+
+        let quota_config = QuotaConfig::new(Some(Quota::new(None, Some(1), None)), HashMap::new(), HashMap::new());
+        let handler = SimplePublisherConnectionHandler::new(registry).with_quota_manager(Arc::new(QuotaManager::new(quota_config)));
+
+        let (mut listener, connector) = MemoryListener::new(1024);
+        let mut broker = Broker::new();
+        let termination_signal_sender = broker.get_termination_signal_sender().await;
+        let broker = tokio::spawn(async move { broker.serve(&mut listener, Arc::new(handler)).await });
+
+        // response is throttled for MAX_THROTTLE_TIME, shutdown doesn't wait for it
+        let mut client = connector.connect().await.unwrap();
+        client.write_all(format!("@foo\n*20\n{}", "hello\n".repeat(20)).as_bytes()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let start = Instant::now();
+        termination_signal_sender.send(()).unwrap();
+        broker.await.unwrap();
+        assert!(start.elapsed() < MAX_THROTTLE_TIME / 2);
+
+        let mut responses = String::new();
+        client.read_to_string(&mut responses).await.unwrap();
+        assert_eq!(responses, "+\n+0\n-ShuttingDown server is shutting down\n");
+    }
+}
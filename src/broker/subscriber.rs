@@ -1,18 +1,283 @@
-use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
 
 use async_trait::async_trait;
-use tokio::net::TcpStream;
+use tokio::select;
 use tokio::sync::broadcast;
+use tracing::{debug, instrument, Span};
 
+use super::auth::{AuthSession, Authenticator};
+use super::{error_response, ConnectionHandler, ConnectionInfo, Transport};
+use crate::acl::{AclOperation, AclStore};
+use crate::error::Error;
+use crate::metrics::{Metrics, ReaderLag};
+use crate::quota::QuotaManager;
+use crate::sesp::{Command, Connection, Response, DEFAULT_MAX_FRAME_SIZE, MAX_BATCH_LEN};
+use crate::topic::{IsolationLevel, SimpleDiskTopicReader, TopicReader, TopicRegistry};
+use crate::types::TopicName;
 
-use super::ConnectionHandler;
+pub struct SimpleSubscriberConnectionHandler {
+    registry: Arc<TopicRegistry>,
+    max_frame_size: usize,
+    idle_timeout: Option<Duration>,
+    authenticator: Option<Arc<Authenticator>>,
+    metrics: Arc<Metrics>,
+    acl_store: Option<Arc<AclStore>>,
+    quota_manager: Option<Arc<QuotaManager>>,
+}
+
+/// per connection state of subscriber
+#[derive(Default)]
+struct SubscriberSession {
+    topic_name: Option<TopicName>,
+    reader: Option<SimpleDiskTopicReader>,
+    read_offset: usize,
+    isolation_level: IsolationLevel,
+    reader_lag: Option<ReaderLag>,
+    /// how long response to last command must be delayed by
+    throttle_time: Option<Duration>,
+}
+
+impl SimpleSubscriberConnectionHandler {
+    pub fn new(registry: Arc<TopicRegistry>) -> Self {
+        Self {
+            registry,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            idle_timeout: None,
+            authenticator: None,
+            metrics: Arc::new(Metrics::new()),
+            acl_store: None,
+            quota_manager: None,
+        }
+    }
+
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// records into `metrics` instead of a private instance
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// closes connections that send no command, not even a `Ping`, for `idle_timeout`
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// requires every connection to authenticate before any other command
+    pub fn with_authenticator(mut self, authenticator: Arc<Authenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    /// denies subscribing to topics not allowed by `acl_store`
+    pub fn with_acl_store(mut self, acl_store: Arc<AclStore>) -> Self {
+        self.acl_store = Some(acl_store);
+        self
+    }
+
+    /// delays responses to subscribers exceeding their fetch quota or fetch quota of the topic
+    pub fn with_quota_manager(mut self, quota_manager: Arc<QuotaManager>) -> Self {
+        self.quota_manager = Some(quota_manager);
+        self
+    }
+
+    async fn authorize(&self, principal: Option<&str>, topic_name: &TopicName) -> Result<(), Error> {
+        match self.acl_store {
+            Some(ref acl_store) => acl_store.authorize(principal, AclOperation::Subscribe, topic_name).await,
+            None => Ok(()),
+        }
+    }
+
+    /// number of messages and message bytes carried by `response`
+    fn fetched(response: &Response) -> (u64, u64) {
+        let (msgs, bytes) = match response {
+            Response::Positive(msg) => (1, msg.len()),
+            Response::Messages(msgs) => (msgs.len(), msgs.iter().map(|msg| msg.value().len()).sum()),
+            Response::Negative(_) => (0, 0),
+        };
+
+        (msgs as u64, bytes as u64)
+    }
+
+    /// number of messages flushed to selected topic past read offset
+    fn lag(session: &SubscriberSession) -> u64 {
+        let end_offset = session
+            .reader
+            .as_ref()
+            .and_then(|reader| reader.last_flushed_offset())
+            .map_or(0, |offset| offset + 1);
+
+        end_offset.saturating_sub(session.read_offset) as u64
+    }
 
-pub struct SimpleSubscriberConnectionHandler;
+    async fn throttle(&self, session: &mut SubscriberSession, principal: Option<&str>, bytes: u64) {
+        if let (Some(quota_manager), Some(topic_name)) = (self.quota_manager.as_ref(), session.topic_name.as_ref()) {
+            let throttle_time = quota_manager.record_fetch(principal, topic_name, bytes).await;
+            session.throttle_time = Some(throttle_time).filter(|throttle_time| !throttle_time.is_zero());
+        }
+    }
+
+    async fn read_message(&self, session: &mut SubscriberSession) -> Result<Response, Error> {
+        let reader = match session.reader {
+            Some(ref mut reader) => reader,
+            None => return Err(Error::Protocol("no topic selected".to_owned())),
+        };
+
+        match reader.read(session.read_offset).await? {
+            Some(msg) => {
+                // offsets of control records and hidden messages are skipped over
+                session.read_offset = msg.offset().map_or(session.read_offset, |offset| *offset) + 1;
+                Ok(Response::Positive(String::from_utf8_lossy(msg.value()).into_owned()))
+            }
+            None => Ok(Response::Negative("None".to_owned())),
+        }
+    }
+
+    async fn fetch(&self, session: &mut SubscriberSession, offset: usize, max_messages: usize, max_bytes: usize) -> Result<Response, Error> {
+        let reader = match session.reader {
+            Some(ref mut reader) => reader,
+            None => return Err(Error::Protocol("no topic selected".to_owned())),
+        };
+
+        if max_messages == 0 || max_bytes == 0 {
+            return Err(Error::InvalidArgument("max_messages and max_bytes must be positive".to_owned()));
+        }
+
+        let msgs = reader.read_range(offset, max_messages.min(MAX_BATCH_LEN), max_bytes).await?;
+        if let Some(offset) = msgs.last().and_then(|msg| msg.offset()) {
+            session.read_offset = offset + 1;
+        }
+
+        Ok(Response::Messages(msgs))
+    }
+
+    async fn offset_for_timestamp(&self, session: &mut SubscriberSession, timestamp_ms: u64) -> Result<Response, Error> {
+        let reader = match session.reader {
+            Some(ref mut reader) => reader,
+            None => return Err(Error::Protocol("no topic selected".to_owned())),
+        };
+
+        let offset = reader.offset_for_timestamp(UNIX_EPOCH + Duration::from_millis(timestamp_ms)).await?;
+        Ok(Response::Positive(offset.to_string()))
+    }
+
+    async fn handle_command(&self, session: &mut SubscriberSession, principal: Option<&str>, command: Command) -> Result<Response, Error> {
+        let is_fetch = matches!(command, Command::ReadMessage | Command::Fetch(..));
+        // rules may have been revoked since topic was selected
+        if is_fetch || matches!(command, Command::OffsetForTimestamp(_)) {
+            if let Some(ref topic_name) = session.topic_name {
+                self.authorize(principal, topic_name).await?;
+            }
+        }
+
+        let response = match command {
+            Command::SelectTopic(_) if session.reader.is_some() => {
+                Err(Error::Protocol("topic can be selected only once per connection".to_owned()))
+            }
+            Command::SelectTopic(topic_name) => {
+                self.authorize(principal, &topic_name).await?;
+                let mut reader = self.registry.reader(&topic_name).await?;
+                reader.set_isolation_level(session.isolation_level);
+                session.reader = Some(reader);
+                session.reader_lag = Some(self.metrics.register_reader(&topic_name));
+                Span::current().record("topic", topic_name.as_str());
+                session.topic_name = Some(topic_name);
+                Ok(Response::Positive(String::new()))
+            }
+            Command::SetIsolationLevel(isolation_level) => {
+                session.isolation_level = isolation_level;
+                if let Some(ref mut reader) = session.reader {
+                    reader.set_isolation_level(isolation_level);
+                }
+                Ok(Response::Positive(String::new()))
+            }
+            Command::SetReadOffset(offset) => {
+                session.read_offset = offset;
+                Ok(Response::Positive(String::new()))
+            }
+            Command::ReadMessage => self.read_message(session).await,
+            Command::Fetch(offset, max_messages, max_bytes) => self.fetch(session, offset, max_messages, max_bytes).await,
+            Command::OffsetForTimestamp(timestamp_ms) => self.offset_for_timestamp(session, timestamp_ms).await,
+            Command::InvalidTopicName(e) => Err(Error::InvalidTopicName(e)),
+            Command::InvalidCommand => Err(Error::Protocol("invalid command".to_owned())),
+            _ => Err(Error::Protocol("not a subscriber command".to_owned())),
+        }?;
+
+        if is_fetch {
+            if let Some(ref topic_name) = session.topic_name {
+                let (msgs, bytes) = Self::fetched(&response);
+                self.metrics.record_read(topic_name, msgs, bytes);
+                if let Some(ref reader_lag) = session.reader_lag {
+                    reader_lag.set(Self::lag(session));
+                }
+                self.throttle(session, principal, bytes).await;
+            }
+        }
+
+        Ok(response)
+    }
+}
 
 
 #[async_trait]
 impl ConnectionHandler for SimpleSubscriberConnectionHandler {
-    async fn handle_connection(stream: TcpStream, addr: SocketAddr, termination_signal_recvr: broadcast::Receiver<()>) {
+    #[instrument(name = "subscriber", skip_all, fields(principal, topic))]
+    async fn handle_connection<S: Transport>(self: Arc<Self>, stream: S, info: ConnectionInfo, mut termination_signal_recvr: broadcast::Receiver<()>) {
+        let mut connection = Connection::with_max_frame_size(stream, self.max_frame_size);
+        connection.set_idle_timeout(self.idle_timeout);
+        connection.set_metrics(self.metrics.clone());
+        let mut auth_session = AuthSession::new(self.authenticator.clone(), &info);
+        let mut session = SubscriberSession::default();
+
+        loop {
+            let command = select! {
+                // command being handled, if any, has been answered by now
+                _ = termination_signal_recvr.recv() => return connection.shutdown().await,
+                command = connection.read_command() => command,
+            };
+
+            let response = match command {
+                Ok(Some(command)) => {
+                    let name = command.name();
+                    self.metrics.record_command(name);
+                    match auth_session.filter(command).await {
+                        Ok(command) => self.handle_command(&mut session, auth_session.principal(), command).await.unwrap_or_else(|e| error_response(name, e)),
+                        Err(response) => response,
+                    }
+                }
+                Ok(None) => {
+                    debug!("peer closed connection");
+                    break;
+                }
+                Err(e) => {
+                    debug!(error = %e, "closing connection");
+                    break;
+                }
+            };
+
+            self.metrics.record_response(&response);
+
+            // throttled response is delayed, but never past termination signal
+            if let Some(throttle_time) = session.throttle_time.take() {
+                select! {
+                    _ = termination_signal_recvr.recv() => {
+                        let _ = connection.write_response(response).await; // ignore result
+                        return connection.shutdown().await;
+                    }
+                    _ = tokio::time::sleep(throttle_time) => (),
+                }
+            }
+
+            if connection.write_response(response).await.is_err() {
+                break;
+            }
+        }
+
+        connection.close().await;
     }
 }
-
@@ -0,0 +1,209 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConnection};
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::TlsConfig;
+use crate::error::Error;
+
+fn invalid_config<E: std::fmt::Display>(e: E) -> Error {
+    Error::Config(format!("TLS: {e}"))
+}
+
+async fn read_pem(path: &Path) -> Result<Vec<u8>, Error> {
+    tokio::fs::read(path).await.map_err(|e| invalid_config(format!("failed to read {}: {e}", path.display())))
+}
+
+async fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let pem = read_pem(path).await?;
+    let certs = rustls_pemfile::certs(&mut &pem[..])
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid_config(format!("invalid certificate in {}: {e}", path.display())))?;
+
+    if certs.is_empty() {
+        return Err(invalid_config(format!("no certificate found in {}", path.display())));
+    }
+
+    Ok(certs)
+}
+
+async fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, Error> {
+    let pem = read_pem(path).await?;
+
+    match rustls_pemfile::private_key(&mut &pem[..]).map_err(|e| invalid_config(format!("invalid private key in {}: {e}", path.display())))? {
+        Some(key) => Ok(key),
+        None => Err(invalid_config(format!("no private key found in {}", path.display()))),
+    }
+}
+
+/// builds acceptor from certificate and key files, clients must present a
+/// certificate signed by one of `client_ca_path` CAs if it is set
+pub(crate) async fn tls_acceptor(tls_config: &TlsConfig) -> Result<TlsAcceptor, Error> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(invalid_config)?;
+
+    let builder = match tls_config.client_ca_path() {
+        Some(client_ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(client_ca_path).await? {
+                roots.add(cert).map_err(invalid_config)?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider as Arc<CryptoProvider>)
+                .build()
+                .map_err(invalid_config)?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let certs = load_certs(tls_config.cert_path()).await?;
+    let key = load_private_key(tls_config.key_path()).await?;
+    let server_config = builder.with_single_cert(certs, key).map_err(invalid_config)?;
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// subject of client's certificate (e.g. `CN=alice, O=acme`), `None` if
+/// client didn't present one
+pub(crate) fn peer_principal(connection: &ServerConnection) -> Option<String> {
+    let cert = connection.peer_certificates()?.first()?;
+    let (_, cert) = x509_parser::parse_x509_certificate(cert).ok()?;
+
+    Some(cert.subject().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::{Broker, ConnectionHandler, ConnectionInfo, MemoryListener, Transport};
+    use async_trait::async_trait;
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+    use rustls::pki_types::ServerName;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::broadcast;
+    use tokio_rustls::TlsConnector;
+
+    /// answers every connection with principal of its peer
+    struct PrincipalEchoHandler;
+
+    #[async_trait]
+    impl ConnectionHandler for PrincipalEchoHandler {
+        async fn handle_connection<S: Transport>(self: Arc<Self>, mut stream: S, info: ConnectionInfo, _termination_signal_recvr: broadcast::Receiver<()>) {
+            let principal = info.principal().unwrap_or("anonymous").to_owned();
+            let _ = stream.write_all(principal.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        }
+    }
+
+    /// writes CA, server and client certificates under `root_path`, returns
+    /// client's certificate chain, client's key and CA certificate
+    async fn write_certs(root_path: &str) -> (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>, CertificateDer<'static>) {
+        tokio::fs::create_dir_all(root_path).await.unwrap();
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params.distinguished_name.push(DnType::CommonName, "test-ca");
+        let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+
+        let server_key = KeyPair::generate().unwrap();
+        let server_cert = CertificateParams::new(vec!["localhost".to_owned()])
+            .unwrap()
+            .signed_by(&server_key, &ca_cert, &ca_key)
+            .unwrap();
+
+        let client_key = KeyPair::generate().unwrap();
+        let mut client_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        client_params.distinguished_name.push(DnType::CommonName, "alice");
+        let client_cert = client_params.signed_by(&client_key, &ca_cert, &ca_key).unwrap();
+
+        tokio::fs::write(format!("{root_path}/ca.pem"), ca_cert.pem()).await.unwrap();
+        tokio::fs::write(format!("{root_path}/server.pem"), server_cert.pem()).await.unwrap();
+        tokio::fs::write(format!("{root_path}/server.key"), server_key.serialize_pem()).await.unwrap();
+
+        let client_key = PrivateKeyDer::try_from(client_key.serialize_der()).unwrap();
+        (vec![client_cert.der().clone()], client_key, ca_cert.der().clone())
+    }
+
+    async fn connect(
+        tls_config: &TlsConfig,
+        client_auth: Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>,
+        ca_cert: CertificateDer<'static>,
+    ) -> std::io::Result<String> {
+        let (mut listener, connector) = MemoryListener::new(16 * 1024);
+        let mut broker = Broker::new();
+        broker.enable_tls(tls_config).await.unwrap();
+        let termination_signal_sender = broker.get_termination_signal_sender().await;
+        let broker = tokio::spawn(async move { broker.serve(&mut listener, Arc::new(PrincipalEchoHandler)).await });
+
+        let mut roots = RootCertStore::empty();
+        roots.add(ca_cert).unwrap();
+        let builder = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let client_config = match client_auth {
+            Some((certs, key)) => builder.with_client_auth_cert(certs, key).unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+
+        let stream = connector.connect().await.unwrap();
+        let result = async {
+            let mut stream = TlsConnector::from(Arc::new(client_config))
+                .connect(ServerName::try_from("localhost").unwrap(), stream)
+                .await?;
+
+            let mut principal = String::new();
+            stream.read_to_string(&mut principal).await?;
+
+            Ok(principal)
+        }
+        .await;
+
+        termination_signal_sender.send(()).unwrap();
+        broker.await.unwrap();
+
+        result
+    }
+
+    #[tokio::test]
+    async fn tls_test_01() {
+        let root_path = "./tls_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+        let (client_certs, client_key, ca_cert) = write_certs(root_path).await;
+
+        let tls_config = TlsConfig::new(format!("{root_path}/server.pem").into(), format!("{root_path}/server.key").into(), None);
+        assert_eq!(connect(&tls_config, None, ca_cert.clone()).await.unwrap(), "anonymous");
+
+        let tls_config = TlsConfig::new(
+            format!("{root_path}/server.pem").into(),
+            format!("{root_path}/server.key").into(),
+            Some(format!("{root_path}/ca.pem").into()),
+        );
+        assert_eq!(connect(&tls_config, Some((client_certs, client_key)), ca_cert.clone()).await.unwrap(), "CN=alice");
+
+        // client certificate is required once client CAs are configured
+        assert!(connect(&tls_config, None, ca_cert).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn tls_acceptor_test_01() {
+        let root_path = "./tls_acceptor_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+        write_certs(root_path).await;
+
+        // certificate file where key file is expected
+        let tls_config = TlsConfig::new(format!("{root_path}/server.pem").into(), format!("{root_path}/server.pem").into(), None);
+        assert!(matches!(tls_acceptor(&tls_config).await, Err(Error::Config(_))));
+
+        let tls_config = TlsConfig::new(format!("{root_path}/missing.pem").into(), format!("{root_path}/server.key").into(), None);
+        assert!(matches!(tls_acceptor(&tls_config).await, Err(Error::Config(_))));
+    }
+}
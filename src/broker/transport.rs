@@ -0,0 +1,202 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+/// byte stream a SESP connection can be served over
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + 'static> Transport for T {}
+
+/// address of connected peer, as reported by listener it connected to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerAddr {
+    Tcp(SocketAddr),
+    /// path the peer's socket is bound to, `None` for unnamed sockets
+    Unix(Option<PathBuf>),
+    /// id of connection, unique within a process
+    Memory(u64),
+}
+
+/// what is known about the peer of a connection before any command is read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    peer_addr: PeerAddr,
+    principal: Option<String>,
+}
+
+/// source of incoming connections served by `Broker`
+#[async_trait]
+pub trait Listener: Send + 'static {
+    type Stream: Transport;
+
+    async fn accept(&mut self) -> std::io::Result<(Self::Stream, PeerAddr)>;
+}
+
+/// listener for in-memory connections made through its `MemoryConnector`,
+/// useful for embedding the broker and for tests.
+pub struct MemoryListener {
+    connections: mpsc::Receiver<(DuplexStream, PeerAddr)>,
+}
+
+/// client side of `MemoryListener`, cheap to clone
+#[derive(Clone)]
+pub struct MemoryConnector {
+    connections: mpsc::Sender<(DuplexStream, PeerAddr)>,
+    next_id: Arc<AtomicU64>,
+    max_buf_size: usize,
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(Some(path)) => write!(f, "unix:{}", path.display()),
+            Self::Unix(None) => write!(f, "unix:(unnamed)"),
+            Self::Memory(id) => write!(f, "memory:{id}"),
+        }
+    }
+}
+
+impl ConnectionInfo {
+    pub fn new(peer_addr: PeerAddr, principal: Option<String>) -> Self {
+        Self { peer_addr, principal }
+    }
+
+    pub fn peer_addr(&self) -> &PeerAddr {
+        &self.peer_addr
+    }
+
+    /// identity peer authenticated as while connecting, that is subject of
+    /// its certificate when mutual TLS is enabled
+    pub fn principal(&self) -> Option<&str> {
+        self.principal.as_deref()
+    }
+}
+
+#[async_trait]
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    async fn accept(&mut self) -> std::io::Result<(Self::Stream, PeerAddr)> {
+        let (stream, addr) = TcpListener::accept(self).await?;
+
+        Ok((stream, PeerAddr::Tcp(addr)))
+    }
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl Listener for tokio::net::UnixListener {
+    type Stream = tokio::net::UnixStream;
+
+    async fn accept(&mut self) -> std::io::Result<(Self::Stream, PeerAddr)> {
+        let (stream, addr) = tokio::net::UnixListener::accept(self).await?;
+
+        Ok((stream, PeerAddr::Unix(addr.as_pathname().map(|path| path.to_owned()))))
+    }
+}
+
+impl MemoryListener {
+    /// each direction of a connection buffers upto `max_buf_size` bytes
+    pub fn new(max_buf_size: usize) -> (Self, MemoryConnector) {
+        let (sender, connections) = mpsc::channel(1);
+        let connector = MemoryConnector {
+            connections: sender,
+            next_id: Arc::new(AtomicU64::new(0)),
+            max_buf_size,
+        };
+
+        (Self { connections }, connector)
+    }
+}
+
+#[async_trait]
+impl Listener for MemoryListener {
+    type Stream = DuplexStream;
+
+    /// fails once every `MemoryConnector` has been dropped
+    async fn accept(&mut self) -> std::io::Result<(Self::Stream, PeerAddr)> {
+        match self.connections.recv().await {
+            Some(connection) => Ok(connection),
+            None => Err(std::io::ErrorKind::NotConnected.into()),
+        }
+    }
+}
+
+impl MemoryConnector {
+    /// returns client end of a new connection, fails once listener has been dropped
+    pub async fn connect(&self) -> std::io::Result<DuplexStream> {
+        let (client, server) = tokio::io::duplex(self.max_buf_size);
+        let addr = PeerAddr::Memory(self.next_id.fetch_add(1, Ordering::Relaxed));
+
+        self.connections
+            .send((server, addr))
+            .await
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::ConnectionRefused))?;
+
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::{Broker, SimpleAdminConnectionHandler};
+    use crate::topic::TopicRegistry;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    #[tokio::test]
+    async fn memory_listener_test_01() {
+        let root_path = "./memory_listener_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let (mut listener, connector) = MemoryListener::new(1024);
+        let handler = Arc::new(SimpleAdminConnectionHandler::new(Arc::new(TopicRegistry::new(root_path))));
+
+        let mut broker = Broker::new();
+        let termination_signal_sender = broker.get_termination_signal_sender().await;
+        let broker = tokio::spawn(async move { broker.serve(&mut listener, handler).await });
+
+        let mut client = BufReader::new(connector.connect().await.unwrap());
+        client.write_all(b"#foo\n#foo\n").await.unwrap();
+
+        let mut responses = String::new();
+        client.read_line(&mut responses).await.unwrap();
+        client.read_line(&mut responses).await.unwrap();
+        assert_eq!(responses, "+\n-AlreadyExists topic `foo` already exists\n");
+
+        termination_signal_sender.send(()).unwrap();
+        broker.await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_listener_test_01() {
+        let root_path = "./unix_listener_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+        tokio::fs::create_dir_all(root_path).await.unwrap();
+
+        let socket_path = std::path::Path::new(root_path).join("broker.sock");
+        let mut listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+
+        let client = tokio::net::UnixStream::connect(&socket_path).await.unwrap();
+        let (_stream, addr) = Listener::accept(&mut listener).await.unwrap();
+
+        assert_eq!(addr, PeerAddr::Unix(None));
+        drop(client);
+    }
+
+    #[test]
+    fn peer_addr_display_test_01() {
+        assert_eq!(PeerAddr::Tcp("127.0.0.1:8080".parse().unwrap()).to_string(), "127.0.0.1:8080");
+        assert_eq!(PeerAddr::Unix(Some("/tmp/broker.sock".into())).to_string(), "unix:/tmp/broker.sock");
+        assert_eq!(PeerAddr::Memory(7).to_string(), "memory:7");
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use super::{positive, Client, ClientError, ClientOptions, Connector};
+use crate::acl::AclRule;
+use crate::sesp::{Command, ConfigOptions, Response};
+use crate::types::TopicName;
+
+/// client of admin address, manages topics and ACLs
+pub struct AdminClient {
+    client: Client,
+}
+
+impl AdminClient {
+    /// connects lazily, on first request
+    pub fn new(connector: impl Connector, options: ClientOptions) -> Self {
+        Self {
+            client: Client::new(Arc::new(connector), options, vec![]),
+        }
+    }
+
+    pub async fn ping(&mut self) -> Result<(), ClientError> {
+        positive(self.client.request(Command::Ping, true).await?).map(|_| ())
+    }
+
+    /// options not given fall back to server defaults, e.g. `("num_of_segments", "4")`
+    pub async fn create_topic(&mut self, topic_name: &TopicName, options: ConfigOptions) -> Result<(), ClientError> {
+        positive(self.client.request(Command::CreateTopic(topic_name.clone(), options), false).await?).map(|_| ())
+    }
+
+    /// topics this client is allowed any operation on, sorted by name
+    pub async fn list_topics(&mut self) -> Result<Vec<TopicName>, ClientError> {
+        match self.client.request(Command::ListTopics, true).await? {
+            Response::Messages(topic_names) => topic_names
+                .iter()
+                .map(|topic_name| {
+                    let topic_name = String::from_utf8_lossy(topic_name.value());
+                    TopicName::try_from(topic_name.trim_end_matches('\n')).map_err(|e| ClientError::Protocol(format!("{e}")))
+                })
+                .collect(),
+            response => Err(ClientError::Protocol(format!("expected topic names, got {response:?}"))),
+        }
+    }
+
+    /// every config option of topic, followed by `last_flushed_offset` (`none` if nothing was flushed yet)
+    pub async fn describe_topic(&mut self, topic_name: &TopicName) -> Result<ConfigOptions, ClientError> {
+        let description = positive(self.client.request(Command::DescribeTopic(topic_name.clone()), true).await?)?;
+
+        description
+            .split_whitespace()
+            .map(|option| match option.split_once('=') {
+                Some((key, value)) => Ok((key.to_owned(), value.to_owned())),
+                None => Err(ClientError::Protocol(format!("invalid topic description `{description}`"))),
+            })
+            .collect()
+    }
+
+    pub async fn alter_topic_config(&mut self, topic_name: &TopicName, options: ConfigOptions) -> Result<(), ClientError> {
+        positive(self.client.request(Command::AlterTopicConfig(topic_name.clone(), options), true).await?).map(|_| ())
+    }
+
+    pub async fn delete_topic(&mut self, topic_name: &TopicName) -> Result<(), ClientError> {
+        positive(self.client.request(Command::DeleteTopic(topic_name.clone()), false).await?).map(|_| ())
+    }
+
+    pub async fn grant_acl(&mut self, rule: AclRule) -> Result<(), ClientError> {
+        positive(self.client.request(Command::GrantAcl(rule), true).await?).map(|_| ())
+    }
+
+    pub async fn revoke_acl(&mut self, rule: AclRule) -> Result<(), ClientError> {
+        positive(self.client.request(Command::RevokeAcl(rule), false).await?).map(|_| ())
+    }
+
+    pub async fn list_acls(&mut self) -> Result<Vec<AclRule>, ClientError> {
+        match self.client.request(Command::ListAcls, true).await? {
+            Response::Messages(rules) => rules
+                .iter()
+                .map(|rule| {
+                    let rule = String::from_utf8_lossy(rule.value());
+                    rule.trim_end_matches('\n').parse().map_err(|e| ClientError::Protocol(format!("{e}")))
+                })
+                .collect(),
+            response => Err(ClientError::Protocol(format!("expected ACL rules, got {response:?}"))),
+        }
+    }
+}
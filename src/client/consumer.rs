@@ -0,0 +1,130 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::Stream;
+
+use super::{positive, Client, ClientError, ClientOptions, Connector};
+use crate::sesp::{Command, Response, MAX_BATCH_LEN};
+use crate::topic::IsolationLevel;
+use crate::types::{Message, TopicName};
+
+/// how often a consumer that has caught up with its topic looks for new messages unless configured otherwise
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// client of subscriber address, reads a single topic with `Fetch` starting
+/// at an offset, tracking offset of next message to read itself
+pub struct Consumer {
+    client: Client,
+    topic_name: TopicName,
+    offset: usize,
+    max_messages: usize,
+    max_bytes: usize,
+    poll_interval: Duration,
+}
+
+impl Consumer {
+    /// connects lazily, on first fetch, and reads from offset 0 unless told otherwise
+    pub fn new(connector: impl Connector, options: ClientOptions, topic_name: TopicName) -> Self {
+        // leaves room for header of a response within max frame size
+        let max_bytes = options.max_frame_size / 2;
+
+        Self {
+            client: Client::new(Arc::new(connector), options, vec![Command::SelectTopic(topic_name.clone())]),
+            topic_name,
+            offset: 0,
+            max_messages: MAX_BATCH_LEN,
+            max_bytes,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// applies to every connection made from now on
+    pub fn with_isolation_level(mut self, isolation_level: IsolationLevel) -> Self {
+        self.client.setup.push(Command::SetIsolationLevel(isolation_level));
+        self
+    }
+
+    /// max number of messages returned by a single fetch, capped at `MAX_BATCH_LEN`
+    pub fn with_max_messages(mut self, max_messages: usize) -> Self {
+        self.max_messages = max_messages.clamp(1, MAX_BATCH_LEN);
+        self
+    }
+
+    /// max number of bytes returned by a single fetch, a message larger than that is still returned on its own
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes.max(1);
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn topic_name(&self) -> &TopicName {
+        &self.topic_name
+    }
+
+    /// offset of next message to read
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// next fetch reads from `offset`
+    pub fn seek(&mut self, offset: usize) {
+        self.offset = offset;
+    }
+
+    /// moves to first message that may have been published at or after `timestamp`.
+    /// messages don't carry timestamps, so this is only as precise as
+    /// modification times of segment files of topic.
+    pub async fn seek_to_timestamp(&mut self, timestamp: SystemTime) -> Result<usize, ClientError> {
+        let timestamp_ms = timestamp.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let data = positive(self.client.request(Command::OffsetForTimestamp(timestamp_ms), true).await?)?;
+
+        self.offset = data.parse().map_err(|_| ClientError::Protocol(format!("expected offset, got `{data}`")))?;
+        Ok(self.offset)
+    }
+
+    /// reads consecutive messages at current offset and moves past them, empty
+    /// if there is no visible message at current offset yet. every message
+    /// carries its offset and ends with a line terminator.
+    pub async fn fetch(&mut self) -> Result<Vec<Message>, ClientError> {
+        let command = Command::Fetch(self.offset, self.max_messages, self.max_bytes);
+        let msgs = match self.client.request(command, true).await? {
+            Response::Messages(msgs) => msgs,
+            response => return Err(ClientError::Protocol(format!("expected messages, got {response:?}"))),
+        };
+
+        if let Some(offset) = msgs.last().and_then(|msg| msg.offset()) {
+            self.offset = offset + 1;
+        }
+
+        Ok(msgs)
+    }
+
+    /// endless stream of messages from current offset onwards, waiting for new
+    /// ones once caught up. ends right after the first error.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Message, ClientError>> {
+        futures::stream::unfold(Some((self, VecDeque::new())), |state| async move {
+            let (mut consumer, mut msgs) = state?;
+            loop {
+                if let Some(msg) = msgs.pop_front() {
+                    return Some((Ok(msg), Some((consumer, msgs))));
+                }
+
+                match consumer.fetch().await {
+                    Ok(fetched) if fetched.is_empty() => tokio::time::sleep(consumer.poll_interval).await,
+                    Ok(fetched) => msgs.extend(fetched),
+                    Err(e) => return Some((Err(e), None)),
+                }
+            }
+        })
+    }
+}
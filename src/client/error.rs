@@ -0,0 +1,167 @@
+use std::fmt;
+
+/// stable SESP error code of a negative response, see README for meaning of each
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorCode {
+    IoError,
+    ProtocolError,
+    NoSuchTopicExists,
+    AlreadyExists,
+    InvalidArgument,
+    InvalidTopicName,
+    InvalidTopicConfig,
+    NotSupported,
+    UnsupportedVersion,
+    DuplicateSequence,
+    OutOfOrderSequence,
+    FrameTooLarge,
+    NotAuthenticated,
+    AuthenticationFailed,
+    NotAuthorized,
+    ShuttingDown,
+    TooManyConnections,
+    /// code introduced by a newer server
+    Other(String),
+}
+
+/// error of a client request
+#[derive(Debug)]
+pub enum ClientError {
+    /// connecting to server, or reading from or writing to it, failed
+    Connection(std::io::Error),
+    /// server didn't answer within request timeout
+    Timeout,
+    /// server answered with something that isn't a valid response to the command
+    Protocol(String),
+    /// server rejected the command with a negative response, `argument` is
+    /// the machine readable argument some codes carry (e.g. offset of a duplicate)
+    Server { code: ErrorCode, argument: Option<String>, description: String },
+    /// command was not sent as it can't be encoded, e.g. message contains a line terminator
+    InvalidArgument(String),
+}
+
+impl ErrorCode {
+    pub fn parse(code: &str) -> Self {
+        match code {
+            "IOError" => Self::IoError,
+            "ProtocolError" => Self::ProtocolError,
+            "NoSuchTopicExists" => Self::NoSuchTopicExists,
+            "AlreadyExists" => Self::AlreadyExists,
+            "InvalidArgument" => Self::InvalidArgument,
+            "InvalidTopicName" => Self::InvalidTopicName,
+            "InvalidTopicConfig" => Self::InvalidTopicConfig,
+            "NotSupported" => Self::NotSupported,
+            "UnsupportedVersion" => Self::UnsupportedVersion,
+            "DuplicateSequence" => Self::DuplicateSequence,
+            "OutOfOrderSequence" => Self::OutOfOrderSequence,
+            "FrameTooLarge" => Self::FrameTooLarge,
+            "NotAuthenticated" => Self::NotAuthenticated,
+            "AuthenticationFailed" => Self::AuthenticationFailed,
+            "NotAuthorized" => Self::NotAuthorized,
+            "ShuttingDown" => Self::ShuttingDown,
+            "TooManyConnections" => Self::TooManyConnections,
+            code => Self::Other(code.to_owned()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::IoError => "IOError",
+            Self::ProtocolError => "ProtocolError",
+            Self::NoSuchTopicExists => "NoSuchTopicExists",
+            Self::AlreadyExists => "AlreadyExists",
+            Self::InvalidArgument => "InvalidArgument",
+            Self::InvalidTopicName => "InvalidTopicName",
+            Self::InvalidTopicConfig => "InvalidTopicConfig",
+            Self::NotSupported => "NotSupported",
+            Self::UnsupportedVersion => "UnsupportedVersion",
+            Self::DuplicateSequence => "DuplicateSequence",
+            Self::OutOfOrderSequence => "OutOfOrderSequence",
+            Self::FrameTooLarge => "FrameTooLarge",
+            Self::NotAuthenticated => "NotAuthenticated",
+            Self::AuthenticationFailed => "AuthenticationFailed",
+            Self::NotAuthorized => "NotAuthorized",
+            Self::ShuttingDown => "ShuttingDown",
+            Self::TooManyConnections => "TooManyConnections",
+            Self::Other(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl ClientError {
+    /// parses data of a negative response, `<code>[:<argument>] <description>`
+    pub(super) fn from_negative_response(data: &str) -> Self {
+        let (code, description) = data.split_once(' ').unwrap_or((data, ""));
+        let (code, argument) = match code.split_once(':') {
+            Some((code, argument)) => (code, Some(argument.to_owned())),
+            None => (code, None),
+        };
+
+        Self::Server {
+            code: ErrorCode::parse(code),
+            argument,
+            description: description.to_owned(),
+        }
+    }
+
+    /// error code of negative response, `None` for errors not coming from server
+    pub fn code(&self) -> Option<&ErrorCode> {
+        match self {
+            Self::Server { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+
+    /// machine readable argument of negative response, if its code has one
+    pub fn argument(&self) -> Option<&str> {
+        match self {
+            Self::Server { argument, .. } => argument.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// whether connection can't be used any more, server closes connection
+    /// right after `ShuttingDown`, `TooManyConnections` and `FrameTooLarge`
+    pub(super) fn is_connection_lost(&self) -> bool {
+        matches!(self, Self::Connection(_) | Self::Timeout | Self::Protocol(_))
+            || matches!(self.code(), Some(ErrorCode::ShuttingDown | ErrorCode::TooManyConnections | ErrorCode::FrameTooLarge))
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connection(e) => write!(f, "connection error: {e}"),
+            Self::Timeout => write!(f, "request timed out"),
+            Self::Protocol(reason) => write!(f, "protocol error: {reason}"),
+            Self::Server { code, description, .. } => write!(f, "{code}: {description}"),
+            Self::InvalidArgument(reason) => write!(f, "invalid argument: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Connection(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// errors of `ClientCodec`, I/O errors of the socket surface as `Storage`
+impl From<crate::error::Error> for ClientError {
+    fn from(value: crate::error::Error) -> Self {
+        match value {
+            crate::error::Error::Storage(e) | crate::error::Error::Connection(e) => Self::Connection(e),
+            crate::error::Error::InvalidArgument(reason) => Self::InvalidArgument(reason),
+            e => Self::Protocol(e.to_string()),
+        }
+    }
+}
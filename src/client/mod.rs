@@ -0,0 +1,453 @@
+mod admin;
+mod consumer;
+mod error;
+mod producer;
+
+pub use self::admin::AdminClient;
+pub use self::consumer::Consumer;
+pub use self::error::{ClientError, ErrorCode};
+pub use self::producer::Producer;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use rustls::pki_types::ServerName;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_util::codec::Framed;
+use tracing::debug;
+
+use crate::broker::{MemoryConnector, Transport};
+use crate::sesp::{ClientCodec, Command, Credentials, Hello, Response, CAPABILITIES, DEFAULT_MAX_FRAME_SIZE, PROTOCOL_VERSION};
+
+/// max time to wait for a response unless configured otherwise
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// source of connections to a server, called again whenever a client reconnects
+#[async_trait]
+pub trait Connector: Send + Sync + 'static {
+    async fn connect(&self) -> std::io::Result<Box<dyn Transport>>;
+}
+
+/// connects over TCP, optionally wrapped in TLS
+#[derive(Clone)]
+pub struct TcpConnector {
+    addr: String,
+    tls: Option<(TlsConnector, ServerName<'static>)>,
+}
+
+/// options shared by every client type
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    client_name: String,
+    credentials: Option<Credentials>,
+    max_frame_size: usize,
+    request_timeout: Duration,
+    max_retries: usize,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+type Session = Framed<Box<dyn Transport>, ClientCodec>;
+
+/// connection shared by every client type. connects lazily, reconnects with
+/// exponential backoff once connection is lost and replays `setup` commands
+/// (e.g. `SelectTopic`) on every new connection.
+struct Client {
+    connector: Arc<dyn Connector>,
+    options: ClientOptions,
+    setup: Vec<Command>,
+    session: Option<Session>,
+}
+
+impl TcpConnector {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self { addr: addr.into(), tls: None }
+    }
+
+    /// verifies server's certificate against `server_name`
+    pub fn with_tls(mut self, config: Arc<rustls::ClientConfig>, server_name: ServerName<'static>) -> Self {
+        self.tls = Some((TlsConnector::from(config), server_name));
+        self
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+}
+
+#[async_trait]
+impl Connector for TcpConnector {
+    async fn connect(&self) -> std::io::Result<Box<dyn Transport>> {
+        let stream = TcpStream::connect(&self.addr).await?;
+        stream.set_nodelay(true)?;
+
+        match self.tls {
+            Some((ref tls_connector, ref server_name)) => Ok(Box::new(tls_connector.connect(server_name.clone(), stream).await?)),
+            None => Ok(Box::new(stream)),
+        }
+    }
+}
+
+#[async_trait]
+impl Connector for MemoryConnector {
+    async fn connect(&self) -> std::io::Result<Box<dyn Transport>> {
+        Ok(Box::new(MemoryConnector::connect(self).await?))
+    }
+}
+
+impl ClientOptions {
+    pub fn new(client_name: impl Into<String>) -> Self {
+        Self {
+            client_name: client_name.into(),
+            credentials: None,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+
+    /// sent with `Auth` right after handshake on every connection
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// should match max frame size of server, bounds batches and fetches
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    pub fn with_request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    /// a request is retried upto `max_retries` times, waiting `initial_backoff`
+    /// before first retry and twice as long before every next one upto `max_backoff`
+    pub fn with_retries(mut self, max_retries: usize, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn client_name(&self) -> &str {
+        &self.client_name
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        self.request_timeout
+    }
+
+    pub fn max_retries(&self) -> usize {
+        self.max_retries
+    }
+
+    fn backoff(&self, attempt: usize) -> Duration {
+        self.initial_backoff.saturating_mul(2u32.saturating_pow(attempt as u32)).min(self.max_backoff)
+    }
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self::new("stream-relay-client")
+    }
+}
+
+/// data of a positive response
+fn positive(response: Response) -> Result<String, ClientError> {
+    match response {
+        Response::Positive(data) => Ok(data),
+        response => Err(ClientError::Protocol(format!("expected positive response, got {response:?}"))),
+    }
+}
+
+/// waits for response of last command sent, negative responses are errors
+async fn receive(session: &mut Session) -> Result<Response, ClientError> {
+    match session.next().await {
+        Some(Ok((_, Response::Negative(data)))) => Err(ClientError::from_negative_response(&data)),
+        Some(Ok((_, response))) => Ok(response),
+        Some(Err(e)) => Err(e.into()),
+        None => Err(ClientError::Connection(std::io::ErrorKind::UnexpectedEof.into())),
+    }
+}
+
+async fn round_trip(session: &mut Session, command: Command) -> Result<Response, ClientError> {
+    session.send((None, command)).await?;
+    receive(session).await
+}
+
+impl Client {
+    fn new(connector: Arc<dyn Connector>, options: ClientOptions, setup: Vec<Command>) -> Self {
+        Self {
+            connector,
+            options,
+            setup,
+            session: None,
+        }
+    }
+
+    /// connects, handshakes, authenticates and replays setup commands
+    async fn open(&self) -> Result<Session, ClientError> {
+        let stream = self.connector.connect().await.map_err(ClientError::Connection)?;
+        let mut session = Framed::new(stream, ClientCodec::new(self.options.max_frame_size));
+
+        let capabilities = CAPABILITIES.iter().map(|capability| capability.to_string()).collect();
+        let hello = Hello::new(PROTOCOL_VERSION, self.options.client_name.clone(), capabilities);
+        positive(round_trip(&mut session, Command::Hello(hello)).await?)?;
+
+        if let Some(ref credentials) = self.options.credentials {
+            positive(round_trip(&mut session, Command::Auth(credentials.clone())).await?)?;
+        }
+
+        for command in self.setup.iter() {
+            positive(round_trip(&mut session, command.clone()).await?)?;
+        }
+
+        Ok(session)
+    }
+
+    /// single attempt of `request`, returns whether `command` was sent along with the outcome
+    async fn try_request(&mut self, command: Command) -> (bool, Result<Response, ClientError>) {
+        let mut session = match self.session.take() {
+            Some(session) => session,
+            None => match tokio::time::timeout(self.options.request_timeout, self.open()).await {
+                Ok(Ok(session)) => session,
+                Ok(Err(e)) => return (false, Err(e)),
+                Err(_) => return (false, Err(ClientError::Timeout)),
+            },
+        };
+
+        // writing fails once server has closed connection, nothing was processed then
+        let mut sent = false;
+        let result = tokio::time::timeout(self.options.request_timeout, async {
+            session.send((None, command)).await?;
+            sent = true;
+            receive(&mut session).await
+        })
+        .await
+        .unwrap_or(Err(ClientError::Timeout));
+
+        if !result.as_ref().is_err_and(ClientError::is_connection_lost) {
+            self.session = Some(session);
+        }
+
+        (sent, result)
+    }
+
+    /// sends `command` and waits for its response, reconnecting as needed.
+    ///
+    /// a command is retried when connecting failed, when server answered
+    /// `ShuttingDown` or `TooManyConnections` (command was not processed)
+    /// and, only if it is `idempotent`, when connection was lost after it was sent.
+    async fn request(&mut self, command: Command, idempotent: bool) -> Result<Response, ClientError> {
+        self.request_with_sent(command, idempotent).await.1
+    }
+
+    /// `request` that also returns whether its last attempt sent `command`
+    async fn request_with_sent(&mut self, command: Command, idempotent: bool) -> (bool, Result<Response, ClientError>) {
+        let mut attempt = 0;
+        loop {
+            let (sent, result) = self.try_request(command.clone()).await;
+
+            let retriable = match result {
+                Err(ref e) if matches!(e.code(), Some(ErrorCode::ShuttingDown | ErrorCode::TooManyConnections)) => true,
+                Err(ClientError::Connection(_) | ClientError::Timeout) => !sent || idempotent,
+                _ => false,
+            };
+            if !retriable || attempt == self.options.max_retries {
+                return (sent, result);
+            }
+
+            let backoff = self.options.backoff(attempt);
+            debug!(command = command.name(), error = %result.unwrap_err(), ?backoff, "retrying request");
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::{Broker, ConnectionHandler, MemoryListener, SimpleAdminConnectionHandler, SimplePublisherConnectionHandler, SimpleSubscriberConnectionHandler};
+    use crate::topic::TopicRegistry;
+    use crate::types::TopicName;
+    use tokio::sync::{broadcast, Mutex};
+    use tokio::task::JoinHandle;
+
+    /// serves `handler` over in-memory connections until termination signal
+    async fn spawn_broker<T: ConnectionHandler>(handler: T) -> (MemoryConnector, broadcast::Sender<()>, JoinHandle<()>) {
+        let (mut listener, connector) = MemoryListener::new(64 * 1024);
+        let mut broker = Broker::new();
+        let termination_signal_sender = broker.get_termination_signal_sender().await;
+        let handle = tokio::spawn(async move { broker.serve(&mut listener, Arc::new(handler)).await });
+
+        (connector, termination_signal_sender, handle)
+    }
+
+    /// connector that can be pointed to another broker, like a restarted one
+    #[derive(Clone)]
+    struct SwappableConnector(Arc<Mutex<Option<MemoryConnector>>>);
+
+    #[async_trait]
+    impl Connector for SwappableConnector {
+        async fn connect(&self) -> std::io::Result<Box<dyn Transport>> {
+            match *self.0.lock().await {
+                Some(ref connector) => Connector::connect(connector).await,
+                None => Err(std::io::ErrorKind::ConnectionRefused.into()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn client_test_01() {
+        let root_path = "./client_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = Arc::new(TopicRegistry::new(root_path));
+        let (admin_connector, admin_termination, admin) = spawn_broker(SimpleAdminConnectionHandler::new(registry.clone())).await;
+        let (publisher_connector, publisher_termination, publisher) = spawn_broker(SimplePublisherConnectionHandler::new(registry.clone())).await;
+        let (subscriber_connector, subscriber_termination, subscriber) = spawn_broker(SimpleSubscriberConnectionHandler::new(registry.clone())).await;
+
+        let topic_name: TopicName = "orders".parse().unwrap();
+        let mut admin_client = AdminClient::new(admin_connector, ClientOptions::default());
+        admin_client.ping().await.unwrap();
+        admin_client.create_topic(&topic_name, vec![("num_of_msg_per_file".to_owned(), "2".to_owned())]).await.unwrap();
+        let error = admin_client.create_topic(&topic_name, vec![]).await.unwrap_err();
+        assert_eq!(error.code(), Some(&ErrorCode::AlreadyExists));
+        assert_eq!(error.to_string(), "AlreadyExists: topic `orders` already exists");
+        assert_eq!(admin_client.list_topics().await.unwrap(), vec![topic_name.clone()]);
+        let description = admin_client.describe_topic(&topic_name).await.unwrap();
+        assert!(description.contains(&("num_of_msg_per_file".to_owned(), "2".to_owned())));
+        assert_eq!(description.last(), Some(&("last_flushed_offset".to_owned(), "none".to_owned())));
+        let error = admin_client.describe_topic(&"missing".parse().unwrap()).await.unwrap_err();
+        assert_eq!(error.code(), Some(&ErrorCode::NoSuchTopicExists));
+
+        let mut producer = Producer::new(publisher_connector.clone(), ClientOptions::default(), topic_name.clone()).with_max_batch_len(2);
+        assert_eq!(producer.publish("first").await.unwrap(), 0);
+        assert_eq!(producer.send("second").await.unwrap(), None);
+        assert_eq!(producer.send("third").await.unwrap(), Some(1));
+        assert_eq!(producer.send("fourth").await.unwrap(), None);
+        assert_eq!(producer.flush().await.unwrap(), Some(3));
+        assert_eq!(producer.flush().await.unwrap(), None);
+        assert!(matches!(producer.send("fifth\nsixth").await, Err(ClientError::InvalidArgument(_))));
+
+        let mut missing_producer = Producer::new(publisher_connector, ClientOptions::default(), "missing".parse().unwrap());
+        assert_eq!(missing_producer.publish("first").await.unwrap_err().code(), Some(&ErrorCode::NoSuchTopicExists));
+
+        let mut consumer = Consumer::new(subscriber_connector, ClientOptions::default(), topic_name).with_offset(1).with_max_messages(2);
+        let values = |msgs: Vec<crate::types::Message>| msgs.iter().map(|msg| msg.value().clone()).collect::<Vec<_>>();
+        assert_eq!(values(consumer.fetch().await.unwrap()), vec!["second\n", "third\n"]);
+        assert_eq!(consumer.offset(), 3);
+        assert_eq!(consumer.seek_to_timestamp(std::time::UNIX_EPOCH).await.unwrap(), 0);
+        consumer.seek(3);
+
+        let mut stream = Box::pin(consumer.into_stream());
+        assert_eq!(stream.next().await.unwrap().unwrap().value(), "fourth\n");
+
+        // picked up by the stream's next poll once published
+        assert_eq!(producer.publish("fifth").await.unwrap(), 4);
+        let msg = stream.next().await.unwrap().unwrap();
+        assert_eq!((msg.value().as_ref(), msg.offset()), (&b"fifth\n"[..], Some(&4)));
+
+        for termination_signal_sender in [admin_termination, publisher_termination, subscriber_termination] {
+            termination_signal_sender.send(()).unwrap();
+        }
+        drop(stream);
+        admin.await.unwrap();
+        publisher.await.unwrap();
+        subscriber.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn client_reconnect_test_01() {
+        let root_path = "./client_reconnect_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = Arc::new(TopicRegistry::new(root_path));
+        let topic_name: TopicName = "orders".parse().unwrap();
+        registry.create_topic(crate::types::TopicMetaData::new_with_few_defaults(registry.topic(topic_name.clone()))).await.unwrap();
+
+        let (connector, termination_signal_sender, broker) = spawn_broker(SimplePublisherConnectionHandler::new(registry.clone())).await;
+        let swappable_connector = SwappableConnector(Arc::new(Mutex::new(Some(connector))));
+        let options = ClientOptions::default().with_retries(10, Duration::from_millis(10), Duration::from_millis(50));
+        let mut producer = Producer::new(swappable_connector.clone(), options, topic_name);
+        assert_eq!(producer.publish("first").await.unwrap(), 0);
+
+        // connection of producer is closed on shutdown and there is no broker to reconnect to until it restarts
+        termination_signal_sender.send(()).unwrap();
+        broker.await.unwrap();
+        *swappable_connector.0.lock().await = None;
+
+        let restart = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let (connector, termination_signal_sender, broker) = spawn_broker(SimplePublisherConnectionHandler::new(registry)).await;
+            *swappable_connector.0.lock().await = Some(connector);
+            (termination_signal_sender, broker)
+        });
+
+        assert_eq!(producer.publish("second").await.unwrap(), 1);
+
+        let (termination_signal_sender, broker) = restart.await.unwrap();
+        termination_signal_sender.send(()).unwrap();
+        broker.await.unwrap();
+
+        // retries run out once broker is gone for good
+        let options = ClientOptions::default().with_retries(2, Duration::from_millis(1), Duration::from_millis(1));
+        let mut admin_client = AdminClient::new(SwappableConnector(Arc::new(Mutex::new(None))), options);
+        assert!(matches!(admin_client.ping().await, Err(ClientError::Connection(_))));
+    }
+
+    #[tokio::test]
+    async fn client_producer_retry_test_01() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let root_path = "./client_producer_retry_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = Arc::new(TopicRegistry::new(root_path));
+        let topic_name: TopicName = "orders".parse().unwrap();
+        registry.create_topic(crate::types::TopicMetaData::new_with_few_defaults(registry.topic(topic_name.clone()))).await.unwrap();
+        let (connector, termination_signal_sender, broker) = spawn_broker(SimplePublisherConnectionHandler::new(registry)).await;
+
+        // batch stays buffered when it couldn't be sent
+        let swappable_connector = SwappableConnector(Arc::new(Mutex::new(None)));
+        let options = ClientOptions::default().with_retries(0, Duration::from_millis(1), Duration::from_millis(1));
+        let mut producer = Producer::new(swappable_connector.clone(), options, topic_name.clone());
+        assert_eq!(producer.send("first").await.unwrap(), None);
+        assert!(matches!(producer.flush().await, Err(ClientError::Connection(_))));
+        assert_eq!(producer.buffered(), 1);
+        *swappable_connector.0.lock().await = Some(connector.clone());
+        assert_eq!(producer.flush().await.unwrap(), Some(0));
+        assert_eq!(producer.buffered(), 0);
+
+        // message written by an attempt whose response got lost isn't written twice
+        let mut client = BufReader::new(Connector::connect(&connector).await.unwrap());
+        client.write_all(b"@orders\n=7 0 second\n").await.unwrap();
+        let mut responses = String::new();
+        client.read_line(&mut responses).await.unwrap();
+        client.read_line(&mut responses).await.unwrap();
+        assert_eq!(responses, "+\n+1\n");
+
+        let mut producer = Producer::new(connector, ClientOptions::default(), topic_name).with_producer_id(7);
+        assert_eq!(producer.publish("second").await.unwrap(), 1);
+        assert_eq!(producer.send("third").await.unwrap(), None);
+        assert_eq!(producer.publish("fourth").await.unwrap(), 3);
+        assert_eq!(producer.buffered(), 0);
+
+        drop(client);
+        termination_signal_sender.send(()).unwrap();
+        broker.await.unwrap();
+    }
+}
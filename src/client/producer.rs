@@ -0,0 +1,197 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use super::{positive, Client, ClientError, ClientOptions, Connector, ErrorCode};
+use crate::sesp::{Command, MAX_BATCH_LEN};
+use crate::types::{Message, TopicName};
+
+/// bytes of a `PublishBatch` frame other than its messages, i.e. `*<N>\n`
+const BATCH_HEADER_LEN: usize = 6;
+
+/// bytes taken by `msg` in a frame, including its line terminator
+fn encoded_len(msg: &Message) -> usize {
+    msg.value().strip_suffix(b"\n").unwrap_or(msg.value()).len() + 1
+}
+
+/// client of publisher address, publishes to a single topic.
+///
+/// publishes are not retried once sent, unless server answered `ShuttingDown`,
+/// as a lost response doesn't tell whether messages were written. producers
+/// given a producer id publish with `PublishSequenced` instead, which is
+/// retried as server drops duplicates.
+pub struct Producer {
+    client: Client,
+    max_batch_len: usize,
+    batch: Vec<Message>,
+    batch_bytes: usize,
+    producer_id: Option<u64>,
+    /// sequence of first buffered message of an idempotent producer
+    sequence: u64,
+}
+
+impl Producer {
+    /// connects lazily, on first publish
+    pub fn new(connector: impl Connector, options: ClientOptions, topic_name: TopicName) -> Self {
+        Self {
+            client: Client::new(Arc::new(connector), options, vec![Command::SelectTopic(topic_name)]),
+            max_batch_len: MAX_BATCH_LEN,
+            batch: vec![],
+            batch_bytes: 0,
+            producer_id: None,
+            sequence: 0,
+        }
+    }
+
+    /// makes `publish`, `send` and `flush` idempotent, their messages are
+    /// published one at a time with `PublishSequenced` and retried even once
+    /// sent. `producer_id` must not be used by any other producer, including
+    /// one of an earlier run, e.g. pick it at random. `publish_batch` stays
+    /// atomic and is not made idempotent.
+    pub fn with_producer_id(mut self, producer_id: u64) -> Self {
+        self.producer_id = Some(producer_id);
+        self
+    }
+
+    /// number of messages `send` buffers before publishing them as a batch, capped at `MAX_BATCH_LEN`
+    pub fn with_max_batch_len(mut self, max_batch_len: usize) -> Self {
+        self.max_batch_len = max_batch_len.clamp(1, MAX_BATCH_LEN);
+        self
+    }
+
+    /// `value` is sent with a line terminator appended unless it already ends with one
+    fn message(&self, value: Bytes) -> Result<Message, ClientError> {
+        let line = value.strip_suffix(b"\n").unwrap_or(&value);
+        if line.contains(&b'\n') {
+            return Err(ClientError::InvalidArgument("message can't contain a line terminator".to_owned()));
+        }
+        if line.len() > self.max_message_len() {
+            return Err(ClientError::InvalidArgument(format!("message exceeds max frame size of {} bytes", self.client.options.max_frame_size)));
+        }
+
+        Ok(Message::from(value))
+    }
+
+    fn parse_offset(data: String) -> Result<usize, ClientError> {
+        data.parse().map_err(|_| ClientError::Protocol(format!("expected offset, got `{data}`")))
+    }
+
+    /// publishes a single message right away, returns its offset. an idempotent
+    /// producer publishes buffered messages first, and keeps `value` buffered
+    /// if publishing it fails so that next `publish` or `flush` retries it.
+    pub async fn publish(&mut self, value: impl Into<Bytes>) -> Result<usize, ClientError> {
+        let msg = self.message(value.into())?;
+
+        if self.producer_id.is_some() {
+            self.flush().await?;
+            self.batch_bytes += encoded_len(&msg);
+            self.batch.push(msg);
+            return self.flush().await.map(|offset| offset.expect("a message was buffered"));
+        }
+
+        Self::parse_offset(positive(self.client.request(Command::PublishMessage(msg), false).await?)?)
+    }
+
+    /// atomically publishes upto `MAX_BATCH_LEN` messages right away, returns offset of first one
+    pub async fn publish_batch(&mut self, values: Vec<Bytes>) -> Result<usize, ClientError> {
+        if !(1..=MAX_BATCH_LEN).contains(&values.len()) {
+            return Err(ClientError::InvalidArgument(format!("expected 1 to {MAX_BATCH_LEN} messages")));
+        }
+        let msgs = values.into_iter().map(|value| self.message(value)).collect::<Result<Vec<_>, _>>()?;
+
+        Self::parse_offset(positive(self.client.request(Command::PublishBatch(msgs), false).await?)?)
+    }
+
+    /// buffers a message, publishing buffered messages as one batch once there
+    /// are `max_batch_len` of them or once the next one wouldn't fit in a frame.
+    /// returns offset of first message of the batch published, if any.
+    pub async fn send(&mut self, value: impl Into<Bytes>) -> Result<Option<usize>, ClientError> {
+        let msg = self.message(value.into())?;
+        let len = encoded_len(&msg);
+
+        let mut base_offset = None;
+        if BATCH_HEADER_LEN + self.batch_bytes + len > self.client.options.max_frame_size {
+            base_offset = self.flush().await?;
+        }
+
+        self.batch.push(msg);
+        self.batch_bytes += len;
+
+        if self.batch.len() >= self.max_batch_len {
+            base_offset = self.flush().await?;
+        }
+
+        Ok(base_offset)
+    }
+
+    /// publishes buffered messages, returns offset of first one, `None` if there were none.
+    ///
+    /// buffered messages stay buffered if publishing fails, unless connection
+    /// was lost after they were sent as it's unknown then whether they were
+    /// written. an idempotent producer keeps every message not yet written.
+    pub async fn flush(&mut self) -> Result<Option<usize>, ClientError> {
+        if self.batch.is_empty() {
+            return Ok(None);
+        }
+
+        match self.producer_id {
+            Some(producer_id) => self.flush_sequenced(producer_id).await,
+            None => self.flush_batch().await,
+        }
+    }
+
+    async fn flush_batch(&mut self) -> Result<Option<usize>, ClientError> {
+        let (sent, result) = self.client.request_with_sent(Command::PublishBatch(self.batch.clone()), false).await;
+
+        // batch is atomic, a negative response means none of it was written
+        let result = result.and_then(positive);
+        if result.is_ok() || result.as_ref().is_err_and(|e| sent && e.code().is_none()) {
+            self.batch.clear();
+            self.batch_bytes = 0;
+        }
+
+        Self::parse_offset(result?).map(Some)
+    }
+
+    async fn flush_sequenced(&mut self, producer_id: u64) -> Result<Option<usize>, ClientError> {
+        let mut base_offset = None;
+        let mut written = 0;
+        let mut result = Ok(());
+
+        for msg in self.batch.clone() {
+            let command = Command::PublishSequenced(producer_id, self.sequence + written as u64, msg);
+            let offset = match self.client.request(command, true).await.and_then(positive) {
+                Ok(data) => Self::parse_offset(data),
+                // written by an earlier attempt whose response got lost
+                Err(e) if e.code() == Some(&ErrorCode::DuplicateSequence) => Self::parse_offset(e.argument().unwrap_or_default().to_owned()),
+                Err(e) => Err(e),
+            };
+
+            match offset {
+                Ok(offset) => {
+                    base_offset.get_or_insert(offset);
+                    written += 1;
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        self.batch_bytes -= self.batch.drain(..written).map(|msg| encoded_len(&msg)).sum::<usize>();
+        self.sequence += written as u64;
+
+        result.map(|_| base_offset)
+    }
+
+    /// longest message accepted, not counting its line terminator
+    pub fn max_message_len(&self) -> usize {
+        self.client.options.max_frame_size.saturating_sub(BATCH_HEADER_LEN + 1)
+    }
+
+    /// messages buffered by `send` and not yet published
+    pub fn buffered(&self) -> usize {
+        self.batch.len()
+    }
+}
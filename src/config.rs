@@ -0,0 +1,254 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::acl::AclConfig;
+use crate::broker::DEFAULT_SHUTDOWN_TIMEOUT;
+use crate::error::Error;
+use crate::quota::QuotaConfig;
+use crate::sesp::DEFAULT_MAX_FRAME_SIZE;
+
+/// configuration of a stream-relay server, read from a TOML file.
+///
+/// every key is optional, missing keys take their default value.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// directory holding every topic
+    root_path: PathBuf,
+    admin_addr: String,
+    publisher_addr: String,
+    subscriber_addr: String,
+    /// serves REST gateway (see `HttpGateway`) on this address when set
+    http_addr: Option<String>,
+    /// serves Prometheus metrics at `http://<metrics_addr>/metrics` when set
+    metrics_addr: Option<String>,
+    /// max size in bytes of a single SESP frame
+    max_frame_size: usize,
+    /// connections sending no command for this long are closed, 0 (default) disables it
+    idle_timeout_ms: u64,
+    /// max time to wait for open connections to finish while shutting down
+    shutdown_timeout_ms: u64,
+    /// max number of open connections per listener, unlimited when not set
+    max_connections: Option<usize>,
+    /// max number of open connections per listener from a single ip,
+    /// unlimited when not set
+    max_connections_per_ip: Option<usize>,
+    /// serve every listener over TLS when set
+    tls: Option<TlsConfig>,
+    /// clients must authenticate against users and tokens of this file when set
+    credentials_path: Option<PathBuf>,
+    /// every topic operation must be allowed by an ACL rule when set
+    acl: Option<AclConfig>,
+    /// publishers and subscribers exceeding these limits are throttled when set
+    quotas: Option<QuotaConfig>,
+    /// filter directives of server's logs (e.g. `info,stream_relay::topic=debug`),
+    /// `RUST_LOG` environment variable takes precedence when set
+    log_level: String,
+    log_format: LogFormat,
+}
+
+/// how server's logs are printed to stderr
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// human readable, multi line events
+    #[default]
+    Pretty,
+    /// one JSON object per event, carrying fields of every enclosing span
+    Json,
+}
+
+/// TLS settings shared by every listener of a server
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file holding server's certificate chain, leaf certificate first
+    cert_path: PathBuf,
+    /// PEM file holding server's private key
+    key_path: PathBuf,
+    /// PEM file holding CA certificates client certificates must chain up to,
+    /// clients must present a certificate when set (mutual TLS)
+    client_ca_path: Option<PathBuf>,
+}
+
+impl ServerConfig {
+    pub async fn load<T: AsRef<Path>>(path: T) -> Result<Self, Error> {
+        let config = tokio::fs::read_to_string(path).await?;
+
+        Ok(toml::from_str(&config)?)
+    }
+
+    pub fn root_path(&self) -> &Path {
+        &self.root_path
+    }
+
+    pub fn admin_addr(&self) -> &str {
+        &self.admin_addr
+    }
+
+    pub fn publisher_addr(&self) -> &str {
+        &self.publisher_addr
+    }
+
+    pub fn subscriber_addr(&self) -> &str {
+        &self.subscriber_addr
+    }
+
+    pub fn http_addr(&self) -> Option<&str> {
+        self.http_addr.as_deref()
+    }
+
+    pub fn metrics_addr(&self) -> Option<&str> {
+        self.metrics_addr.as_deref()
+    }
+
+    pub fn max_frame_size(&self) -> &usize {
+        &self.max_frame_size
+    }
+
+    /// `None` if idle connections are never closed
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        (self.idle_timeout_ms > 0).then(|| Duration::from_millis(self.idle_timeout_ms))
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_millis(self.shutdown_timeout_ms)
+    }
+
+    pub fn max_connections(&self) -> Option<usize> {
+        self.max_connections
+    }
+
+    pub fn max_connections_per_ip(&self) -> Option<usize> {
+        self.max_connections_per_ip
+    }
+
+    pub fn tls(&self) -> &Option<TlsConfig> {
+        &self.tls
+    }
+
+    pub fn credentials_path(&self) -> Option<&Path> {
+        self.credentials_path.as_deref()
+    }
+
+    pub fn acl(&self) -> &Option<AclConfig> {
+        &self.acl
+    }
+
+    pub fn quotas(&self) -> &Option<QuotaConfig> {
+        &self.quotas
+    }
+
+    pub fn log_level(&self) -> &str {
+        &self.log_level
+    }
+
+    pub fn log_format(&self) -> LogFormat {
+        self.log_format
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            root_path: PathBuf::from("./data"),
+            admin_addr: "127.0.0.1:7070".to_owned(),
+            publisher_addr: "127.0.0.1:7071".to_owned(),
+            subscriber_addr: "127.0.0.1:7072".to_owned(),
+            http_addr: None,
+            metrics_addr: None,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            idle_timeout_ms: 0,
+            shutdown_timeout_ms: DEFAULT_SHUTDOWN_TIMEOUT.as_millis() as u64,
+            max_connections: None,
+            max_connections_per_ip: None,
+            tls: None,
+            credentials_path: None,
+            acl: None,
+            quotas: None,
+            log_level: "info".to_owned(),
+            log_format: LogFormat::default(),
+        }
+    }
+}
+
+impl TlsConfig {
+    pub fn new(cert_path: PathBuf, key_path: PathBuf, client_ca_path: Option<PathBuf>) -> Self {
+        Self {
+            cert_path,
+            key_path,
+            client_ca_path,
+        }
+    }
+
+    pub fn cert_path(&self) -> &Path {
+        &self.cert_path
+    }
+
+    pub fn key_path(&self) -> &Path {
+        &self.key_path
+    }
+
+    pub fn client_ca_path(&self) -> Option<&Path> {
+        self.client_ca_path.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_config_deserialize_test_01() {
+        let config: ServerConfig = toml::from_str(
+            r#"
+            root_path = "/var/lib/stream-relay"
+            publisher_addr = "0.0.0.0:7071"
+            idle_timeout_ms = 600000
+            metrics_addr = "127.0.0.1:9090"
+            http_addr = "127.0.0.1:8080"
+            max_connections_per_ip = 16
+            log_format = "json"
+
+            [tls]
+            cert_path = "server.pem"
+            key_path = "server.key"
+            client_ca_path = "ca.pem"
+
+            [acl]
+            super_users = ["CN=admin"]
+
+            [[acl.rules]]
+            principal = "*"
+            operation = "subscribe"
+            topic = "public.*"
+
+            [quotas.topics."public.news"]
+            publish_msgs_per_sec = 100
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.root_path(), Path::new("/var/lib/stream-relay"));
+        assert_eq!(config.publisher_addr(), "0.0.0.0:7071");
+        assert_eq!(config.admin_addr(), ServerConfig::default().admin_addr());
+        assert_eq!(config.idle_timeout(), Some(Duration::from_secs(600)));
+        assert_eq!(config.metrics_addr(), Some("127.0.0.1:9090"));
+        assert_eq!(ServerConfig::default().metrics_addr(), None);
+        assert_eq!((config.http_addr(), ServerConfig::default().http_addr()), (Some("127.0.0.1:8080"), None));
+        assert_eq!(ServerConfig::default().idle_timeout(), None);
+        assert_eq!((config.max_connections(), config.max_connections_per_ip()), (None, Some(16)));
+        assert_eq!((config.log_level(), config.log_format()), ("info", LogFormat::Json));
+        assert_eq!(ServerConfig::default().log_format(), LogFormat::Pretty);
+        assert_eq!(config.tls().as_ref().unwrap().client_ca_path(), Some(Path::new("ca.pem")));
+
+        assert_eq!(config.acl().as_ref().unwrap().super_users(), &["CN=admin".to_owned()]);
+        assert_eq!(config.acl().as_ref().unwrap().rules().len(), 1);
+        assert!(ServerConfig::default().acl().is_none());
+        assert_eq!(config.quotas().as_ref().unwrap().topics()[&"public.news".parse().unwrap()].publish_msgs_per_sec(), Some(100));
+
+        assert!(toml::from_str::<ServerConfig>("no_such_key = 1").is_err());
+    }
+}
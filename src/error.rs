@@ -0,0 +1,160 @@
+use std::fmt;
+
+use crate::types::{TopicConfigError, TopicName, TopicNameError};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// crate wide error type, every variant maps to a stable SESP error code
+/// (see `Error::code`) that is sent as the first word of negative responses.
+#[derive(Debug)]
+pub enum Error {
+    /// reading from or writing to disk failed
+    Storage(std::io::Error),
+    /// peer sent a malformed command or a command not valid in current state
+    Protocol(String),
+    /// referenced topic doesn't exist
+    NotFound(TopicName),
+    /// topic being created already exists
+    AlreadyExists(TopicName),
+    InvalidArgument(String),
+    InvalidTopicName(TopicNameError),
+    InvalidTopicConfig(TopicConfigError),
+    /// command is valid but not yet supported by the server
+    NotSupported,
+    /// client asked for a SESP version older than the oldest one server speaks
+    UnsupportedVersion(u32),
+    /// producer's message with this sequence was already written at `offset`
+    DuplicateSequence { sequence: u64, offset: usize },
+    /// producer skipped a sequence or retried one too old to be deduplicated
+    OutOfOrderSequence { sequence: u64, expected: u64 },
+    /// peer sent a frame longer than max frame size (in bytes) of connection
+    FrameTooLarge(usize),
+    /// command was sent before connection authenticated
+    NotAuthenticated,
+    /// credentials were rejected, or too many attempts failed recently
+    AuthenticationFailed(String),
+    /// principal of connection isn't allowed to perform the operation
+    NotAuthorized(String),
+    /// server stopped serving commands of this connection and is about to close it
+    ShuttingDown,
+    /// connection was refused as server or peer's ip has too many open connections
+    TooManyConnections,
+    /// reading from or writing to peer's connection failed
+    Connection(std::io::Error),
+    /// server's configuration (e.g. a TLS certificate) is unusable, never sent to clients
+    Config(String),
+}
+
+impl Error {
+    /// stable SESP error code, these never change once released
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Storage(_) => "IOError",
+            Self::Protocol(_) => "ProtocolError",
+            Self::NotFound(_) => "NoSuchTopicExists",
+            Self::AlreadyExists(_) => "AlreadyExists",
+            Self::InvalidArgument(_) => "InvalidArgument",
+            Self::InvalidTopicName(_) => "InvalidTopicName",
+            Self::InvalidTopicConfig(TopicConfigError::NotSupported { .. }) => "NotSupported",
+            Self::InvalidTopicConfig(_) => "InvalidTopicConfig",
+            Self::NotSupported => "NotSupported",
+            Self::UnsupportedVersion(_) => "UnsupportedVersion",
+            Self::DuplicateSequence { .. } => "DuplicateSequence",
+            Self::OutOfOrderSequence { .. } => "OutOfOrderSequence",
+            Self::FrameTooLarge(_) => "FrameTooLarge",
+            Self::NotAuthenticated => "NotAuthenticated",
+            Self::AuthenticationFailed(_) => "AuthenticationFailed",
+            Self::NotAuthorized(_) => "NotAuthorized",
+            Self::ShuttingDown => "ShuttingDown",
+            Self::TooManyConnections => "TooManyConnections",
+            Self::Connection(_) => "ConnectionError",
+            Self::Config(_) => "ConfigError",
+        }
+    }
+
+    /// machine readable argument of some codes, sent as `<Code>:<argument>`.
+    /// unlike description its format never changes once released.
+    pub fn argument(&self) -> Option<String> {
+        match self {
+            Self::DuplicateSequence { offset, .. } => Some(offset.to_string()),
+            Self::OutOfOrderSequence { expected, .. } => Some(expected.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Storage(e) => write!(f, "storage error: {e}"),
+            Self::Protocol(reason) => write!(f, "protocol error: {reason}"),
+            Self::NotFound(topic) => write!(f, "topic `{topic}` doesn't exist"),
+            Self::AlreadyExists(topic) => write!(f, "topic `{topic}` already exists"),
+            Self::InvalidArgument(reason) => write!(f, "invalid argument: {reason}"),
+            Self::InvalidTopicName(e) => write!(f, "{e}"),
+            Self::InvalidTopicConfig(e) => write!(f, "{e}"),
+            Self::NotSupported => write!(f, "not supported"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "protocol version {version} is not supported, server speaks versions {} to {}",
+                crate::sesp::MIN_PROTOCOL_VERSION,
+                crate::sesp::PROTOCOL_VERSION,
+            ),
+            Self::DuplicateSequence { sequence, offset } => {
+                write!(f, "sequence {sequence} was already written at offset {offset}")
+            }
+            Self::OutOfOrderSequence { sequence, expected } => {
+                write!(f, "got sequence {sequence} while expecting {expected}")
+            }
+            Self::FrameTooLarge(max_frame_size) => write!(f, "frame exceeds max size of {max_frame_size} bytes"),
+            Self::NotAuthenticated => write!(f, "authentication required"),
+            Self::AuthenticationFailed(reason) => write!(f, "authentication failed: {reason}"),
+            Self::NotAuthorized(reason) => write!(f, "not authorized: {reason}"),
+            Self::ShuttingDown => write!(f, "server is shutting down"),
+            Self::TooManyConnections => write!(f, "too many open connections, retry later"),
+            Self::Connection(e) => write!(f, "connection error: {e}"),
+            Self::Config(reason) => write!(f, "invalid config: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Storage(e) | Self::Connection(e) => Some(e),
+            Self::InvalidTopicName(e) => Some(e),
+            Self::InvalidTopicConfig(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Storage(value)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(value: toml::de::Error) -> Self {
+        Self::Storage(std::io::Error::new(std::io::ErrorKind::InvalidData, value))
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(value: toml::ser::Error) -> Self {
+        Self::Storage(std::io::Error::new(std::io::ErrorKind::InvalidData, value))
+    }
+}
+
+impl From<TopicNameError> for Error {
+    fn from(value: TopicNameError) -> Self {
+        Self::InvalidTopicName(value)
+    }
+}
+
+impl From<TopicConfigError> for Error {
+    fn from(value: TopicConfigError) -> Self {
+        Self::InvalidTopicConfig(value)
+    }
+}
@@ -0,0 +1,376 @@
+mod rest;
+mod ws;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tracing::{debug, error};
+
+use crate::acl::{AclOperation, AclStore};
+use crate::broker::{Authenticator, PeerAddr};
+use crate::error::Error;
+use crate::metrics::Metrics;
+use crate::quota::QuotaManager;
+use crate::sesp::Credentials;
+use crate::topic::TopicRegistry;
+use crate::types::{TopicConfigError, TopicName};
+
+/// serves publish, fetch and topic management over HTTP for clients that
+/// can't speak SESP, on top of the same registry as SESP handlers
+pub struct HttpGateway {
+    registry: Arc<TopicRegistry>,
+    authenticator: Option<Arc<Authenticator>>,
+    metrics: Arc<Metrics>,
+    acl_store: Option<Arc<AclStore>>,
+    quota_manager: Option<Arc<QuotaManager>>,
+    /// closes WebSocket subscriptions, which outlive the requests they were upgraded from
+    termination_signal_sender: broadcast::Sender<()>,
+}
+
+/// body of every non 2xx response
+#[derive(Serialize)]
+struct ErrorBody {
+    /// same as SESP error code, e.g. `NoSuchTopicExists`
+    code: &'static str,
+    message: String,
+}
+
+impl HttpGateway {
+    pub fn new(registry: Arc<TopicRegistry>) -> Self {
+        Self {
+            registry,
+            authenticator: None,
+            metrics: Arc::new(Metrics::new()),
+            acl_store: None,
+            quota_manager: None,
+            termination_signal_sender: broadcast::channel(1).0,
+        }
+    }
+
+    /// requires every request to carry an `Authorization: Bearer <token>` header
+    pub fn with_authenticator(mut self, authenticator: Arc<Authenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    /// records into `metrics` instead of a private instance
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// denies every topic operation not allowed by `acl_store`
+    pub fn with_acl_store(mut self, acl_store: Arc<AclStore>) -> Self {
+        self.acl_store = Some(acl_store);
+        self
+    }
+
+    /// delays responses to clients exceeding their quota or quota of the topic
+    pub fn with_quota_manager(mut self, quota_manager: Arc<QuotaManager>) -> Self {
+        self.quota_manager = Some(quota_manager);
+        self
+    }
+
+    /// principal of request, `None` while authentication is disabled
+    async fn authenticate(&self, headers: &HeaderMap, peer_addr: SocketAddr) -> Result<Option<String>, Error> {
+        self.authenticate_token(bearer_token(headers), peer_addr).await
+    }
+
+    async fn authenticate_token(&self, token: Option<&str>, peer_addr: SocketAddr) -> Result<Option<String>, Error> {
+        let authenticator = match self.authenticator {
+            Some(ref authenticator) => authenticator,
+            None => return Ok(None),
+        };
+
+        let token = token.ok_or(Error::NotAuthenticated)?;
+        let principal = authenticator.authenticate(Credentials::Token(token.to_owned()), &PeerAddr::Tcp(peer_addr)).await?;
+
+        Ok(Some(principal))
+    }
+
+    async fn authorize(&self, principal: Option<&str>, operation: AclOperation, topic_name: &TopicName) -> Result<(), Error> {
+        match self.acl_store {
+            Some(ref acl_store) => acl_store.authorize(principal, operation, topic_name).await,
+            None => Ok(()),
+        }
+    }
+
+    /// serves requests until termination signal is received, requests being
+    /// handled by then are answered before returning
+    pub async fn serve(self, listener: TcpListener, mut termination_signal_recvr: broadcast::Receiver<()>) -> std::io::Result<()> {
+        let termination_signal_sender = self.termination_signal_sender.clone();
+        let app = rest::router().merge(ws::router()).with_state(Arc::new(self));
+
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+            .with_graceful_shutdown(async move {
+                let _ = termination_signal_recvr.recv().await; // ignore result
+                let _ = termination_signal_sender.send(()); // ignore result, there may be no subscription
+            })
+            .await
+    }
+}
+
+/// token of `Authorization: Bearer <token>` header
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+impl Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::AlreadyExists(_) => StatusCode::CONFLICT,
+            Self::NotAuthenticated | Self::AuthenticationFailed(_) => StatusCode::UNAUTHORIZED,
+            Self::NotAuthorized(_) => StatusCode::FORBIDDEN,
+            Self::NotSupported | Self::InvalidTopicConfig(TopicConfigError::NotSupported { .. }) => StatusCode::NOT_IMPLEMENTED,
+            Self::ShuttingDown | Self::TooManyConnections => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Storage(_) | Self::Connection(_) | Self::Config(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let status_code = self.status_code();
+        match status_code == StatusCode::INTERNAL_SERVER_ERROR {
+            true => error!(error = %self, "request failed"),
+            false => debug!(code = self.code(), error = %self, "request failed"),
+        }
+
+        let body = ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+        };
+
+        (status_code, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::{AclConfig, AclRule, TopicPattern};
+    use crate::broker::CredentialStore;
+    use crate::topic::TopicWriter;
+    use crate::types::Message;
+    use bytes::Bytes;
+    use futures::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// status code and body of response to a single HTTP/1.1 request
+    async fn request(addr: SocketAddr, method: &str, path: &str, token: Option<&str>, body: Option<&str>) -> (u16, String) {
+        let mut request = format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n");
+        if let Some(token) = token {
+            request.push_str(&format!("Authorization: Bearer {token}\r\n"));
+        }
+        if let Some(body) = body {
+            request.push_str(&format!("Content-Type: application/json\r\nContent-Length: {}\r\n", body.len()));
+        }
+        request.push_str("\r\n");
+        request.push_str(body.unwrap_or_default());
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let status_code = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_owned();
+
+        (status_code, body)
+    }
+
+    async fn spawn_gateway(gateway: HttpGateway) -> (SocketAddr, broadcast::Sender<()>, tokio::task::JoinHandle<std::io::Result<()>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (termination_signal_sender, termination_signal_recvr) = broadcast::channel(1);
+        let server = tokio::spawn(gateway.serve(listener, termination_signal_recvr));
+
+        (addr, termination_signal_sender, server)
+    }
+
+    #[tokio::test]
+    async fn http_gateway_test_01() {
+        let root_path = "./http_gateway_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = Arc::new(TopicRegistry::new(root_path));
+        let (addr, termination_signal_sender, server) = spawn_gateway(HttpGateway::new(registry.clone())).await;
+
+        let create = r#"{"name": "foo", "config": {"num_of_segments": 2}}"#;
+        assert_eq!(request(addr, "POST", "/topics", None, Some(create)).await.0, 201);
+        let (status_code, body) = request(addr, "POST", "/topics", None, Some(create)).await;
+        assert_eq!((status_code, body.as_str()), (409, r#"{"code":"AlreadyExists","message":"topic `foo` already exists"}"#));
+        let (status_code, body) = request(addr, "POST", "/topics", None, Some(r#"{"name": "foo/bar"}"#)).await;
+        assert!(status_code == 400 && body.contains("InvalidTopicName"));
+        assert_eq!(request(addr, "GET", "/topics", None, None).await, (200, r#"{"topics":["foo"]}"#.to_owned()));
+
+        let publish = r#"{"messages": ["hello", "world"]}"#;
+        assert_eq!(request(addr, "POST", "/topics/foo/messages", None, Some(publish)).await, (200, r#"{"base_offset":0}"#.to_owned()));
+        let (status_code, body) = request(addr, "POST", "/topics/foo/messages", None, Some(r#"{"messages": ["a\nb"]}"#)).await;
+        assert!(status_code == 400 && body.contains("line terminator"));
+        assert_eq!(request(addr, "POST", "/topics/bar/messages", None, Some(publish)).await.0, 404);
+
+        let (status_code, body) = request(addr, "GET", "/topics/foo/messages?offset=1&limit=10", None, None).await;
+        assert_eq!((status_code, body.as_str()), (200, r#"{"messages":[{"offset":1,"value":"world"}],"next_offset":2}"#));
+        let (_, body) = request(addr, "GET", "/topics/foo/messages?offset=2", None, None).await;
+        assert_eq!(body, r#"{"messages":[],"next_offset":2}"#);
+
+        // messages that aren't valid utf8 can only be fetched base64 encoded
+        let writer = registry.writer(&"foo".parse().unwrap()).await.unwrap();
+        writer.lock().await.write(Message::new(Bytes::from_static(b"\xff\n"), None)).await.unwrap();
+        writer.lock().await.flush_topic_metadata().await.unwrap();
+        let (status_code, body) = request(addr, "GET", "/topics/foo/messages?offset=1", None, None).await;
+        assert_eq!((status_code, body.as_str()), (200, r#"{"messages":[{"offset":1,"value":"world"}],"next_offset":2}"#));
+        let (status_code, body) = request(addr, "GET", "/topics/foo/messages?offset=2", None, None).await;
+        assert!(status_code == 400 && body.contains("not valid utf8"));
+        let (status_code, body) = request(addr, "GET", "/topics/foo/messages?offset=1&encoding=base64", None, None).await;
+        assert_eq!((status_code, body.as_str()), (200, r#"{"messages":[{"offset":1,"value":"d29ybGQ="},{"offset":2,"value":"/w=="}],"next_offset":3}"#));
+
+        assert_eq!(request(addr, "DELETE", "/topics/foo", None, None).await.0, 204);
+        assert_eq!(request(addr, "DELETE", "/topics/foo", None, None).await.0, 404);
+        assert_eq!(request(addr, "GET", "/topics", None, None).await.1, r#"{"topics":[]}"#);
+
+        termination_signal_sender.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn http_gateway_auth_test_01() {
+        let root_path = "./http_gateway_auth_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = Arc::new(TopicRegistry::new(root_path));
+        for topic_name in ["orders", "payments"] {
+            let topic_metadata = crate::types::TopicMetaData::new_with_few_defaults(registry.topic(topic_name.parse().unwrap()));
+            registry.create_topic(topic_metadata).await.unwrap();
+        }
+
+        let mut credential_store = CredentialStore::default();
+        credential_store.add_token("alice".to_owned(), "alice-token");
+        let rule = AclRule::new("alice".to_owned(), AclOperation::Publish, TopicPattern::try_from("orders".to_owned()).unwrap());
+        let acl_store = AclStore::new(AclConfig::new(vec![], vec![rule]));
+        let gateway = HttpGateway::new(registry)
+            .with_authenticator(Arc::new(Authenticator::new(credential_store)))
+            .with_acl_store(Arc::new(acl_store));
+        let (addr, termination_signal_sender, server) = spawn_gateway(gateway).await;
+
+        let publish = r#"{"messages": ["hello"]}"#;
+        let (status_code, body) = request(addr, "POST", "/topics/orders/messages", None, Some(publish)).await;
+        assert!(status_code == 401 && body.contains("NotAuthenticated"));
+        assert_eq!(request(addr, "POST", "/topics/orders/messages", Some("wrong"), Some(publish)).await.0, 401);
+
+        let token = Some("alice-token");
+        assert_eq!(request(addr, "POST", "/topics/orders/messages", token, Some(publish)).await.0, 200);
+        assert_eq!(request(addr, "POST", "/topics/payments/messages", token, Some(publish)).await.0, 403);
+        assert_eq!(request(addr, "GET", "/topics/orders/messages", token, None).await.0, 403);
+        assert_eq!(request(addr, "DELETE", "/topics/orders", token, None).await.0, 403);
+        assert_eq!(request(addr, "GET", "/topics", token, None).await.1, r#"{"topics":["orders"]}"#);
+
+        termination_signal_sender.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn websocket_gateway_test_01() {
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+        let root_path = "./websocket_gateway_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = Arc::new(TopicRegistry::new(root_path));
+        let topic_name: TopicName = "foo".parse().unwrap();
+        registry.create_topic(crate::types::TopicMetaData::new_with_few_defaults(registry.topic(topic_name.clone()))).await.unwrap();
+
+        let writer = registry.writer(&topic_name).await.unwrap();
+        let publish = |value: &'static [u8]| {
+            let writer = writer.clone();
+            async move {
+                let mut writer = writer.lock().await;
+                writer.write(Message::new(Bytes::from_static(value), None)).await.unwrap();
+                writer.flush_topic_metadata().await.unwrap();
+            }
+        };
+        publish(b"first\n").await;
+        publish(b"second\n").await;
+
+        let (addr, termination_signal_sender, server) = spawn_gateway(HttpGateway::new(registry.clone())).await;
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/topics/foo/subscribe?offset=1")).await.unwrap();
+        assert_eq!(socket.next().await.unwrap().unwrap(), WsMessage::text(r#"{"offset":1,"value":"second"}"#));
+        let url = format!("ws://{addr}/topics/foo/subscribe?offset=2&encoding=base64");
+        let (mut base64_socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        // pushed once flushed, without asking
+        publish(b"third\n").await;
+        assert_eq!(socket.next().await.unwrap().unwrap(), WsMessage::text(r#"{"offset":2,"value":"third"}"#));
+        assert_eq!(base64_socket.next().await.unwrap().unwrap(), WsMessage::text(r#"{"offset":2,"value":"dGhpcmQ="}"#));
+
+        // a message that isn't valid utf8 can only be pushed base64 encoded
+        let close_reason = |msg| match msg {
+            WsMessage::Close(Some(close_frame)) => (u16::from(close_frame.code), close_frame.reason.into_owned()),
+            msg => panic!("expected close frame, got {msg:?}"),
+        };
+        publish(b"\xff\n").await;
+        assert_eq!(base64_socket.next().await.unwrap().unwrap(), WsMessage::text(r#"{"offset":3,"value":"/w=="}"#));
+        assert_eq!(close_reason(socket.next().await.unwrap().unwrap()), (1011, "InvalidArgument".to_owned()));
+
+        registry.delete_topic(&topic_name).await.unwrap();
+        assert_eq!(close_reason(base64_socket.next().await.unwrap().unwrap()), (1011, "NoSuchTopicExists".to_owned()));
+
+        let error = tokio_tungstenite::connect_async(format!("ws://{addr}/topics/bar/subscribe")).await.unwrap_err();
+        assert!(matches!(error, tokio_tungstenite::tungstenite::Error::Http(response) if response.status() == 404));
+
+        registry.create_topic(crate::types::TopicMetaData::new_with_few_defaults(registry.topic(topic_name.clone()))).await.unwrap();
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/topics/foo/subscribe")).await.unwrap();
+        termination_signal_sender.send(()).unwrap();
+        match socket.next().await.unwrap().unwrap() {
+            WsMessage::Close(Some(close_frame)) => assert_eq!(u16::from(close_frame.code), 1001),
+            msg => panic!("expected close frame, got {msg:?}"),
+        }
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn websocket_gateway_auth_test_01() {
+        let root_path = "./websocket_gateway_auth_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = Arc::new(TopicRegistry::new(root_path));
+        for topic_name in ["orders", "payments"] {
+            let topic_metadata = crate::types::TopicMetaData::new_with_few_defaults(registry.topic(topic_name.parse().unwrap()));
+            registry.create_topic(topic_metadata).await.unwrap();
+        }
+
+        let mut credential_store = CredentialStore::default();
+        credential_store.add_token("alice".to_owned(), "alice-token");
+        let rule = AclRule::new("alice".to_owned(), AclOperation::Subscribe, TopicPattern::try_from("orders".to_owned()).unwrap());
+        let gateway = HttpGateway::new(registry)
+            .with_authenticator(Arc::new(Authenticator::new(credential_store)))
+            .with_acl_store(Arc::new(AclStore::new(AclConfig::new(vec![], vec![rule]))));
+        let (addr, termination_signal_sender, server) = spawn_gateway(gateway).await;
+
+        let status_code = |url: String| async move {
+            match tokio_tungstenite::connect_async(url).await {
+                Ok((_, response)) => response.status().as_u16(),
+                Err(tokio_tungstenite::tungstenite::Error::Http(response)) => response.status().as_u16(),
+                Err(e) => panic!("{e}"),
+            }
+        };
+        assert_eq!(status_code(format!("ws://{addr}/topics/orders/subscribe")).await, 401);
+        assert_eq!(status_code(format!("ws://{addr}/topics/payments/subscribe?access_token=alice-token")).await, 403);
+        assert_eq!(status_code(format!("ws://{addr}/topics/orders/subscribe?access_token=alice-token")).await, 101);
+
+        termination_signal_sender.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+use base64::Engine;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use super::HttpGateway;
+use crate::acl::AclOperation;
+use crate::error::Error;
+use crate::sesp::{DEFAULT_MAX_FRAME_SIZE, MAX_BATCH_LEN};
+use crate::topic::{IsolationLevel, TopicReader, TopicWriter};
+use crate::types::{Message, TopicMetaData, TopicName};
+
+/// number of messages returned by a fetch not asking for a `limit`
+const DEFAULT_FETCH_LIMIT: usize = 100;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CreateTopicRequest {
+    name: String,
+    /// same options as `CreateTopic`, e.g. `{"num_of_segments": 4}`
+    #[serde(default)]
+    config: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct TopicsResponse {
+    topics: Vec<TopicName>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PublishRequest {
+    /// published atomically as one batch, none of them may contain a line terminator
+    messages: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct PublishResponse {
+    /// offset of first message, rest follow consecutively
+    base_offset: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FetchQuery {
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+    max_bytes: Option<usize>,
+    #[serde(default)]
+    isolation_level: IsolationLevel,
+    #[serde(default)]
+    encoding: ValueEncoding,
+}
+
+/// how values of fetched messages are represented in JSON
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum ValueEncoding {
+    /// as is, messages that aren't valid utf8 can't be fetched
+    #[default]
+    Utf8,
+    /// standard base64 with padding
+    Base64,
+}
+
+#[derive(Serialize)]
+pub(super) struct FetchedMessage {
+    offset: usize,
+    /// without trailing line terminator
+    value: String,
+}
+
+#[derive(Serialize)]
+struct FetchResponse {
+    messages: Vec<FetchedMessage>,
+    /// offset to fetch from next, past the last returned message
+    next_offset: usize,
+}
+
+pub(super) fn router() -> Router<Arc<HttpGateway>> {
+    Router::new()
+        .route("/topics", get(list_topics).post(create_topic))
+        .route("/topics/:name", delete(delete_topic))
+        .route("/topics/:name/messages", get(fetch_messages).post(publish_messages))
+}
+
+impl FetchedMessage {
+    pub(super) fn new(msg: &Message, encoding: ValueEncoding) -> Result<Self, Error> {
+        let offset = msg.offset().copied().unwrap_or_default();
+        let value = msg.value().strip_suffix(b"\n").unwrap_or(msg.value());
+
+        let value = match encoding {
+            ValueEncoding::Utf8 => std::str::from_utf8(value)
+                .map_err(|_| Error::InvalidArgument(format!("message at offset {offset} is not valid utf8, fetch it with `encoding=base64`")))?
+                .to_owned(),
+            ValueEncoding::Base64 => base64::engine::general_purpose::STANDARD.encode(value),
+        };
+
+        Ok(Self { offset, value })
+    }
+}
+
+/// encodes `msgs` upto the first one that can't be, which is dropped along
+/// with every message after it. fails only if that is the very first one.
+pub(super) fn encode_messages(msgs: &mut Vec<Message>, encoding: ValueEncoding) -> Result<Vec<FetchedMessage>, Error> {
+    let mut fetched = Vec::with_capacity(msgs.len());
+    for msg in msgs.iter() {
+        match FetchedMessage::new(msg, encoding) {
+            Ok(message) => fetched.push(message),
+            Err(e) if fetched.is_empty() => return Err(e),
+            Err(_) => break,
+        }
+    }
+    msgs.truncate(fetched.len());
+
+    Ok(fetched)
+}
+
+pub(super) fn parse_topic_name(name: String) -> Result<TopicName, Error> {
+    TopicName::try_from(name).map_err(Error::InvalidTopicName)
+}
+
+/// topics principal of request may either publish to or subscribe to
+async fn list_topics(
+    State(gateway): State<Arc<HttpGateway>>,
+    ConnectInfo(peer_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Json<TopicsResponse>, Error> {
+    let principal = gateway.authenticate(&headers, peer_addr).await?;
+
+    let mut topics = vec![];
+    for topic_name in gateway.registry.topics().await? {
+        let can_publish = gateway.authorize(principal.as_deref(), AclOperation::Publish, &topic_name).await.is_ok();
+        if can_publish || gateway.authorize(principal.as_deref(), AclOperation::Subscribe, &topic_name).await.is_ok() {
+            topics.push(topic_name);
+        }
+    }
+
+    Ok(Json(TopicsResponse { topics }))
+}
+
+async fn create_topic(
+    State(gateway): State<Arc<HttpGateway>>,
+    ConnectInfo(peer_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<CreateTopicRequest>,
+) -> Result<StatusCode, Error> {
+    let principal = gateway.authenticate(&headers, peer_addr).await?;
+    let topic_name = parse_topic_name(request.name)?;
+    gateway.authorize(principal.as_deref(), AclOperation::Create, &topic_name).await?;
+
+    let mut topic_metadata = TopicMetaData::new_with_few_defaults(gateway.registry.topic(topic_name));
+    for (key, value) in request.config.iter() {
+        let value = match value {
+            serde_json::Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        topic_metadata.set_config_option(key, &value, false)?;
+    }
+
+    gateway.registry.create_topic(topic_metadata).await?;
+
+    Ok(StatusCode::CREATED)
+}
+
+async fn delete_topic(
+    State(gateway): State<Arc<HttpGateway>>,
+    ConnectInfo(peer_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<StatusCode, Error> {
+    let principal = gateway.authenticate(&headers, peer_addr).await?;
+    let topic_name = parse_topic_name(name)?;
+    gateway.authorize(principal.as_deref(), AclOperation::Delete, &topic_name).await?;
+
+    gateway.registry.delete_topic(&topic_name).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn publish_messages(
+    State(gateway): State<Arc<HttpGateway>>,
+    ConnectInfo(peer_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Json(request): Json<PublishRequest>,
+) -> Result<Json<PublishResponse>, Error> {
+    let principal = gateway.authenticate(&headers, peer_addr).await?;
+    let topic_name = parse_topic_name(name)?;
+    gateway.authorize(principal.as_deref(), AclOperation::Publish, &topic_name).await?;
+
+    if !(1..=MAX_BATCH_LEN).contains(&request.messages.len()) {
+        return Err(Error::InvalidArgument(format!("expected 1 to {MAX_BATCH_LEN} messages")));
+    }
+
+    let msgs = request
+        .messages
+        .into_iter()
+        .map(|value| match value.contains('\n') {
+            true => Err(Error::InvalidArgument("message can't contain a line terminator".to_owned())),
+            false => Ok(Message::from(Bytes::from(value + "\n"))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let (num_of_msgs, bytes) = (msgs.len() as u64, msgs.iter().map(|msg| msg.value().len() as u64).sum());
+
+    let writer = gateway.registry.writer(&topic_name).await?;
+    let base_offset = {
+        let mut writer = writer.lock().await;
+        let base_offset = writer.write_batch(msgs).await?;
+
+        let start = Instant::now();
+        writer.flush_topic_metadata().await?;
+        gateway.metrics.record_flush(start.elapsed());
+
+        base_offset
+    };
+
+    gateway.metrics.record_publish(&topic_name, num_of_msgs, bytes);
+    if let Some(ref quota_manager) = gateway.quota_manager {
+        tokio::time::sleep(quota_manager.record_publish(principal.as_deref(), &topic_name, num_of_msgs, bytes).await).await;
+    }
+
+    Ok(Json(PublishResponse { base_offset }))
+}
+
+async fn fetch_messages(
+    State(gateway): State<Arc<HttpGateway>>,
+    ConnectInfo(peer_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Query(query): Query<FetchQuery>,
+) -> Result<Json<FetchResponse>, Error> {
+    let principal = gateway.authenticate(&headers, peer_addr).await?;
+    let topic_name = parse_topic_name(name)?;
+    gateway.authorize(principal.as_deref(), AclOperation::Subscribe, &topic_name).await?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_FETCH_LIMIT).min(MAX_BATCH_LEN);
+    let max_bytes = query.max_bytes.unwrap_or(DEFAULT_MAX_FRAME_SIZE);
+    if limit == 0 || max_bytes == 0 {
+        return Err(Error::InvalidArgument("limit and max_bytes must be positive".to_owned()));
+    }
+
+    let mut reader = gateway.registry.reader(&topic_name).await?;
+    reader.set_isolation_level(query.isolation_level);
+    let mut msgs = reader.read_range(query.offset, limit, max_bytes).await?;
+    // messages that can't be encoded are left for next fetch
+    let messages = encode_messages(&mut msgs, query.encoding)?;
+
+    let next_offset = msgs.last().and_then(|msg| msg.offset()).map_or(query.offset, |offset| offset + 1);
+    let bytes = msgs.iter().map(|msg| msg.value().len() as u64).sum();
+    gateway.metrics.record_read(&topic_name, msgs.len() as u64, bytes);
+
+    if let Some(ref quota_manager) = gateway.quota_manager {
+        tokio::time::sleep(quota_manager.record_fetch(principal.as_deref(), &topic_name, bytes).await).await;
+    }
+
+    Ok(Json(FetchResponse { messages, next_offset }))
+}
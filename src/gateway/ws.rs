@@ -0,0 +1,188 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::ws::{close_code, CloseFrame, Message as WsMessage, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use tokio::select;
+use tokio::sync::{broadcast, watch};
+use tokio::time::Instant;
+use tracing::{debug, instrument};
+
+use super::rest::{encode_messages, parse_topic_name, FetchedMessage, ValueEncoding};
+use super::{bearer_token, HttpGateway};
+use crate::acl::AclOperation;
+use crate::error::Error;
+use crate::metrics::ReaderLag;
+use crate::sesp::{DEFAULT_MAX_FRAME_SIZE, MAX_BATCH_LEN};
+use crate::topic::{IsolationLevel, SimpleDiskTopicReader, TopicReader};
+use crate::types::{Message, TopicName};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubscribeQuery {
+    #[serde(default)]
+    offset: usize,
+    #[serde(default)]
+    isolation_level: IsolationLevel,
+    #[serde(default)]
+    encoding: ValueEncoding,
+    /// alternative to `Authorization` header, which browsers can't set on
+    /// WebSocket requests
+    access_token: Option<String>,
+}
+
+/// per subscription state, same as that of a SESP subscriber
+struct Subscription {
+    gateway: Arc<HttpGateway>,
+    principal: Option<String>,
+    topic_name: TopicName,
+    reader: SimpleDiskTopicReader,
+    reader_lag: ReaderLag,
+    offset: usize,
+    encoding: ValueEncoding,
+    /// `last_flushed_offset` of topic after every flush
+    flushes: watch::Receiver<Option<usize>>,
+    /// set once a read found no message, next one waits for a flush
+    caught_up: bool,
+    /// set after a push exceeding quota, nothing is read before then
+    throttled_until: Option<Instant>,
+}
+
+pub(super) fn router() -> Router<Arc<HttpGateway>> {
+    Router::new().route("/topics/:name/subscribe", get(subscribe))
+}
+
+async fn subscribe(
+    State(gateway): State<Arc<HttpGateway>>,
+    ConnectInfo(peer_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Query(query): Query<SubscribeQuery>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, Error> {
+    let token = bearer_token(&headers).or(query.access_token.as_deref());
+    let principal = gateway.authenticate_token(token, peer_addr).await?;
+    let topic_name = parse_topic_name(name)?;
+    gateway.authorize(principal.as_deref(), AclOperation::Subscribe, &topic_name).await?;
+
+    let flushes = gateway.registry.watch_flushes(&topic_name).await?;
+    let mut reader = gateway.registry.reader(&topic_name).await?;
+    reader.set_isolation_level(query.isolation_level);
+
+    let termination_signal_recvr = gateway.termination_signal_sender.subscribe();
+    let reader_lag = gateway.metrics.register_reader(&topic_name);
+    let subscription = Subscription {
+        gateway,
+        principal,
+        topic_name,
+        reader,
+        reader_lag,
+        offset: query.offset,
+        encoding: query.encoding,
+        flushes,
+        caught_up: false,
+        throttled_until: None,
+    };
+
+    Ok(upgrade.on_upgrade(move |socket| subscription.run(socket, termination_signal_recvr)))
+}
+
+impl Subscription {
+    /// reads upto a batch of messages at current offset along with their
+    /// encoded form, waiting for next flush first if previous read found none.
+    /// cancel safe, a throttled or waiting poll can be cancelled and retried.
+    async fn poll(&mut self) -> Result<(Vec<Message>, Vec<FetchedMessage>), Error> {
+        if let Some(throttled_until) = self.throttled_until {
+            tokio::time::sleep_until(throttled_until).await;
+            self.throttled_until = None;
+        }
+
+        if self.caught_up {
+            // sender is dropped once topic is deleted
+            self.flushes.changed().await.map_err(|_| Error::NotFound(self.topic_name.clone()))?;
+            self.caught_up = false;
+        }
+        let last_flushed_offset = *self.flushes.borrow_and_update();
+        self.reader.observe_flush(last_flushed_offset);
+
+        // rules may have been revoked since socket was opened
+        self.gateway.authorize(self.principal.as_deref(), AclOperation::Subscribe, &self.topic_name).await?;
+
+        let mut msgs = self.reader.read_range(self.offset, MAX_BATCH_LEN, DEFAULT_MAX_FRAME_SIZE).await?;
+        let fetched = encode_messages(&mut msgs, self.encoding)?;
+        self.caught_up = msgs.is_empty();
+
+        Ok((msgs, fetched))
+    }
+
+    /// sends every message as a text frame of its own, returns `false` once client is gone
+    async fn push(&mut self, socket: &mut (impl SinkExt<WsMessage> + Unpin), msgs: Vec<Message>, fetched: Vec<FetchedMessage>) -> bool {
+        let bytes = msgs.iter().map(|msg| msg.value().len() as u64).sum();
+        for (msg, fetched) in msgs.iter().zip(fetched) {
+            let frame = serde_json::to_string(&fetched).expect("message is always serializable");
+            if socket.send(WsMessage::Text(frame)).await.is_err() {
+                return false;
+            }
+
+            // offsets of control records and hidden messages are skipped over
+            self.offset = msg.offset().map_or(self.offset, |offset| offset + 1);
+        }
+
+        let lag = self.reader.last_flushed_offset().map_or(0, |offset| offset + 1).saturating_sub(self.offset);
+        self.gateway.metrics.record_read(&self.topic_name, msgs.len() as u64, bytes);
+        self.reader_lag.set(lag as u64);
+        if let Some(ref quota_manager) = self.gateway.quota_manager {
+            let throttle_time = quota_manager.record_fetch(self.principal.as_deref(), &self.topic_name, bytes).await;
+            self.throttled_until = Some(Instant::now() + throttle_time);
+        }
+
+        true
+    }
+
+    /// pushes messages as they are flushed until client closes the socket or
+    /// server shuts down, messages are never pushed twice or out of order
+    #[instrument(name = "websocket", skip_all, fields(principal = self.principal.as_deref(), topic = %self.topic_name))]
+    async fn run(mut self, socket: WebSocket, mut termination_signal_recvr: broadcast::Receiver<()>) {
+        let (mut sender, mut receiver) = socket.split();
+
+        let close_frame = loop {
+            let msgs = select! {
+                _ = termination_signal_recvr.recv() => break Some(CloseFrame {
+                    code: close_code::AWAY,
+                    reason: Error::ShuttingDown.code().into(),
+                }),
+                // pings are answered by axum, nothing but close is expected from client
+                incoming = receiver.next() => match incoming {
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break None,
+                    Some(Ok(_)) => continue,
+                },
+                msgs = self.poll() => msgs,
+            };
+
+            match msgs {
+                Ok((msgs, _)) if msgs.is_empty() => (),
+                Ok((msgs, fetched)) => {
+                    if !self.push(&mut sender, msgs, fetched).await {
+                        break None;
+                    }
+                }
+                Err(e) => {
+                    debug!(error = %e, "closing subscription");
+                    // reason of a close frame is limited to 123 bytes, too short for most error messages
+                    break Some(CloseFrame {
+                        code: close_code::ERROR,
+                        reason: e.code().into(),
+                    });
+                }
+            }
+        };
+
+        let _ = sender.send(WsMessage::Close(close_frame)).await; // ignore result, client may be gone
+    }
+}
@@ -1,4 +1,11 @@
+pub mod acl;
 pub mod broker;
+pub mod client;
+pub mod config;
+pub mod error;
+pub mod gateway;
+pub mod metrics;
+pub mod quota;
 pub mod topic;
 pub mod types;
 pub mod sesp;
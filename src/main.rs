@@ -1,3 +1,177 @@
-fn main() {
-    println!("Hello, world!");
+use std::error::Error;
+use std::sync::Arc;
+
+use stream_relay::broker::{
+    Authenticator, Broker, ConnectionHandler, SimpleAdminConnectionHandler, SimplePublisherConnectionHandler,
+    SimpleSubscriberConnectionHandler,
+};
+use stream_relay::acl::{AclStore, ACL_CHANGES_FILE_NAME};
+use stream_relay::config::{LogFormat, ServerConfig};
+use stream_relay::gateway::HttpGateway;
+use stream_relay::metrics::Metrics;
+use stream_relay::quota::QuotaManager;
+use stream_relay::topic::TopicRegistry;
+use tokio::task::JoinHandle;
+use tracing_subscriber::EnvFilter;
+
+/// prints logs to stderr, `RUST_LOG` takes precedence over `log_level` of config
+fn init_logging(config: &ServerConfig) -> Result<(), Box<dyn Error>> {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new(config.log_level())?,
+    };
+
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr);
+    match config.log_format() {
+        LogFormat::Pretty => subscriber.pretty().init(),
+        LogFormat::Json => subscriber.json().with_current_span(true).with_span_list(true).init(),
+    }
+
+    Ok(())
+}
+
+/// binds a broker to `addr` and serves it with `handler` until termination signal
+async fn spawn_broker<T: ConnectionHandler>(
+    config: &ServerConfig,
+    addr: &str,
+    handler: T,
+    metrics: Arc<Metrics>,
+    termination_signal_senders: &mut Vec<tokio::sync::broadcast::Sender<()>>,
+) -> Result<JoinHandle<()>, Box<dyn Error>> {
+    let mut broker = Broker::new();
+    broker.set_metrics(metrics);
+    broker.bind(addr).await?;
+    if let Some(tls_config) = config.tls() {
+        broker.enable_tls(tls_config).await?;
+    }
+    broker.set_shutdown_timeout(config.shutdown_timeout());
+    broker.set_max_connections(config.max_connections());
+    broker.set_max_connections_per_ip(config.max_connections_per_ip());
+
+    termination_signal_senders.push(broker.get_termination_signal_sender().await);
+
+    let handler = Arc::new(handler);
+    Ok(tokio::spawn(async move { broker.run(handler).await }))
+}
+
+/// serves `GET /metrics` on `addr` until termination signal
+async fn spawn_metrics_server(
+    addr: &str,
+    metrics: Arc<Metrics>,
+    termination_signal_senders: &mut Vec<tokio::sync::broadcast::Sender<()>>,
+) -> Result<JoinHandle<()>, Box<dyn Error>> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let (termination_signal_sender, termination_signal_recvr) = tokio::sync::broadcast::channel(1);
+    termination_signal_senders.push(termination_signal_sender);
+
+    Ok(tokio::spawn(async move {
+        if let Err(e) = stream_relay::metrics::serve(listener, metrics, termination_signal_recvr).await {
+            tracing::error!(error = %e, "metrics server failed");
+        }
+    }))
+}
+
+/// serves REST gateway on `addr` until termination signal
+async fn spawn_http_gateway(
+    addr: &str,
+    gateway: HttpGateway,
+    termination_signal_senders: &mut Vec<tokio::sync::broadcast::Sender<()>>,
+) -> Result<JoinHandle<()>, Box<dyn Error>> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!(addr = %listener.local_addr()?, "serving HTTP gateway");
+    let (termination_signal_sender, termination_signal_recvr) = tokio::sync::broadcast::channel(1);
+    termination_signal_senders.push(termination_signal_sender);
+
+    Ok(tokio::spawn(async move {
+        if let Err(e) = gateway.serve(listener, termination_signal_recvr).await {
+            tracing::error!(error = %e, "HTTP gateway failed");
+        }
+    }))
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    // usage: stream-relay [config.toml]
+    let config = match std::env::args().nth(1) {
+        Some(config_path) => ServerConfig::load(config_path).await?,
+        None => ServerConfig::default(),
+    };
+    init_logging(&config)?;
+
+    let registry = Arc::new(TopicRegistry::new(config.root_path()));
+    registry.recover().await?;
+
+    let authenticator = match config.credentials_path() {
+        Some(credentials_path) => Some(Arc::new(Authenticator::load(credentials_path).await?)),
+        None => None,
+    };
+
+    let metrics = Arc::new(Metrics::new());
+    let max_frame_size = *config.max_frame_size();
+    let mut admin_handler = SimpleAdminConnectionHandler::new(registry.clone())
+        .with_max_frame_size(max_frame_size)
+        .with_metrics(metrics.clone());
+    let mut publisher_handler = SimplePublisherConnectionHandler::new(registry.clone())
+        .with_max_frame_size(max_frame_size)
+        .with_metrics(metrics.clone());
+    let mut subscriber_handler = SimpleSubscriberConnectionHandler::new(registry.clone())
+        .with_max_frame_size(max_frame_size)
+        .with_metrics(metrics.clone());
+    if let Some(idle_timeout) = config.idle_timeout() {
+        admin_handler = admin_handler.with_idle_timeout(idle_timeout);
+        publisher_handler = publisher_handler.with_idle_timeout(idle_timeout);
+        subscriber_handler = subscriber_handler.with_idle_timeout(idle_timeout);
+    }
+
+    let mut http_gateway = HttpGateway::new(registry.clone()).with_metrics(metrics.clone());
+
+    if let Some(ref authenticator) = authenticator {
+        admin_handler = admin_handler.with_authenticator(authenticator.clone());
+        publisher_handler = publisher_handler.with_authenticator(authenticator.clone());
+        subscriber_handler = subscriber_handler.with_authenticator(authenticator.clone());
+        http_gateway = http_gateway.with_authenticator(authenticator.clone());
+    }
+
+    if let Some(acl_config) = config.acl() {
+        let acl_store = Arc::new(AclStore::load(acl_config.clone(), config.root_path().join(ACL_CHANGES_FILE_NAME)).await?);
+        admin_handler = admin_handler.with_acl_store(acl_store.clone());
+        publisher_handler = publisher_handler.with_acl_store(acl_store.clone());
+        subscriber_handler = subscriber_handler.with_acl_store(acl_store.clone());
+        http_gateway = http_gateway.with_acl_store(acl_store);
+    }
+
+    if let Some(quota_config) = config.quotas() {
+        let quota_manager = Arc::new(QuotaManager::new(quota_config.clone()));
+        publisher_handler = publisher_handler.with_quota_manager(quota_manager.clone());
+        subscriber_handler = subscriber_handler.with_quota_manager(quota_manager.clone());
+        http_gateway = http_gateway.with_quota_manager(quota_manager);
+    }
+
+    let mut termination_signal_senders = vec![];
+    let mut brokers = vec![
+        spawn_broker(&config, config.admin_addr(), admin_handler, metrics.clone(), &mut termination_signal_senders).await?,
+        spawn_broker(&config, config.publisher_addr(), publisher_handler, metrics.clone(), &mut termination_signal_senders).await?,
+        spawn_broker(&config, config.subscriber_addr(), subscriber_handler, metrics.clone(), &mut termination_signal_senders).await?,
+    ];
+    if let Some(http_addr) = config.http_addr() {
+        brokers.push(spawn_http_gateway(http_addr, http_gateway, &mut termination_signal_senders).await?);
+    }
+    if let Some(metrics_addr) = config.metrics_addr() {
+        brokers.push(spawn_metrics_server(metrics_addr, metrics, &mut termination_signal_senders).await?);
+    }
+
+    tokio::signal::ctrl_c().await?;
+    tracing::info!("received Ctrl-C, shutting down");
+    for termination_signal_sender in termination_signal_senders {
+        let _ = termination_signal_sender.send(()); // ignore result
+    }
+
+    // brokers return once their connections are closed, or shutdown timeout elapsed
+    for broker in brokers {
+        broker.await?;
+    }
+
+    registry.flush().await?;
+
+    Ok(())
 }
@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+
+use crate::sesp::Response;
+use crate::types::TopicName;
+
+/// upper bounds (in seconds) of flush latency histogram buckets
+const FLUSH_LATENCY_BUCKETS: &[f64] = &[0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+/// counters and gauges of a broker, rendered in Prometheus text format.
+///
+/// every handler and broker owns a private instance unless one is shared
+/// with all of them through their `with_metrics`/`set_metrics`.
+#[derive(Debug)]
+pub struct Metrics {
+    connections_total: AtomicU64,
+    open_connections: AtomicU64,
+    rejected_connections_total: AtomicU64,
+    commands_total: Family,
+    error_responses_total: Family,
+    published_messages_total: Family,
+    published_bytes_total: Family,
+    read_messages_total: Family,
+    read_bytes_total: Family,
+    /// lag of every open reader keyed by topic, then by reader id
+    reader_lags: Mutex<BTreeMap<String, BTreeMap<u64, u64>>>,
+    next_reader_id: AtomicU64,
+    flush_latency: Histogram,
+}
+
+/// values of a metric keyed by value of its only label
+#[derive(Debug)]
+struct Family {
+    label: &'static str,
+    values: Mutex<BTreeMap<String, u64>>,
+}
+
+#[derive(Debug)]
+struct Histogram {
+    /// non cumulative count of observations of each bucket, last one is `+Inf`
+    buckets: Vec<AtomicU64>,
+    sum_micros: AtomicU64,
+}
+
+/// counts a connection as open until dropped
+#[derive(Debug)]
+pub struct OpenConnection {
+    metrics: Arc<Metrics>,
+}
+
+/// lag of a single reader of a topic, reported until dropped
+#[derive(Debug)]
+pub struct ReaderLag {
+    metrics: Arc<Metrics>,
+    topic_name: TopicName,
+    id: u64,
+}
+
+impl Family {
+    fn new(label: &'static str) -> Self {
+        Self {
+            label,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn inc_by(&self, label_value: &str, n: u64) {
+        let mut values = self.values.lock().unwrap();
+        match values.get_mut(label_value) {
+            Some(value) => *value += n,
+            None => {
+                values.insert(label_value.to_owned(), n);
+            }
+        }
+    }
+
+    fn render(&self, out: &mut String, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+        for (label_value, value) in self.values.lock().unwrap().iter() {
+            let _ = writeln!(out, "{name}{{{}=\"{}\"}} {value}", self.label, escape_label_value(label_value));
+        }
+    }
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: (0..=FLUSH_LATENCY_BUCKETS.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = FLUSH_LATENCY_BUCKETS
+            .iter()
+            .position(|upper_bound| seconds <= *upper_bound)
+            .unwrap_or(FLUSH_LATENCY_BUCKETS.len());
+
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} histogram");
+
+        let mut count = 0;
+        for (idx, bucket) in self.buckets.iter().enumerate() {
+            count += bucket.load(Ordering::Relaxed);
+            let upper_bound = FLUSH_LATENCY_BUCKETS.get(idx).map_or("+Inf".to_owned(), |upper_bound| upper_bound.to_string());
+            let _ = writeln!(out, "{name}_bucket{{le=\"{upper_bound}\"}} {count}");
+        }
+
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "{name}_sum {sum}\n{name}_count {count}");
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            connections_total: AtomicU64::new(0),
+            open_connections: AtomicU64::new(0),
+            rejected_connections_total: AtomicU64::new(0),
+            commands_total: Family::new("command"),
+            error_responses_total: Family::new("code"),
+            published_messages_total: Family::new("topic"),
+            published_bytes_total: Family::new("topic"),
+            read_messages_total: Family::new("topic"),
+            read_bytes_total: Family::new("topic"),
+            reader_lags: Mutex::new(BTreeMap::new()),
+            next_reader_id: AtomicU64::new(0),
+            flush_latency: Histogram::new(),
+        }
+    }
+
+    pub fn open_connection(self: &Arc<Self>) -> OpenConnection {
+        self.connections_total.fetch_add(1, Ordering::Relaxed);
+        self.open_connections.fetch_add(1, Ordering::Relaxed);
+
+        OpenConnection { metrics: self.clone() }
+    }
+
+    pub fn record_rejected_connection(&self) {
+        self.rejected_connections_total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// `command` is name of command as returned by `Command::name`
+    pub fn record_command(&self, command: &str) {
+        self.commands_total.inc_by(command, 1);
+    }
+
+    /// counts negative responses by error code, `-None` of `ReadMessage` is not an error
+    pub fn record_response(&self, response: &Response) {
+        if let Response::Negative(data) = response {
+            // argument of code, if any, is left out to keep label values bounded
+            let code = data.split([' ', ':']).next().unwrap_or_default();
+            if code != "None" {
+                self.error_responses_total.inc_by(code, 1);
+            }
+        }
+    }
+
+    pub fn record_publish(&self, topic_name: &TopicName, msgs: u64, bytes: u64) {
+        self.published_messages_total.inc_by(topic_name.as_str(), msgs);
+        self.published_bytes_total.inc_by(topic_name.as_str(), bytes);
+    }
+
+    pub fn record_read(&self, topic_name: &TopicName, msgs: u64, bytes: u64) {
+        self.read_messages_total.inc_by(topic_name.as_str(), msgs);
+        self.read_bytes_total.inc_by(topic_name.as_str(), bytes);
+    }
+
+    /// registers a long lived reader of topic, e.g. a subscriber, whose lag
+    /// counts towards that of topic until returned value is dropped
+    pub fn register_reader(self: &Arc<Self>, topic_name: &TopicName) -> ReaderLag {
+        let id = self.next_reader_id.fetch_add(1, Ordering::Relaxed);
+        self.reader_lags.lock().unwrap().entry(topic_name.as_str().to_owned()).or_default().insert(id, 0);
+
+        ReaderLag {
+            metrics: self.clone(),
+            topic_name: topic_name.clone(),
+            id,
+        }
+    }
+
+    pub fn record_flush(&self, latency: Duration) {
+        self.flush_latency.observe(latency);
+    }
+
+    /// every metric in Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            ("stream_relay_connections_total", &self.connections_total, "counter", "connections accepted"),
+            ("stream_relay_open_connections", &self.open_connections, "gauge", "connections currently open"),
+            ("stream_relay_rejected_connections_total", &self.rejected_connections_total, "counter", "connections closed for exceeding a connection limit"),
+        ];
+        for (name, value, kind, help) in counters {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {}", value.load(Ordering::Relaxed));
+        }
+
+        self.commands_total.render(&mut out, "stream_relay_commands_total", "counter", "commands received by type");
+        self.error_responses_total.render(&mut out, "stream_relay_error_responses_total", "counter", "negative responses by error code");
+        self.published_messages_total.render(&mut out, "stream_relay_published_messages_total", "counter", "messages published by topic");
+        self.published_bytes_total.render(&mut out, "stream_relay_published_bytes_total", "counter", "bytes published by topic");
+        self.read_messages_total.render(&mut out, "stream_relay_read_messages_total", "counter", "messages read by topic");
+        self.read_bytes_total.render(&mut out, "stream_relay_read_bytes_total", "counter", "bytes read by topic");
+        self.render_reader_lags(&mut out);
+        self.flush_latency.render(&mut out, "stream_relay_flush_latency_seconds", "time taken to flush topic metadata after a publish");
+
+        out
+    }
+
+    /// lag of the slowest reader of each topic, topics without readers are left out
+    fn render_reader_lags(&self, out: &mut String) {
+        let name = "stream_relay_reader_lag_messages";
+        let _ = writeln!(out, "# HELP {name} messages not yet read by the slowest subscriber of topic\n# TYPE {name} gauge");
+        for (topic_name, lags) in self.reader_lags.lock().unwrap().iter() {
+            let lag = lags.values().max().copied().unwrap_or_default();
+            let _ = writeln!(out, "{name}{{topic=\"{}\"}} {lag}", escape_label_value(topic_name));
+        }
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        self.metrics.open_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ReaderLag {
+    /// `lag` is number of messages flushed to topic past those read so far
+    pub fn set(&self, lag: u64) {
+        let mut reader_lags = self.metrics.reader_lags.lock().unwrap();
+        if let Some(lags) = reader_lags.get_mut(self.topic_name.as_str()) {
+            lags.insert(self.id, lag);
+        }
+    }
+}
+
+impl Drop for ReaderLag {
+    fn drop(&mut self) {
+        let mut reader_lags = self.metrics.reader_lags.lock().unwrap();
+        if let Some(lags) = reader_lags.get_mut(self.topic_name.as_str()) {
+            lags.remove(&self.id);
+            if lags.is_empty() {
+                reader_lags.remove(self.topic_name.as_str());
+            }
+        }
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+async fn render_metrics(State(metrics): State<Arc<Metrics>>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics.render())
+}
+
+/// serves `GET /metrics` until termination signal is received
+pub async fn serve(listener: TcpListener, metrics: Arc<Metrics>, mut termination_signal_recvr: broadcast::Receiver<()>) -> std::io::Result<()> {
+    let app = Router::new().route("/metrics", get(render_metrics)).with_state(metrics);
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            let _ = termination_signal_recvr.recv().await; // ignore result
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn metrics_render_test_01() {
+        let metrics = Arc::new(Metrics::new());
+        let topic_name: TopicName = "foo".parse().unwrap();
+
+        let connection = metrics.open_connection();
+        let _other_connection = metrics.open_connection();
+        drop(connection);
+        metrics.record_command("PublishMessage");
+        metrics.record_command("PublishMessage");
+        metrics.record_response(&Response::Negative("NoSuchTopicExists topic `bar` doesn't exist".to_owned()));
+        metrics.record_response(&Response::Negative("None".to_owned()));
+        metrics.record_publish(&topic_name, 2, 12);
+        metrics.record_read(&topic_name, 1, 6);
+        let reader_lag = metrics.register_reader(&topic_name);
+        let slowest_reader_lag = metrics.register_reader(&topic_name);
+        reader_lag.set(1);
+        slowest_reader_lag.set(3);
+        metrics.record_flush(Duration::from_millis(3));
+
+        let rendered = metrics.render();
+        for line in [
+            "stream_relay_connections_total 2",
+            "stream_relay_open_connections 1",
+            "stream_relay_commands_total{command=\"PublishMessage\"} 2",
+            "stream_relay_error_responses_total{code=\"NoSuchTopicExists\"} 1",
+            "stream_relay_published_messages_total{topic=\"foo\"} 2",
+            "stream_relay_published_bytes_total{topic=\"foo\"} 12",
+            "stream_relay_read_bytes_total{topic=\"foo\"} 6",
+            "stream_relay_reader_lag_messages{topic=\"foo\"} 3",
+            "stream_relay_flush_latency_seconds_bucket{le=\"0.0025\"} 0",
+            "stream_relay_flush_latency_seconds_bucket{le=\"0.005\"} 1",
+            "stream_relay_flush_latency_seconds_bucket{le=\"+Inf\"} 1",
+            "stream_relay_flush_latency_seconds_count 1",
+        ] {
+            assert!(rendered.lines().any(|rendered_line| rendered_line == line), "missing `{line}` in:\n{rendered}");
+        }
+        assert!(!rendered.contains("code=\"None\""));
+
+        drop(slowest_reader_lag);
+        assert!(metrics.render().lines().any(|line| line == "stream_relay_reader_lag_messages{topic=\"foo\"} 1"));
+        drop(reader_lag);
+        assert!(!metrics.render().contains("stream_relay_reader_lag_messages{"));
+        assert_eq!(escape_label_value("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[tokio::test]
+    async fn metrics_serve_test_01() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.record_command("Fetch");
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (termination_signal_sender, termination_signal_recvr) = broadcast::channel(1);
+        let server = tokio::spawn(serve(listener, metrics, termination_signal_recvr));
+
+        let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("stream_relay_commands_total{command=\"Fetch\"} 1"));
+
+        termination_signal_sender.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
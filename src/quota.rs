@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Deserializer, Serialize};
+use tokio::sync::Mutex;
+
+use crate::types::TopicName;
+
+/// max time a single response is delayed for, clients exceeding their quota
+/// by more than this get throttled again on following commands
+pub const MAX_THROTTLE_TIME: Duration = Duration::from_secs(10);
+
+/// limits of a principal or a topic, missing limits are unlimited
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quota {
+    #[serde(deserialize_with = "deserialize_limit")]
+    publish_bytes_per_sec: Option<u64>,
+    #[serde(deserialize_with = "deserialize_limit")]
+    publish_msgs_per_sec: Option<u64>,
+    #[serde(deserialize_with = "deserialize_limit")]
+    fetch_bytes_per_sec: Option<u64>,
+}
+
+/// quota settings of a server, keyed by principal and by topic name
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaConfig {
+    /// applies to every principal (including unauthenticated connections)
+    /// without an entry of its own
+    default: Option<Quota>,
+    principals: HashMap<String, Quota>,
+    topics: HashMap<TopicName, Quota>,
+}
+
+/// refills at `rate` tokens per second upto one second worth of tokens, and
+/// can go into debt so a single large request is throttled rather than rejected
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Limit {
+    PublishBytes,
+    PublishMsgs,
+    FetchBytes,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum BucketKey {
+    /// `None` for unauthenticated connections
+    Principal(Option<String>, Limit),
+    Topic(TopicName, Limit),
+}
+
+/// token buckets of every principal and topic with a quota, shared by every
+/// connection handler
+pub struct QuotaManager {
+    config: QuotaConfig,
+    buckets: Mutex<HashMap<BucketKey, TokenBucket>>,
+}
+
+/// a limit of 0 would stall clients forever, absence of a limit means unlimited
+fn deserialize_limit<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match Option::<u64>::deserialize(deserializer)? {
+        Some(0) => Err(serde::de::Error::custom("quota must be positive, leave it out for no limit")),
+        limit => Ok(limit),
+    }
+}
+
+impl Quota {
+    /// panic! if any limit is 0, leave it out (`None`) for no limit instead
+    pub fn new(publish_bytes_per_sec: Option<u64>, publish_msgs_per_sec: Option<u64>, fetch_bytes_per_sec: Option<u64>) -> Self {
+        assert!(
+            ![publish_bytes_per_sec, publish_msgs_per_sec, fetch_bytes_per_sec].contains(&Some(0)),
+            "quota must be positive"
+        );
+
+        Self {
+            publish_bytes_per_sec,
+            publish_msgs_per_sec,
+            fetch_bytes_per_sec,
+        }
+    }
+
+    pub fn publish_bytes_per_sec(&self) -> Option<u64> {
+        self.publish_bytes_per_sec
+    }
+
+    pub fn publish_msgs_per_sec(&self) -> Option<u64> {
+        self.publish_msgs_per_sec
+    }
+
+    pub fn fetch_bytes_per_sec(&self) -> Option<u64> {
+        self.fetch_bytes_per_sec
+    }
+
+    fn limit(&self, limit: Limit) -> Option<u64> {
+        match limit {
+            Limit::PublishBytes => self.publish_bytes_per_sec,
+            Limit::PublishMsgs => self.publish_msgs_per_sec,
+            Limit::FetchBytes => self.fetch_bytes_per_sec,
+        }
+    }
+}
+
+impl QuotaConfig {
+    pub fn new(default: Option<Quota>, principals: HashMap<String, Quota>, topics: HashMap<TopicName, Quota>) -> Self {
+        Self {
+            default,
+            principals,
+            topics,
+        }
+    }
+
+    pub fn default_quota(&self) -> &Option<Quota> {
+        &self.default
+    }
+
+    pub fn principals(&self) -> &HashMap<String, Quota> {
+        &self.principals
+    }
+
+    pub fn topics(&self) -> &HashMap<TopicName, Quota> {
+        &self.topics
+    }
+
+    fn principal_quota(&self, principal: Option<&str>) -> Option<&Quota> {
+        principal
+            .and_then(|principal| self.principals.get(principal))
+            .or(self.default.as_ref())
+    }
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last_refill: now,
+        }
+    }
+
+    /// takes `amount` tokens, returns how long caller must wait for bucket
+    /// to get out of debt
+    fn reserve(&mut self, amount: u64, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate) - amount as f64;
+        self.last_refill = now;
+
+        match self.tokens < 0.0 {
+            true => Duration::from_secs_f64(-self.tokens / self.rate),
+            false => Duration::ZERO,
+        }
+    }
+}
+
+impl QuotaManager {
+    pub fn new(config: QuotaConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// records `msgs` messages totalling `bytes` bytes published by
+    /// `principal` to `topic_name`, returns how long its response should be delayed
+    pub async fn record_publish(&self, principal: Option<&str>, topic_name: &TopicName, msgs: u64, bytes: u64) -> Duration {
+        self.record(principal, topic_name, &[(Limit::PublishBytes, bytes), (Limit::PublishMsgs, msgs)]).await
+    }
+
+    /// records `bytes` bytes fetched by `principal` from `topic_name`, returns
+    /// how long its response should be delayed
+    pub async fn record_fetch(&self, principal: Option<&str>, topic_name: &TopicName, bytes: u64) -> Duration {
+        self.record(principal, topic_name, &[(Limit::FetchBytes, bytes)]).await
+    }
+
+    async fn record(&self, principal: Option<&str>, topic_name: &TopicName, amounts: &[(Limit, u64)]) -> Duration {
+        self.record_at(principal, topic_name, amounts, Instant::now()).await
+    }
+
+    async fn record_at(&self, principal: Option<&str>, topic_name: &TopicName, amounts: &[(Limit, u64)], now: Instant) -> Duration {
+        let principal_quota = self.config.principal_quota(principal);
+        let topic_quota = self.config.topics.get(topic_name);
+
+        let mut buckets = self.buckets.lock().await;
+        let mut throttle_time = Duration::ZERO;
+        for &(limit, amount) in amounts {
+            let principal_bucket = principal_quota
+                .and_then(|quota| quota.limit(limit))
+                .map(|rate| (BucketKey::Principal(principal.map(str::to_owned), limit), rate));
+            let topic_bucket = topic_quota
+                .and_then(|quota| quota.limit(limit))
+                .map(|rate| (BucketKey::Topic(topic_name.clone(), limit), rate));
+
+            for (key, rate) in principal_bucket.into_iter().chain(topic_bucket) {
+                let bucket = buckets.entry(key).or_insert_with(|| TokenBucket::new(rate, now));
+                throttle_time = throttle_time.max(bucket.reserve(amount, now));
+            }
+        }
+
+        throttle_time.min(MAX_THROTTLE_TIME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::test;
+
+    #[test]
+    async fn token_bucket_test_01() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(100, now);
+
+        assert_eq!(bucket.reserve(100, now), Duration::ZERO);
+        assert_eq!(bucket.reserve(50, now), Duration::from_millis(500));
+        // refilled for a second, still 50 tokens short after taking another 100
+        assert_eq!(bucket.reserve(100, now + Duration::from_secs(1)), Duration::from_millis(500));
+        // never holds more than a second worth of tokens
+        assert_eq!(bucket.reserve(100, now + Duration::from_secs(60)), Duration::ZERO);
+        assert_eq!(bucket.reserve(1, now + Duration::from_secs(60)), Duration::from_millis(10));
+    }
+
+    #[test]
+    async fn quota_manager_test_01() {
+        let quota_manager = QuotaManager::new(QuotaConfig::new(
+            Some(Quota::new(None, Some(10), Some(1000))),
+            HashMap::from([("alice".to_owned(), Quota::new(Some(100), None, None))]),
+            HashMap::from([("orders".parse().unwrap(), Quota::new(None, Some(5), None))]),
+        ));
+        let orders: TopicName = "orders".parse().unwrap();
+        let events: TopicName = "events".parse().unwrap();
+        let now = Instant::now();
+
+        // alice has a quota of her own, only publish bytes are limited
+        let publish = [(Limit::PublishBytes, 200), (Limit::PublishMsgs, 1)];
+        assert_eq!(quota_manager.record_at(Some("alice"), &events, &publish, now).await, Duration::from_secs(1));
+        assert_eq!(quota_manager.record_at(Some("alice"), &events, &[(Limit::FetchBytes, 1 << 20)], now).await, Duration::ZERO);
+
+        // everyone else falls back to default quota, buckets are per principal
+        let publish = [(Limit::PublishBytes, 1), (Limit::PublishMsgs, 20)];
+        assert_eq!(quota_manager.record_at(Some("bob"), &events, &publish, now).await, Duration::from_secs(1));
+        assert_eq!(quota_manager.record_at(None, &events, &publish, now).await, Duration::from_secs(1));
+
+        // topic quota is shared by every principal publishing to the topic
+        let publish = [(Limit::PublishMsgs, 5)];
+        assert_eq!(quota_manager.record_at(Some("alice"), &orders, &publish, now).await, Duration::ZERO);
+        assert_eq!(quota_manager.record_at(Some("carol"), &orders, &publish, now).await, Duration::from_secs(1));
+
+        // throttling is capped, rest of the debt is paid by following commands
+        let fetch = [(Limit::FetchBytes, 1_000_000)];
+        assert_eq!(quota_manager.record_at(Some("dave"), &events, &fetch, now).await, MAX_THROTTLE_TIME);
+    }
+
+    #[test]
+    async fn quota_config_deserialize_test_01() {
+        let quota_config: QuotaConfig = toml::from_str(
+            r#"
+            [default]
+            publish_bytes_per_sec = 1048576
+
+            [principals."CN=alice, O=acme"]
+            publish_msgs_per_sec = 1000
+            fetch_bytes_per_sec = 4194304
+
+            [topics."orders.eu"]
+            publish_bytes_per_sec = 65536
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(quota_config.default_quota(), &Some(Quota::new(Some(1048576), None, None)));
+        assert_eq!(quota_config.principals()["CN=alice, O=acme"], Quota::new(None, Some(1000), Some(4194304)));
+        assert_eq!(quota_config.topics()[&"orders.eu".parse().unwrap()].publish_bytes_per_sec(), Some(65536));
+
+        // a limit of 0 is rejected rather than stalling clients, topic keys must be valid topic names
+        assert!(toml::from_str::<QuotaConfig>("[default]\npublish_msgs_per_sec = 0").is_err());
+        assert!(toml::from_str::<QuotaConfig>("[topics.\"a/b\"]\npublish_msgs_per_sec = 1").is_err());
+
+        assert!(toml::from_str::<QuotaConfig>("[default]\nmsgs_per_sec = 1").is_err());
+    }
+}
@@ -0,0 +1,452 @@
+use bytes::{Buf, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::{continuation_lines, parse_request, Command, RequestId, Response, DEFAULT_MAX_FRAME_SIZE};
+use crate::error::Error;
+use crate::types::Message;
+
+/// server side of SESP, decodes commands and encodes responses.
+///
+/// items carry the optional request id of a command, which must be passed
+/// back along with its response.
+#[derive(Debug, Clone)]
+pub struct ServerCodec {
+    max_frame_size: usize,
+    scan: FrameScan,
+}
+
+/// client side of SESP, encodes commands and decodes responses
+#[derive(Debug, Clone)]
+pub struct ClientCodec {
+    max_frame_size: usize,
+    scan: FrameScan,
+}
+
+/// progress made scanning a partially received frame, kept between calls to
+/// `decode` so bytes already scanned are never looked at again
+#[derive(Debug, Clone, Default)]
+struct FrameScan {
+    scanned: usize,
+    lines_left: Option<usize>, // `None` until first line is complete
+}
+
+impl ServerCodec {
+    /// frames longer than `max_frame_size` bytes fail with `Error::FrameTooLarge`
+    pub fn new(max_frame_size: usize) -> Self {
+        Self {
+            max_frame_size,
+            scan: FrameScan::default(),
+        }
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+}
+
+impl Default for ServerCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FRAME_SIZE)
+    }
+}
+
+impl ClientCodec {
+    /// frames longer than `max_frame_size` bytes fail with `Error::FrameTooLarge`
+    pub fn new(max_frame_size: usize) -> Self {
+        Self {
+            max_frame_size,
+            scan: FrameScan::default(),
+        }
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+}
+
+impl Default for ClientCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FRAME_SIZE)
+    }
+}
+
+impl FrameScan {
+    /// length of the frame at the start of `src`, `None` if it is not complete
+    /// yet. a frame is its first line followed by `continuation_lines(first_line)`
+    /// more lines. `src` must only grow between calls until a frame is returned.
+    fn frame_len(&mut self, src: &[u8], max_frame_size: usize, continuation_lines: fn(&[u8]) -> Result<usize, Error>) -> Result<Option<usize>, Error> {
+        let result = self.scan(src, max_frame_size, continuation_lines);
+        if !matches!(result, Ok(None)) {
+            *self = Self::default();
+        }
+
+        result
+    }
+
+    fn scan(&mut self, src: &[u8], max_frame_size: usize, continuation_lines: fn(&[u8]) -> Result<usize, Error>) -> Result<Option<usize>, Error> {
+        loop {
+            if self.lines_left == Some(0) {
+                return match self.scanned {
+                    end if end > max_frame_size => Err(Error::FrameTooLarge(max_frame_size)),
+                    end => Ok(Some(end)),
+                };
+            }
+
+            let line_end = match src[self.scanned..].iter().position(|byte| *byte == b'\n') {
+                Some(end) => self.scanned + end + 1,
+                None if src.len() > max_frame_size => return Err(Error::FrameTooLarge(max_frame_size)),
+                None => {
+                    self.scanned = src.len();
+                    return Ok(None);
+                }
+            };
+
+            self.lines_left = match self.lines_left {
+                Some(lines_left) => Some(lines_left - 1),
+                None => Some(continuation_lines(&src[..line_end])?),
+            };
+            self.scanned = line_end;
+        }
+    }
+
+    /// `src` was consumed by someone else
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// number of lines following first line of a response that belong to it,
+/// that is `N` for a `*<N> <offset>` messages header and 0 for everything else
+fn response_continuation_lines(first_line: &[u8]) -> Result<usize, Error> {
+    match split_request_id(first_line).1.split_first() {
+        Some((b'*', header)) => header
+            .split(|byte| *byte == b' ' || *byte == b'\n')
+            .next()
+            .and_then(|len| std::str::from_utf8(len).ok()?.parse().ok())
+            .ok_or_else(|| Error::Protocol("invalid messages header".to_owned())),
+        _ => Ok(0),
+    }
+}
+
+/// splits optional `^<request_id> ` prefix, leaves `frame` untouched if it
+/// has none or if it is malformed
+fn split_request_id(frame: &[u8]) -> (Option<RequestId>, &[u8]) {
+    if frame.first() != Some(&b'^') {
+        return (None, frame);
+    }
+
+    let request_id = frame
+        .iter()
+        .position(|byte| *byte == b' ')
+        .and_then(|end| Some((std::str::from_utf8(&frame[1..end]).ok()?.parse().ok()?, end)));
+
+    match request_id {
+        Some((request_id, end)) => (Some(request_id), &frame[end + 1..]),
+        None => (None, frame),
+    }
+}
+
+/// parses a whole response frame, `+<data>\n`, `-<data>\n` or `*<N> <offset>\n`
+/// followed by N messages. data of positive and negative responses excludes
+/// the line terminator.
+fn parse_response(frame: Bytes) -> Option<(Option<RequestId>, Response)> {
+    let (request_id, response) = split_request_id(&frame);
+    let response = frame.slice_ref(response);
+
+    let line_end = response.iter().position(|byte| *byte == b'\n')?;
+    let data = std::str::from_utf8(&response[1..line_end]);
+
+    let response = match response.first()? {
+        b'+' => Response::Positive(data.ok()?.to_owned()),
+        b'-' => Response::Negative(data.ok()?.to_owned()),
+        b'*' => {
+            let mut tokens = data.ok()?.split(' ');
+            let len: usize = tokens.next()?.parse().ok()?;
+            let first_offset: usize = match (len, tokens.next()) {
+                (0, None) => 0,
+                (_, Some(first_offset)) => first_offset.parse().ok()?,
+                _ => return None,
+            };
+
+            let mut msgs = Vec::with_capacity(len);
+            let mut start = line_end + 1;
+            while start < response.len() {
+                let end = start + response[start..].iter().position(|byte| *byte == b'\n')? + 1;
+                msgs.push(Message::new(response.slice(start..end), Some(first_offset + msgs.len())));
+                start = end;
+            }
+
+            if msgs.len() != len || tokens.next().is_some() {
+                return None;
+            }
+
+            Response::Messages(msgs)
+        }
+        _ => return None,
+    };
+
+    Some((request_id, response))
+}
+
+impl Decoder for ServerCodec {
+    type Item = (Option<RequestId>, Command);
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.scan.frame_len(src, self.max_frame_size, continuation_lines)? {
+            Some(len) => Ok(Some(parse_request(src.split_to(len).freeze()))),
+            None => Ok(None),
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(src)? {
+            Some(request) => Ok(Some(request)),
+            // truncated frame, gets parsed as invalid command
+            None if src.has_remaining() => {
+                self.scan.reset();
+                Ok(Some(parse_request(src.split().freeze())))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl Encoder<(Option<RequestId>, Response)> for ServerCodec {
+    type Error = Error;
+
+    fn encode(&mut self, (request_id, response): (Option<RequestId>, Response), dst: &mut BytesMut) -> Result<(), Self::Error> {
+        if let Some(request_id) = request_id {
+            dst.extend_from_slice(format!("^{request_id} ").as_bytes());
+        }
+        dst.extend_from_slice(&response.as_vec_of_u8());
+
+        Ok(())
+    }
+}
+
+impl Decoder for ClientCodec {
+    type Item = (Option<RequestId>, Response);
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.scan.frame_len(src, self.max_frame_size, response_continuation_lines)? {
+            Some(len) => match parse_response(src.split_to(len).freeze()) {
+                Some(response) => Ok(Some(response)),
+                None => Err(Error::Protocol("invalid response".to_owned())),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+impl Encoder<(Option<RequestId>, Command)> for ClientCodec {
+    type Error = Error;
+
+    fn encode(&mut self, (request_id, command): (Option<RequestId>, Command), dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let command = command.as_vec_of_u8()?;
+
+        if let Some(request_id) = request_id {
+            dst.extend_from_slice(format!("^{request_id} ").as_bytes());
+        }
+        dst.extend_from_slice(&command);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sesp::Hello;
+    use crate::topic::IsolationLevel;
+    use futures::{SinkExt, StreamExt};
+    use tokio_util::codec::{Framed, FramedRead};
+
+    fn msg(value: &'static [u8]) -> Message {
+        Message::from(Bytes::from_static(value))
+    }
+
+    /// encodes `command` as a client would and decodes it as server would
+    fn round_trip(command: Command) -> (Option<RequestId>, Command) {
+        let mut buf = BytesMut::new();
+        ClientCodec::default().encode((Some(7), command), &mut buf).unwrap();
+
+        let request = ServerCodec::default().decode(&mut buf).unwrap().unwrap();
+        assert!(buf.is_empty());
+
+        request
+    }
+
+    #[test]
+    fn client_codec_encode_test_01() {
+        let hello = Hello::new(1, "my-client".to_owned(), vec!["fetch".to_owned(), "request_ids".to_owned()]);
+        match round_trip(Command::Hello(hello.clone())) {
+            (Some(7), Command::Hello(decoded)) => assert_eq!(decoded, hello),
+            _ => panic!("command should have been decoded as Command::Hello"),
+        }
+
+        let options = vec![("retention_ms".to_owned(), "1000".to_owned())];
+        match round_trip(Command::CreateTopic("foo".parse().unwrap(), options.clone())) {
+            (Some(7), Command::CreateTopic(topic, decoded)) => assert_eq!((topic.as_str(), decoded), ("foo", options)),
+            _ => panic!("command should have been decoded as Command::CreateTopic"),
+        }
+
+        match round_trip(Command::PublishBatch(vec![msg(b"hello\n"), msg(b"world")])) {
+            (Some(7), Command::PublishBatch(msgs)) => assert_eq!(msgs, vec![msg(b"hello\n"), msg(b"world\n")]),
+            _ => panic!("command should have been decoded as Command::PublishBatch"),
+        }
+
+        match round_trip(Command::PublishSequenced(3, 42, msg(b"hello\n"))) {
+            (Some(7), Command::PublishSequenced(3, 42, decoded)) => assert_eq!(decoded, msg(b"hello\n")),
+            _ => panic!("command should have been decoded as Command::PublishSequenced"),
+        }
+
+        match round_trip(Command::TransactionalPublish("foo".parse().unwrap(), msg(b"hello"))) {
+            (Some(7), Command::TransactionalPublish(topic, decoded)) => assert_eq!((topic.as_str(), decoded), ("foo", msg(b"hello\n"))),
+            _ => panic!("command should have been decoded as Command::TransactionalPublish"),
+        }
+
+        assert!(matches!(round_trip(Command::Fetch(1, 2, 3)), (Some(7), Command::Fetch(1, 2, 3))));
+        assert!(matches!(
+            round_trip(Command::SetIsolationLevel(IsolationLevel::ReadCommitted)),
+            (Some(7), Command::SetIsolationLevel(IsolationLevel::ReadCommitted))
+        ));
+        assert!(matches!(round_trip(Command::CommitTransaction), (Some(7), Command::CommitTransaction)));
+    }
+
+    #[test]
+    fn client_codec_encode_test_02() {
+        let mut buf = BytesMut::new();
+        let mut codec = ClientCodec::default();
+
+        // embedded line terminator would split message into two commands
+        let command = Command::PublishMessage(msg(b"hello\nworld\n"));
+        assert!(matches!(codec.encode((None, command), &mut buf), Err(Error::InvalidArgument(_))));
+        assert!(matches!(codec.encode((None, Command::InvalidCommand), &mut buf), Err(Error::InvalidArgument(_))));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn server_codec_decode_test_01() {
+        let mut codec = ServerCodec::default();
+        let mut buf = BytesMut::from(&b"*2\nhello\n"[..]);
+
+        // batch isn't complete yet
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        buf.extend_from_slice(b"world\n<\n");
+        assert!(matches!(codec.decode(&mut buf).unwrap(), Some((None, Command::PublishBatch(_)))));
+        assert!(matches!(codec.decode(&mut buf).unwrap(), Some((None, Command::ReadMessage))));
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        buf.extend_from_slice(b"<");
+        assert!(matches!(codec.decode_eof(&mut buf).unwrap(), Some((None, Command::InvalidCommand))));
+        assert!(codec.decode_eof(&mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn server_codec_decode_test_03() {
+        let mut codec = ServerCodec::default();
+        let mut buf = BytesMut::new();
+
+        // batch arriving a few bytes at a time is scanned only once
+        let batch = b"*3\nhello\nbig\nworld";
+        for chunk in batch.chunks(2) {
+            buf.extend_from_slice(chunk);
+            assert!(codec.decode(&mut buf).unwrap().is_none());
+        }
+        assert_eq!(codec.scan.scanned, batch.len());
+
+        buf.extend_from_slice(b"\n>foo\n");
+
+        match codec.decode(&mut buf).unwrap() {
+            Some((None, Command::PublishBatch(msgs))) => assert_eq!(msgs.len(), 3),
+            _ => panic!("command should have been decoded as Command::PublishBatch"),
+        }
+        assert!(matches!(codec.decode(&mut buf).unwrap(), Some((None, Command::PublishMessage(_)))));
+        assert_eq!(codec.scan.scanned, 0);
+    }
+
+    #[test]
+    fn server_codec_decode_test_02() {
+        let mut codec = ServerCodec::new(8);
+
+        let mut buf = BytesMut::from(&b">hello"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        buf.extend_from_slice(b" world");
+        assert!(matches!(codec.decode(&mut buf), Err(Error::FrameTooLarge(8))));
+    }
+
+    #[test]
+    fn client_codec_decode_test_01() {
+        let responses = vec![
+            (Some(1), Response::Positive("hello".to_owned())),
+            (None, Response::Negative("None".to_owned())),
+            (Some(2), Response::Messages(vec![Message::new(Bytes::from_static(b"hello\n"), Some(5)), Message::new(Bytes::from_static(b"world\n"), Some(6))])),
+            (None, Response::Messages(vec![])),
+        ];
+
+        let mut buf = BytesMut::new();
+        for response in responses {
+            ServerCodec::default().encode(response, &mut buf).unwrap();
+        }
+
+        let mut codec = ClientCodec::default();
+        match codec.decode(&mut buf).unwrap() {
+            Some((Some(1), Response::Positive(data))) => assert_eq!(data, "hello"),
+            _ => panic!("response should have been decoded as Response::Positive"),
+        }
+        match codec.decode(&mut buf).unwrap() {
+            Some((None, Response::Negative(data))) => assert_eq!(data, "None"),
+            _ => panic!("response should have been decoded as Response::Negative"),
+        }
+        match codec.decode(&mut buf).unwrap() {
+            Some((Some(2), Response::Messages(msgs))) => {
+                assert_eq!(msgs.iter().map(|msg| *msg.offset().unwrap()).collect::<Vec<_>>(), vec![5, 6]);
+                assert_eq!(msgs[1].value(), &Bytes::from_static(b"world\n"));
+            }
+            _ => panic!("response should have been decoded as Response::Messages"),
+        }
+        assert!(matches!(codec.decode(&mut buf).unwrap(), Some((None, Response::Messages(msgs))) if msgs.is_empty()));
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        let mut buf = BytesMut::from(&b"hello\n"[..]);
+        assert!(matches!(codec.decode(&mut buf), Err(Error::Protocol(_))));
+    }
+
+    #[tokio::test]
+    async fn framed_duplex_test_01() {
+        let (client, server) = tokio::io::duplex(64);
+        let mut client = Framed::new(client, ClientCodec::default());
+        let mut server = Framed::new(server, ServerCodec::default());
+
+        client.send((Some(1), Command::SelectTopic("foo".parse().unwrap()))).await.unwrap();
+        client.send((None, Command::Fetch(0, 10, 1024))).await.unwrap();
+
+        match server.next().await.unwrap().unwrap() {
+            (Some(1), Command::SelectTopic(topic)) => assert_eq!(topic.as_str(), "foo"),
+            _ => panic!("command should have been decoded as Command::SelectTopic"),
+        }
+        assert!(matches!(server.next().await.unwrap().unwrap(), (None, Command::Fetch(0, 10, 1024))));
+
+        server.send((Some(1), Response::Positive(String::new()))).await.unwrap();
+        server.send((None, Response::Messages(vec![Message::new(Bytes::from_static(b"hello\n"), Some(0))]))).await.unwrap();
+        drop(server);
+
+        assert!(matches!(client.next().await.unwrap().unwrap(), (Some(1), Response::Positive(data)) if data.is_empty()));
+        assert!(matches!(client.next().await.unwrap().unwrap(), (None, Response::Messages(msgs)) if msgs.len() == 1));
+        assert!(client.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn framed_read_test_01() {
+        let data: &[u8] = b"^1 @foo\n\xff<\n";
+        let mut commands = FramedRead::new(data, ServerCodec::default());
+
+        assert!(matches!(commands.next().await.unwrap().unwrap(), (Some(1), Command::SelectTopic(_))));
+        assert!(matches!(commands.next().await.unwrap().unwrap(), (None, Command::InvalidCommand)));
+        assert!(commands.next().await.is_none());
+    }
+}
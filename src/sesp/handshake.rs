@@ -0,0 +1,158 @@
+use crate::error::Error;
+
+/// latest SESP version spoken by this implementation
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// oldest SESP version this implementation can still talk
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// optional protocol features supported by this implementation
+pub const CAPABILITIES: &[&str] = &["topic_config", "error_codes", "request_ids", "publish_batch", "fetch", "idempotence", "transactions", "auth", "acl", "ping", "list_topics", "timestamps"];
+
+/// `Hello` command sent by client to start the handshake
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hello {
+    version: u32,
+    client_name: String,
+    capabilities: Vec<String>,
+}
+
+/// outcome of a successful handshake
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handshake {
+    version: u32,
+    client_name: String,
+    capabilities: Vec<String>,
+}
+
+impl Hello {
+    pub fn new(version: u32, client_name: String, capabilities: Vec<String>) -> Self {
+        Self {
+            version,
+            client_name,
+            capabilities,
+        }
+    }
+
+    /// parses `<version> <client_name>[ <capability>[,<capability>]*]`
+    pub(crate) fn parse(data: &[u8]) -> Option<Self> {
+        let data = std::str::from_utf8(data).ok()?;
+        let mut tokens = data.split(' ').filter(|token| !token.is_empty());
+
+        let version = tokens.next()?.parse().ok()?;
+        let client_name = tokens.next()?.to_owned();
+        let capabilities = match tokens.next() {
+            Some(capabilities) => capabilities
+                .split(',')
+                .filter(|capability| !capability.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            None => vec![],
+        };
+
+        if tokens.next().is_some() {
+            return None;
+        }
+
+        Some(Self::new(version, client_name, capabilities))
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn client_name(&self) -> &str {
+        &self.client_name
+    }
+
+    pub fn capabilities(&self) -> &[String] {
+        &self.capabilities
+    }
+
+    /// picks the highest version both sides speak and enables requested
+    /// capabilities that are supported by server
+    pub fn negotiate(self) -> Result<Handshake, Error> {
+        if self.version < MIN_PROTOCOL_VERSION {
+            return Err(Error::UnsupportedVersion(self.version));
+        }
+
+        let capabilities = self
+            .capabilities
+            .into_iter()
+            .filter(|capability| CAPABILITIES.contains(&capability.as_str()))
+            .collect();
+
+        Ok(Handshake {
+            version: self.version.min(PROTOCOL_VERSION),
+            client_name: self.client_name,
+            capabilities,
+        })
+    }
+}
+
+impl Handshake {
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn client_name(&self) -> &str {
+        &self.client_name
+    }
+
+    /// capabilities requested by client and supported by server
+    pub fn capabilities(&self) -> &[String] {
+        &self.capabilities
+    }
+
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|enabled| enabled == capability)
+    }
+
+    /// data of positive response to `Hello`: `<version> <capability>[,<capability>]*`
+    /// listing every capability supported by server
+    pub fn response_data(&self) -> String {
+        format!("{} {}", self.version, CAPABILITIES.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hello_parse_test_01() {
+        let hello = Hello::parse(b"1 my-client topic_config,no_such_capability").unwrap();
+
+        assert_eq!(hello.version(), 1);
+        assert_eq!(hello.client_name(), "my-client");
+        assert_eq!(hello.capabilities(), &["topic_config".to_owned(), "no_such_capability".to_owned()]);
+
+        let handshake = hello.negotiate().unwrap();
+        assert_eq!(handshake.version(), 1);
+        assert!(handshake.has_capability("topic_config"));
+        assert!(!handshake.has_capability("no_such_capability"));
+    }
+
+    #[test]
+    fn hello_parse_test_02() {
+        let hello = Hello::parse(b"7 my-client").unwrap();
+        assert!(hello.capabilities().is_empty());
+
+        // newer clients are downgraded to latest version known to server
+        assert_eq!(hello.negotiate().unwrap().version(), PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn hello_parse_test_03() {
+        assert!(Hello::parse(b"").is_none());
+        assert!(Hello::parse(b"1").is_none());
+        assert!(Hello::parse(b"one my-client").is_none());
+        assert!(Hello::parse(b"1 my-client a,b extra").is_none());
+    }
+
+    #[test]
+    fn hello_negotiate_test_01() {
+        let hello = Hello::new(0, "my-client".to_owned(), vec![]);
+        assert!(matches!(hello.negotiate(), Err(Error::UnsupportedVersion(0))));
+    }
+}
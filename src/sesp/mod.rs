@@ -1,26 +1,109 @@
-use std::error::Error;
+mod codec;
+mod handshake;
+
+pub use self::codec::{ClientCodec, ServerCodec};
+pub use self::handshake::{Handshake, Hello, CAPABILITIES, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
 
 use bytes::Bytes;
-use tokio::net::TcpStream;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::codec::{FramedRead, FramedWrite};
+use tracing::{debug, Instrument};
 
-use crate::types::Message;
+use crate::acl::AclRule;
+use crate::error::Error;
+use crate::metrics::Metrics;
+use crate::topic::IsolationLevel;
+use crate::types::{Message, TopicName, TopicNameError};
 
-#[derive(Debug)]
+/// `key=value` options passed along with topic configuration commands
+pub type ConfigOptions = Vec<(String, String)>;
+
+/// optional client chosen id of a request, echoed back on its response
+pub type RequestId = u64;
+
+/// max number of messages in a single `PublishBatch`
+pub const MAX_BATCH_LEN: usize = 4096;
+
+/// credentials carried by `Auth` command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Credentials {
+    Password { username: String, password: String },
+    /// static API token
+    Token(String),
+}
+
+/// max size in bytes of a single frame unless configured otherwise, a
+/// `PublishBatch` is a single frame including all of its messages
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+/// max number of commands read ahead of the one being processed
+const READ_AHEAD: usize = 64;
+
+/// max number of responses waiting to be written to the socket
+const WRITE_BEHIND: usize = 64;
+
+#[derive(Debug, Clone)]
 pub enum Command {
-    CreateTopic(Bytes),
-    DeleteTopic(Bytes),
-    SelectTopic(Bytes),
+    Hello(Hello),
+    /// keepalive, answered with `+PONG`
+    Ping,
+    Auth(Credentials),
+    CreateTopic(TopicName, ConfigOptions),
+    AlterTopicConfig(TopicName, ConfigOptions),
+    DeleteTopic(TopicName),
+    ListTopics,
+    DescribeTopic(TopicName),
+    SelectTopic(TopicName),
     SetReadOffset(usize),
     ReadMessage,
+    /// start offset, max number of messages and max number of bytes to read
+    Fetch(usize, usize, usize),
+    /// unix timestamp in milliseconds
+    OffsetForTimestamp(u64),
     PublishMessage(Message),
+    PublishBatch(Vec<Message>),
+    /// producer id, producer's sequence number of the message and the message
+    PublishSequenced(u64, u64, Message),
+    BeginTransaction,
+    CommitTransaction,
+    AbortTransaction,
+    /// message buffered in ongoing transaction to be written to given topic
+    TransactionalPublish(TopicName, Message),
+    SetIsolationLevel(IsolationLevel),
+    GrantAcl(AclRule),
+    RevokeAcl(AclRule),
+    ListAcls,
     InvalidCommand,
+    /// well formed command carrying a topic name that failed validation
+    InvalidTopicName(TopicNameError),
 }
 
 
+/// server side of a SESP connection.
+///
+/// commands are read ahead and responses are written back by background
+/// tasks, so a client can pipeline many commands without waiting for
+/// responses and the caller never waits on socket I/O between commands.
+/// commands are still handed out, and must be answered, one at a time in
+/// the order they were received.
 #[derive(Debug)]
 pub struct Connection {
-    stream: BufReader<TcpStream>,
+    commands: mpsc::Receiver<Result<(Option<RequestId>, Command), Error>>,
+    responses: mpsc::Sender<(Option<RequestId>, Response)>,
+    pending_request_ids: VecDeque<Option<RequestId>>,
+    reader_task: JoinHandle<()>,
+    writer_task: JoinHandle<()>,
+    handshake: Option<Handshake>,
+    received_command: bool,
+    idle_timeout: Option<Duration>,
+    metrics: Option<Arc<Metrics>>,
 }
 
 
@@ -28,6 +111,8 @@ pub struct Connection {
 pub enum Response {
     Positive(String),
     Negative(String),
+    /// consecutive messages, each message must carry its offset
+    Messages(Vec<Message>),
 }
 
 
@@ -36,8 +121,12 @@ impl From<Bytes> for Command {
         if value.len() < 2 {
             return Self::InvalidCommand;
         }
+
+        if value.first() == Some(&b'*') {
+            return parse_publish_batch(value).unwrap_or(Self::InvalidCommand);
+        }
         
-        let action_byte = *value.get(0).unwrap();
+        let action_byte = *value.first().unwrap();
         let data_end = value.len() - 1;
 
         if value.get(data_end).unwrap() != &b'\n' {
@@ -48,40 +137,604 @@ impl From<Bytes> for Command {
         let data = value.slice(1..data_end);
 
         match action_byte {
-            b'#' => Self::CreateTopic(data),
-            b'!' => Self::DeleteTopic(data),
-            b'@' => Self::SelectTopic(data),
+            b'.' if data.is_empty() => Self::Ping,
+            b'?' => match Hello::parse(&data) {
+                Some(hello) => Self::Hello(hello),
+                None => Self::InvalidCommand,
+            },
+            b':' => match parse_auth(&data) {
+                Some(credentials) => Self::Auth(credentials),
+                None => Self::InvalidCommand,
+            },
+            b'#' => match parse_topic_with_config_options(&data) {
+                Some((Ok(topic), options)) => Self::CreateTopic(topic, options),
+                Some((Err(e), _)) => Self::InvalidTopicName(e),
+                None => Self::InvalidCommand,
+            },
+            b'%' => match parse_topic_with_config_options(&data) {
+                Some((Ok(topic), options)) if !options.is_empty() => Self::AlterTopicConfig(topic, options),
+                Some((Err(e), options)) if !options.is_empty() => Self::InvalidTopicName(e),
+                _ => Self::InvalidCommand,
+            },
+            b'!' => match TopicName::try_from(&data[..]) {
+                Ok(topic) => Self::DeleteTopic(topic),
+                Err(e) => Self::InvalidTopicName(e),
+            },
+            b'[' if data.is_empty() => Self::ListTopics,
+            b']' => match TopicName::try_from(&data[..]) {
+                Ok(topic) => Self::DescribeTopic(topic),
+                Err(e) => Self::InvalidTopicName(e),
+            },
+            b'@' => match TopicName::try_from(&data[..]) {
+                Ok(topic) => Self::SelectTopic(topic),
+                Err(e) => Self::InvalidTopicName(e),
+            },
             b'>' => Self::PublishMessage(Message::from(value.slice(1..))),
+            b'=' => parse_publish_sequenced(&value).unwrap_or(Self::InvalidCommand),
             b'$' => {
-                if let Ok(offset) = String::from_utf8_lossy(&data.to_vec()).parse::<usize>() {
+                if let Ok(offset) = String::from_utf8_lossy(&data).parse::<usize>() {
                     Self::SetReadOffset(offset)
                 } else {
                     Self::InvalidCommand
                 }
             },
-            b'<' if data.len() == 0 => Self::ReadMessage,
+            b'<' if data.is_empty() => Self::ReadMessage,
+            b'&' => match parse_fetch(&data) {
+                Some((offset, max_messages, max_bytes)) => Self::Fetch(offset, max_messages, max_bytes),
+                None => Self::InvalidCommand,
+            },
+            b'\'' => match std::str::from_utf8(&data).ok().and_then(|timestamp| timestamp.parse().ok()) {
+                Some(timestamp) => Self::OffsetForTimestamp(timestamp),
+                None => Self::InvalidCommand,
+            },
+            b'{' if data.is_empty() => Self::BeginTransaction,
+            b'}' if data.is_empty() => Self::CommitTransaction,
+            b'~' if data.is_empty() => Self::AbortTransaction,
+            b';' => match data.iter().position(|byte| *byte == b' ') {
+                Some(end) => match TopicName::try_from(&data[..end]) {
+                    Ok(topic) => Self::TransactionalPublish(topic, Message::from(value.slice(end + 2..))),
+                    Err(e) => Self::InvalidTopicName(e),
+                },
+                None => Self::InvalidCommand,
+            },
+            b'|' => match &data[..] {
+                b"read_uncommitted" => Self::SetIsolationLevel(IsolationLevel::ReadUncommitted),
+                b"read_committed" => Self::SetIsolationLevel(IsolationLevel::ReadCommitted),
+                _ => Self::InvalidCommand,
+            },
+            b'/' => match &data[..] {
+                b"list" => Self::ListAcls,
+                _ => parse_acl(&data).unwrap_or(Self::InvalidCommand),
+            },
             _ => Self::InvalidCommand,
         }
     }
 }
 
+impl Command {
+    /// name of command's variant, as used in README and metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Hello(_) => "Hello",
+            Self::Ping => "Ping",
+            Self::Auth(_) => "Auth",
+            Self::CreateTopic(..) => "CreateTopic",
+            Self::AlterTopicConfig(..) => "AlterTopicConfig",
+            Self::DeleteTopic(_) => "DeleteTopic",
+            Self::ListTopics => "ListTopics",
+            Self::DescribeTopic(_) => "DescribeTopic",
+            Self::SelectTopic(_) => "SelectTopic",
+            Self::SetReadOffset(_) => "SetReadOffset",
+            Self::ReadMessage => "ReadMessage",
+            Self::Fetch(..) => "Fetch",
+            Self::OffsetForTimestamp(_) => "OffsetForTimestamp",
+            Self::PublishMessage(_) => "PublishMessage",
+            Self::PublishBatch(_) => "PublishBatch",
+            Self::PublishSequenced(..) => "PublishSequenced",
+            Self::BeginTransaction => "BeginTransaction",
+            Self::CommitTransaction => "CommitTransaction",
+            Self::AbortTransaction => "AbortTransaction",
+            Self::TransactionalPublish(..) => "TransactionalPublish",
+            Self::SetIsolationLevel(_) => "SetIsolationLevel",
+            Self::GrantAcl(_) => "GrantAcl",
+            Self::RevokeAcl(_) => "RevokeAcl",
+            Self::ListAcls => "ListAcls",
+            Self::InvalidCommand => "InvalidCommand",
+            Self::InvalidTopicName(_) => "InvalidTopicName",
+        }
+    }
+
+    /// capability a client that did a handshake must have negotiated to send command
+    pub fn capability(&self) -> Option<&'static str> {
+        match self {
+            Self::Ping => Some("ping"),
+            Self::Auth(_) => Some("auth"),
+            Self::CreateTopic(_, options) if !options.is_empty() => Some("topic_config"),
+            Self::AlterTopicConfig(..) => Some("topic_config"),
+            Self::ListTopics | Self::DescribeTopic(_) => Some("list_topics"),
+            Self::Fetch(..) => Some("fetch"),
+            Self::OffsetForTimestamp(_) => Some("timestamps"),
+            Self::PublishBatch(_) => Some("publish_batch"),
+            Self::PublishSequenced(..) => Some("idempotence"),
+            Self::BeginTransaction
+            | Self::CommitTransaction
+            | Self::AbortTransaction
+            | Self::TransactionalPublish(..)
+            | Self::SetIsolationLevel(_) => Some("transactions"),
+            Self::GrantAcl(_) | Self::RevokeAcl(_) | Self::ListAcls => Some("acl"),
+            _ => None,
+        }
+    }
+
+    /// encodes command as sent by a client, fails for commands that can't be
+    /// sent (`InvalidCommand`, `InvalidTopicName`) and for messages that
+    /// contain a `\n` anywhere but at the end.
+    pub fn as_vec_of_u8(self) -> Result<Vec<u8>, Error> {
+        let mut ans = vec![];
+        match self {
+            Self::Ping => ans.push(b'.'),
+            Self::Hello(hello) => {
+                ans.extend_from_slice(format!("?{} {}", hello.version(), hello.client_name()).as_bytes());
+                if !hello.capabilities().is_empty() {
+                    ans.extend_from_slice(format!(" {}", hello.capabilities().join(",")).as_bytes());
+                }
+            }
+            Self::Auth(Credentials::Password { username, password }) => {
+                ans.extend_from_slice(format!(":password {username} {password}").as_bytes())
+            }
+            Self::Auth(Credentials::Token(token)) => ans.extend_from_slice(format!(":token {token}").as_bytes()),
+            Self::CreateTopic(topic, options) => encode_topic_with_config_options(&mut ans, b'#', &topic, &options),
+            Self::AlterTopicConfig(topic, options) => encode_topic_with_config_options(&mut ans, b'%', &topic, &options),
+            Self::DeleteTopic(topic) => ans.extend_from_slice(format!("!{topic}").as_bytes()),
+            Self::ListTopics => ans.push(b'['),
+            Self::DescribeTopic(topic) => ans.extend_from_slice(format!("]{topic}").as_bytes()),
+            Self::SelectTopic(topic) => ans.extend_from_slice(format!("@{topic}").as_bytes()),
+            Self::SetReadOffset(offset) => ans.extend_from_slice(format!("${offset}").as_bytes()),
+            Self::ReadMessage => ans.push(b'<'),
+            Self::Fetch(offset, max_messages, max_bytes) => {
+                ans.extend_from_slice(format!("&{offset} {max_messages} {max_bytes}").as_bytes())
+            }
+            Self::OffsetForTimestamp(timestamp) => ans.extend_from_slice(format!("'{timestamp}").as_bytes()),
+            Self::PublishMessage(msg) => {
+                ans.push(b'>');
+                encode_message(&mut ans, &msg)?;
+            }
+            Self::PublishBatch(msgs) => {
+                ans.extend_from_slice(format!("*{}\n", msgs.len()).as_bytes());
+                for msg in msgs.iter() {
+                    encode_message(&mut ans, msg)?;
+                }
+            }
+            Self::PublishSequenced(producer_id, sequence, msg) => {
+                ans.extend_from_slice(format!("={producer_id} {sequence} ").as_bytes());
+                encode_message(&mut ans, &msg)?;
+            }
+            Self::BeginTransaction => ans.push(b'{'),
+            Self::CommitTransaction => ans.push(b'}'),
+            Self::AbortTransaction => ans.push(b'~'),
+            Self::TransactionalPublish(topic, msg) => {
+                ans.extend_from_slice(format!(";{topic} ").as_bytes());
+                encode_message(&mut ans, &msg)?;
+            }
+            Self::SetIsolationLevel(IsolationLevel::ReadUncommitted) => ans.extend_from_slice(b"|read_uncommitted"),
+            Self::SetIsolationLevel(IsolationLevel::ReadCommitted) => ans.extend_from_slice(b"|read_committed"),
+            Self::GrantAcl(rule) => ans.extend_from_slice(format!("/grant {rule}").as_bytes()),
+            Self::RevokeAcl(rule) => ans.extend_from_slice(format!("/revoke {rule}").as_bytes()),
+            Self::ListAcls => ans.extend_from_slice(b"/list"),
+            Self::InvalidCommand | Self::InvalidTopicName(_) => {
+                return Err(Error::InvalidArgument("invalid command can't be encoded".to_owned()))
+            }
+        }
+
+        if !ans.ends_with(b"\n") {
+            ans.push(b'\n');
+        }
+
+        Ok(ans)
+    }
+}
+
+fn encode_topic_with_config_options(ans: &mut Vec<u8>, action_byte: u8, topic: &TopicName, options: &ConfigOptions) {
+    ans.push(action_byte);
+    ans.extend_from_slice(topic.as_str().as_bytes());
+    for (key, value) in options.iter() {
+        ans.extend_from_slice(format!(" {key}={value}").as_bytes());
+    }
+}
+
+/// appends message terminated by `\n`
+fn encode_message(ans: &mut Vec<u8>, msg: &Message) -> Result<(), Error> {
+    let value = msg.value().strip_suffix(b"\n").unwrap_or(msg.value());
+    if value.contains(&b'\n') {
+        return Err(Error::InvalidArgument("message can't contain a line terminator".to_owned()));
+    }
+
+    ans.extend_from_slice(value);
+    ans.push(b'\n');
+
+    Ok(())
+}
+
+/// number of lines following `first_line` that belong to the same frame,
+/// that is `N` for a `*N` `PublishBatch` header and 0 for everything else.
+///
+/// fails for a `PublishBatch` header with an invalid `N`, as there is no
+/// telling where its frame ends lines following it must not be mistaken for
+/// commands.
+fn continuation_lines(first_line: &[u8]) -> Result<usize, Error> {
+    let command = match first_line.first() {
+        Some(b'^') => match first_line.iter().position(|byte| *byte == b' ') {
+            Some(end) => &first_line[end + 1..],
+            None => return Ok(0),
+        },
+        _ => first_line,
+    };
+
+    match command.split_first() {
+        Some((b'*', header)) => parse_batch_len(header.strip_suffix(b"\n").unwrap_or(header))
+            .ok_or_else(|| Error::Protocol(format!("invalid PublishBatch header, expected 1 to {MAX_BATCH_LEN} messages"))),
+        _ => Ok(0),
+    }
+}
+
+fn parse_batch_len(data: &[u8]) -> Option<usize> {
+    std::str::from_utf8(data)
+        .ok()?
+        .parse()
+        .ok()
+        .filter(|len| (1..=MAX_BATCH_LEN).contains(len))
+}
+
+/// parses `*<N>\n` header followed by exactly N messages, one per line
+fn parse_publish_batch(value: Bytes) -> Option<Command> {
+    let header_end = value.iter().position(|byte| *byte == b'\n')?;
+    let batch_len = parse_batch_len(&value[1..header_end])?;
+
+    let mut msgs = Vec::with_capacity(batch_len);
+    let mut start = header_end + 1;
+    while start < value.len() {
+        let end = start + value[start..].iter().position(|byte| *byte == b'\n')? + 1;
+        msgs.push(Message::from(value.slice(start..end)));
+        start = end;
+    }
+
+    (msgs.len() == batch_len).then_some(Command::PublishBatch(msgs))
+}
+
+/// parses `=<producer_id> <sequence> <message>\n`
+fn parse_publish_sequenced(value: &Bytes) -> Option<Command> {
+    let mut start = 1;
+    let mut next_number = || {
+        let end = start + value[start..].iter().position(|byte| *byte == b' ')?;
+        let number = std::str::from_utf8(&value[start..end]).ok()?.parse().ok()?;
+        start = end + 1;
+
+        Some(number)
+    };
+
+    let producer_id = next_number()?;
+    let sequence = next_number()?;
+
+    Some(Command::PublishSequenced(producer_id, sequence, Message::from(value.slice(start..))))
+}
+
+/// parses `password <username> <password>` or `token <token>`, password may
+/// contain spaces
+fn parse_auth(data: &[u8]) -> Option<Credentials> {
+    let data = std::str::from_utf8(data).ok()?;
+
+    match data.split_once(' ')? {
+        ("password", credentials) => match credentials.split_once(' ')? {
+            (username, password) if !username.is_empty() && !password.is_empty() => Some(Credentials::Password {
+                username: username.to_owned(),
+                password: password.to_owned(),
+            }),
+            _ => None,
+        },
+        ("token", token) if !token.is_empty() && !token.contains(' ') => Some(Credentials::Token(token.to_owned())),
+        _ => None,
+    }
+}
+
+/// parses `grant <operation> <topic pattern> <principal>` or the same for
+/// `revoke`, principal may contain spaces
+fn parse_acl(data: &[u8]) -> Option<Command> {
+    let data = std::str::from_utf8(data).ok()?;
+    let (action, rule) = data.split_once(' ')?;
+
+    let rule = rule.parse::<AclRule>().ok()?;
+    match action {
+        "grant" => Some(Command::GrantAcl(rule)),
+        "revoke" => Some(Command::RevokeAcl(rule)),
+        _ => None,
+    }
+}
+
+/// parses `<offset> <max_messages> <max_bytes>`
+fn parse_fetch(data: &[u8]) -> Option<(usize, usize, usize)> {
+    let data = std::str::from_utf8(data).ok()?;
+    let mut tokens = data.split(' ').map(|token| token.parse::<usize>().ok());
+
+    match (tokens.next()??, tokens.next()??, tokens.next()??, tokens.next()) {
+        (offset, max_messages, max_bytes, None) => Some((offset, max_messages, max_bytes)),
+        _ => None,
+    }
+}
+
+/// parses `<topic>[ <key>=<value>]*` returning `None` if data is malformed
+fn parse_topic_with_config_options(data: &Bytes) -> Option<(Result<TopicName, TopicNameError>, ConfigOptions)> {
+    let data = std::str::from_utf8(data).ok()?;
+    let mut tokens = data.split(' ').filter(|token| !token.is_empty());
+
+    let topic = tokens.next()?;
+    let options = tokens
+        .map(|token| match token.split_once('=') {
+            Some((key, value)) if !key.is_empty() && !value.is_empty() => Some((key.to_owned(), value.to_owned())),
+            _ => None,
+        })
+        .collect::<Option<ConfigOptions>>()?;
+
+    Some((TopicName::try_from(topic), options))
+}
+
+/// splits optional `^<request_id> ` prefix from a command line
+fn parse_request(line: Bytes) -> (Option<RequestId>, Command) {
+    if line.first() != Some(&b'^') {
+        return (None, Command::from(line));
+    }
+
+    let request_id = line
+        .iter()
+        .position(|byte| *byte == b' ')
+        .and_then(|end| Some((std::str::from_utf8(&line[1..end]).ok()?.parse().ok()?, end)));
+
+    match request_id {
+        Some((request_id, end)) => (Some(request_id), Command::from(line.slice(end + 1..))),
+        None => (None, Command::InvalidCommand),
+    }
+}
+
+async fn read_commands<R: AsyncRead>(
+    mut commands_stream: FramedRead<ReadHalf<R>, ServerCodec>,
+    commands: mpsc::Sender<Result<(Option<RequestId>, Command), Error>>,
+) {
+    while let Some(request) = commands_stream.next().await {
+        let request = request.map_err(|e| match e {
+            // codec never touches disk, any I/O error comes from socket
+            Error::Storage(e) => Error::Connection(e),
+            e => e,
+        });
+
+        if let Err(ref e) = request {
+            debug!(error = %e, "failed to read command");
+        }
+
+        let is_err = request.is_err();
+        if commands.send(request).await.is_err() || is_err {
+            return;
+        }
+    }
+}
+
+async fn write_responses<W: AsyncWrite>(
+    mut responses_sink: FramedWrite<WriteHalf<W>, ServerCodec>,
+    mut responses: mpsc::Receiver<(Option<RequestId>, Response)>,
+) {
+    while let Some(response) = responses.recv().await {
+        if let Err(e) = responses_sink.feed(response).await {
+            return debug!(error = %e, "failed to write response");
+        }
+
+        // batch writes of pipelined responses into as few syscalls as possible
+        if responses.is_empty() {
+            if let Err(e) = responses_sink.flush().await {
+                return debug!(error = %e, "failed to write response");
+            }
+        }
+    }
+
+    let _ = responses_sink.close().await; // ignore result
+}
+
 impl Connection {
-    pub fn new(stream: TcpStream) -> Self {
-        Self { stream: BufReader::new(stream) }
+    pub fn new<S: AsyncRead + AsyncWrite + Send + 'static>(stream: S) -> Self {
+        Self::with_max_frame_size(stream, DEFAULT_MAX_FRAME_SIZE)
     }
 
-    pub async fn read_command(&mut self) -> Result<Command, Box<dyn Error>> {
-        let mut line = String::new();
-        self.stream.read_line(&mut line).await?;
+    /// frames longer than `max_frame_size` bytes are answered with
+    /// `-FrameTooLarge` after which connection is closed
+    pub fn with_max_frame_size<S: AsyncRead + AsyncWrite + Send + 'static>(stream: S, max_frame_size: usize) -> Self {
+        let (read_half, write_half) = tokio::io::split(stream);
+        let (commands_sender, commands) = mpsc::channel(READ_AHEAD);
+        let (responses, responses_recvr) = mpsc::channel(WRITE_BEHIND);
+
+        let codec = ServerCodec::new(max_frame_size);
+        // both tasks log within span of whoever created the connection
+        let reader_task = tokio::spawn(read_commands(FramedRead::new(read_half, codec.clone()), commands_sender).in_current_span());
+        let writer_task = tokio::spawn(write_responses(FramedWrite::new(write_half, codec), responses_recvr).in_current_span());
+
+        Self {
+            commands,
+            responses,
+            pending_request_ids: VecDeque::new(),
+            reader_task,
+            writer_task,
+            handshake: None,
+            received_command: false,
+            idle_timeout: None,
+            metrics: None,
+        }
+    }
 
-        Ok(Command::from(Bytes::from(line)))
+    /// outcome of handshake, `None` if client skipped it
+    pub fn handshake(&self) -> Option<&Handshake> {
+        self.handshake.as_ref()
     }
 
-    pub async fn write_response(&mut self, response: Response) -> Result<(), Box<dyn Error>> {
-        self.stream.write_all(&response.as_vec_of_u8()).await?;
+    /// `read_command` fails with `ErrorKind::TimedOut` once peer sends no
+    /// command for `idle_timeout`, `None` waits forever
+    pub fn set_idle_timeout(&mut self, idle_timeout: Option<Duration>) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// records commands answered by connection itself (e.g. `Ping`) and
+    /// their responses into `metrics`, rest are for caller to record
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// answers a command handled by connection itself, `command` is `None` for malformed frames
+    async fn answer(&mut self, command: Option<&'static str>, response: Response) -> Result<(), Error> {
+        if let Some(ref metrics) = self.metrics {
+            if let Some(command) = command {
+                metrics.record_command(command);
+            }
+            metrics.record_response(&response);
+        }
+
+        self.write_response(response).await
+    }
+
+    /// returns `Ok(None)` once peer has closed the connection.
+    ///
+    /// `Hello` is answered here and never returned to the caller, it is only
+    /// accepted as the very first command of a connection. `Ping` is
+    /// answered here as well, any time.
+    ///
+    /// an oversized frame, or a `PublishBatch` header that doesn't tell where
+    /// its frame ends, is answered here as well. `Err(Error::FrameTooLarge)` or
+    /// `Err(Error::Protocol)` is returned after which caller must close the
+    /// connection since rest of the stream can't be framed reliably.
+    pub async fn read_command(&mut self) -> Result<Option<Command>, Error> {
+        loop {
+            let request = match self.idle_timeout {
+                Some(idle_timeout) => tokio::time::timeout(idle_timeout, self.commands.recv()).await.map_err(|_| {
+                    debug!(?idle_timeout, "connection idle for too long");
+                    Error::Connection(std::io::ErrorKind::TimedOut.into())
+                })?,
+                None => self.commands.recv().await,
+            };
+
+            let (request_id, command) = match request {
+                Some(Err(Error::FrameTooLarge(max_frame_size))) => {
+                    self.pending_request_ids.push_back(None);
+                    self.answer(None, Response::from(Error::FrameTooLarge(max_frame_size))).await?;
+                    return Err(Error::FrameTooLarge(max_frame_size));
+                }
+                Some(Err(Error::Protocol(reason))) => {
+                    self.pending_request_ids.push_back(None);
+                    self.answer(None, Response::from(Error::Protocol(reason.clone()))).await?;
+                    return Err(Error::Protocol(reason));
+                }
+                Some(request) => request?,
+                None => return Ok(None),
+            };
+
+            if let Err(e) = self.check_capabilities(request_id, &command) {
+                // a client that didn't ask for request ids can't match a prefixed response
+                let request_id = request_id.filter(|_| self.handshake.as_ref().is_none_or(|handshake| handshake.has_capability("request_ids")));
+                self.pending_request_ids.push_back(request_id);
+                self.answer(Some(command.name()), Response::from(e)).await?;
+                continue;
+            }
+
+            self.pending_request_ids.push_back(request_id);
+            if let Command::Ping = command {
+                self.answer(Some(command.name()), Response::Positive("PONG".to_owned())).await?;
+                continue;
+            }
+
+            let received_command = std::mem::replace(&mut self.received_command, true);
+
+            if let Command::Hello(hello) = command {
+                let response = match self.negotiate(hello, received_command) {
+                    Ok(handshake) => {
+                        debug!(version = handshake.version(), client_name = handshake.client_name(), "completed handshake");
+                        Response::Positive(handshake.response_data())
+                    }
+                    Err(e) => Response::from(e),
+                };
+                self.answer(Some("Hello"), response).await?;
+
+                continue;
+            }
+
+            return Ok(Some(command));
+        }
+    }
+
+    /// once client did a handshake it may only use capabilities it negotiated,
+    /// clients that skipped it may use every capability
+    fn check_capabilities(&self, request_id: Option<RequestId>, command: &Command) -> Result<(), Error> {
+        let handshake = match self.handshake {
+            Some(ref handshake) => handshake,
+            None => return Ok(()),
+        };
+
+        let capabilities = request_id.map(|_| "request_ids").into_iter().chain(command.capability());
+        for capability in capabilities {
+            if !handshake.has_capability(capability) {
+                return Err(Error::Protocol(format!("capability `{capability}` was not negotiated")));
+            }
+        }
 
         Ok(())
     }
+
+    fn negotiate(&mut self, hello: Hello, received_command: bool) -> Result<&Handshake, Error> {
+        if received_command {
+            return Err(Error::Protocol("Hello must be the first command of a connection".to_owned()));
+        }
+
+        Ok(self.handshake.insert(hello.negotiate()?))
+    }
+
+    /// queues response to the oldest command not yet answered, response
+    /// carries the same request id (if any) as the command.
+    pub async fn write_response(&mut self, response: Response) -> Result<(), Error> {
+        let request_id = self.pending_request_ids.pop_front().flatten();
+
+        self.responses
+            .send((request_id, response))
+            .await
+            .map_err(|_| Error::Connection(std::io::ErrorKind::BrokenPipe.into()))
+    }
+
+    /// answers with `-ShuttingDown`, without a request id, then closes the
+    /// connection. client reads it as response to the next command it sent
+    /// (if any), neither that command nor any following one was processed.
+    pub async fn shutdown(mut self) {
+        let _ = self.write_response(Response::from(Error::ShuttingDown)).await; // ignore result
+        self.close().await;
+    }
+
+    /// stops reading commands and returns once every queued response has
+    /// been written (or writing failed)
+    pub async fn close(mut self) {
+        self.reader_task.abort();
+
+        // writer task exits once every sender of its channel is gone
+        let (responses, _) = mpsc::channel(1);
+        drop(std::mem::replace(&mut self.responses, responses));
+        let _ = (&mut self.writer_task).await; // ignore result
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // writer task exits by itself after writing queued responses
+        self.reader_task.abort();
+    }
+}
+
+impl From<Error> for Response {
+    /// negative response of the form `-<Code>[:<argument>] <description>`
+    fn from(value: Error) -> Self {
+        match value.argument() {
+            Some(argument) => Self::Negative(format!("{}:{argument} {value}", value.code())),
+            None => Self::Negative(format!("{} {value}", value.code())),
+        }
+    }
 }
 
 impl Response {
@@ -96,9 +749,23 @@ impl Response {
                 ans.push(b'+');
                 ans.extend_from_slice(data.as_bytes());
             },
+            Self::Messages(msgs) => {
+                // `*<N> <offset of first message>\n` followed by N messages one per line
+                match msgs.first().and_then(|msg| msg.offset()) {
+                    Some(offset) => ans.extend_from_slice(format!("*{} {offset}\n", msgs.len()).as_bytes()),
+                    None => ans.extend_from_slice(b"*0\n"),
+                }
+
+                for msg in msgs.iter() {
+                    ans.extend_from_slice(msg.value());
+                    if !msg.value().ends_with(b"\n") {
+                        ans.push(b'\n');
+                    }
+                }
+            },
         }
 
-        if !ans.ends_with(&[b'\n']) {
+        if !ans.ends_with(b"\n") {
             ans.push(b'\n');
         }
 
@@ -109,26 +776,61 @@ impl Response {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpStream;
 
     #[test]
     fn create_topic_command_from_bytes_test() {
         let data = Bytes::from_static(b"#foo\n");
         let cmd = Command::from(data);
 
-        if let Command::CreateTopic(topic_name) = cmd {
-            assert_eq!(topic_name, Bytes::from_static(b"foo"));
+        if let Command::CreateTopic(topic_name, options) = cmd {
+            assert_eq!(topic_name.as_str(), "foo");
+            assert!(options.is_empty());
         } else {
             panic!("command should have been parsed as Command::CreateTopic");
         }
     }
 
+    #[test]
+    fn create_topic_with_config_options_command_from_bytes_test() {
+        let data = Bytes::from_static(b"#foo num_of_msg_per_file=64 durability=fsync\n");
+        let cmd = Command::from(data);
+
+        if let Command::CreateTopic(topic_name, options) = cmd {
+            assert_eq!(topic_name.as_str(), "foo");
+            assert_eq!(
+                options,
+                vec![
+                    ("num_of_msg_per_file".to_owned(), "64".to_owned()),
+                    ("durability".to_owned(), "fsync".to_owned()),
+                ]
+            );
+        } else {
+            panic!("command should have been parsed as Command::CreateTopic");
+        }
+    }
+
+    #[test]
+    fn alter_topic_config_command_from_bytes_test() {
+        let data = Bytes::from_static(b"%foo retention_ms=3600000\n");
+        let cmd = Command::from(data);
+
+        if let Command::AlterTopicConfig(topic_name, options) = cmd {
+            assert_eq!(topic_name.as_str(), "foo");
+            assert_eq!(options, vec![("retention_ms".to_owned(), "3600000".to_owned())]);
+        } else {
+            panic!("command should have been parsed as Command::AlterTopicConfig");
+        }
+    }
+
     #[test]
     fn delete_topic_command_from_bytes_test() {
         let data = Bytes::from_static(b"!foo\n");
         let cmd = Command::from(data);
 
         if let Command::DeleteTopic(topic_name) = cmd {
-            assert_eq!(topic_name, Bytes::from_static(b"foo"));
+            assert_eq!(topic_name.as_str(), "foo");
         } else {
             panic!("command should have been parsed as Command::DeleteTopic");
         }
@@ -140,7 +842,7 @@ mod tests {
         let cmd = Command::from(data);
 
         if let Command::SelectTopic(topic_name) = cmd {
-            assert_eq!(topic_name, Bytes::from_static(b"foo"));
+            assert_eq!(topic_name.as_str(), "foo");
         } else {
             panic!("command should have been parsed as Command::SelectTopic");
         }
@@ -182,6 +884,169 @@ mod tests {
         }
     }
 
+    #[test]
+    fn publish_sequenced_command_from_bytes_test() {
+        let data = Bytes::from_static(b"=7 42 hello world\n");
+        let cmd = Command::from(data);
+
+        if let Command::PublishSequenced(producer_id, sequence, msg) = cmd {
+            assert_eq!((producer_id, sequence), (7, 42));
+            assert_eq!(msg, Message::from(Bytes::from_static(b"hello world\n")));
+        } else {
+            panic!("command should have been parsed as Command::PublishSequenced");
+        }
+
+        for data in [&b"=7 hello\n"[..], b"=7\n", b"=x 42 hello\n", b"=7 -1 hello\n"] {
+            if !matches!(Command::from(Bytes::copy_from_slice(data)), Command::InvalidCommand) {
+                panic!("command should have been parsed as Command::InvalidCommand");
+            }
+        }
+    }
+
+    #[test]
+    fn auth_command_from_bytes_test() {
+        let cmd = Command::from(Bytes::from_static(b":password alice correct horse\n"));
+        if let Command::Auth(Credentials::Password { username, password }) = cmd {
+            assert_eq!((username.as_str(), password.as_str()), ("alice", "correct horse"));
+        } else {
+            panic!("command should have been parsed as Command::Auth");
+        }
+
+        let cmd = Command::from(Bytes::from_static(b":token s3cr3t\n"));
+        assert!(matches!(cmd, Command::Auth(Credentials::Token(token)) if token == "s3cr3t"));
+
+        for data in [&b":password alice\n"[..], b":token\n", b":token a b\n", b":certificate x\n", b":password  secret\n"] {
+            if !matches!(Command::from(Bytes::copy_from_slice(data)), Command::InvalidCommand) {
+                panic!("command should have been parsed as Command::InvalidCommand");
+            }
+        }
+    }
+
+    #[test]
+    fn transaction_commands_from_bytes_test() {
+        assert!(matches!(Command::from(Bytes::from_static(b"{\n")), Command::BeginTransaction));
+        assert!(matches!(Command::from(Bytes::from_static(b"}\n")), Command::CommitTransaction));
+        assert!(matches!(Command::from(Bytes::from_static(b"~\n")), Command::AbortTransaction));
+
+        let data = Bytes::from_static(b";foo hello world\n");
+        let cmd = Command::from(data);
+
+        if let Command::TransactionalPublish(topic, msg) = cmd {
+            assert_eq!(topic.as_str(), "foo");
+            assert_eq!(msg, Message::from(Bytes::from_static(b"hello world\n")));
+        } else {
+            panic!("command should have been parsed as Command::TransactionalPublish");
+        }
+
+        assert!(matches!(Command::from(Bytes::from_static(b";f/o hello\n")), Command::InvalidTopicName(_)));
+
+        for data in [&b"{1\n"[..], b";foo\n"] {
+            if !matches!(Command::from(Bytes::copy_from_slice(data)), Command::InvalidCommand) {
+                panic!("command should have been parsed as Command::InvalidCommand");
+            }
+        }
+    }
+
+    #[test]
+    fn set_isolation_level_command_from_bytes_test() {
+        assert!(matches!(
+            Command::from(Bytes::from_static(b"|read_committed\n")),
+            Command::SetIsolationLevel(IsolationLevel::ReadCommitted)
+        ));
+        assert!(matches!(
+            Command::from(Bytes::from_static(b"|read_uncommitted\n")),
+            Command::SetIsolationLevel(IsolationLevel::ReadUncommitted)
+        ));
+        assert!(matches!(Command::from(Bytes::from_static(b"|serializable\n")), Command::InvalidCommand));
+    }
+
+    #[test]
+    fn acl_commands_from_bytes_test() {
+        let rule = AclRule::new("CN=alice, O=acme".to_owned(), crate::acl::AclOperation::Publish, "orders.*".to_owned().try_into().unwrap());
+
+        assert!(matches!(
+            Command::from(Bytes::from_static(b"/grant publish orders.* CN=alice, O=acme\n")),
+            Command::GrantAcl(cmd_rule) if cmd_rule == rule
+        ));
+        assert!(matches!(
+            Command::from(Bytes::from_static(b"/revoke publish orders.* CN=alice, O=acme\n")),
+            Command::RevokeAcl(cmd_rule) if cmd_rule == rule
+        ));
+        assert!(matches!(Command::from(Bytes::from_static(b"/list\n")), Command::ListAcls));
+        assert_eq!(Command::GrantAcl(rule).as_vec_of_u8().unwrap(), b"/grant publish orders.* CN=alice, O=acme\n");
+
+        for data in [&b"/grant read foo alice\n"[..], b"/grant publish f/o alice\n", b"/grant publish foo\n", b"/allow publish foo alice\n"] {
+            if !matches!(Command::from(Bytes::copy_from_slice(data)), Command::InvalidCommand) {
+                panic!("command should have been parsed as Command::InvalidCommand");
+            }
+        }
+    }
+
+    #[test]
+    fn topic_metadata_commands_from_bytes_test() {
+        assert!(matches!(Command::from(Bytes::from_static(b"[\n")), Command::ListTopics));
+        assert!(matches!(Command::from(Bytes::from_static(b"]foo\n")), Command::DescribeTopic(topic) if topic.as_str() == "foo"));
+        assert!(matches!(Command::from(Bytes::from_static(b"'1760000000000\n")), Command::OffsetForTimestamp(1760000000000)));
+        assert_eq!(Command::OffsetForTimestamp(1760000000000).as_vec_of_u8().unwrap(), b"'1760000000000\n");
+
+        assert!(matches!(Command::from(Bytes::from_static(b"]../etc\n")), Command::InvalidTopicName(_)));
+        for data in [&b"[foo\n"[..], b"'\n", b"'-1\n", b"'1 2\n"] {
+            if !matches!(Command::from(Bytes::copy_from_slice(data)), Command::InvalidCommand) {
+                panic!("command should have been parsed as Command::InvalidCommand");
+            }
+        }
+    }
+
+    #[test]
+    fn fetch_command_from_bytes_test() {
+        let data = Bytes::from_static(b"&1001 100 65536\n");
+        let cmd = Command::from(data);
+
+        if let Command::Fetch(offset, max_messages, max_bytes) = cmd {
+            assert_eq!((offset, max_messages, max_bytes), (1001, 100, 65536));
+        } else {
+            panic!("command should have been parsed as Command::Fetch");
+        }
+
+        for data in [&b"&1001 100\n"[..], b"&1001 100 65536 1\n", b"&1001  100 65536\n", b"&a 100 65536\n"] {
+            if !matches!(Command::from(Bytes::copy_from_slice(data)), Command::InvalidCommand) {
+                panic!("command should have been parsed as Command::InvalidCommand");
+            }
+        }
+    }
+
+    #[test]
+    fn publish_batch_command_from_bytes_test() {
+        let data = Bytes::from_static(b"*2\nhello\nworld\n");
+        let cmd = Command::from(data);
+
+        if let Command::PublishBatch(msgs) = cmd {
+            assert_eq!(
+                msgs,
+                vec![
+                    Message::from(Bytes::from_static(b"hello\n")),
+                    Message::from(Bytes::from_static(b"world\n")),
+                ]
+            );
+        } else {
+            panic!("command should have been parsed as Command::PublishBatch");
+        }
+    }
+
+    #[test]
+    fn continuation_lines_test() {
+        assert_eq!(continuation_lines(b"*3\n").unwrap(), 3);
+        assert_eq!(continuation_lines(b"^7 *3\n").unwrap(), 3);
+        assert_eq!(continuation_lines(b">*3\n").unwrap(), 0);
+        assert_eq!(continuation_lines(b"^x *3\n").unwrap(), 3); // payload is skipped along with malformed command
+
+        // lines following an invalid header can't be framed
+        assert!(matches!(continuation_lines(b"*0\n"), Err(Error::Protocol(_))));
+        assert!(matches!(continuation_lines(b"*x\n"), Err(Error::Protocol(_))));
+        assert!(matches!(continuation_lines(b"^7 *\n"), Err(Error::Protocol(_))));
+        assert!(matches!(continuation_lines(format!("*{}\n", MAX_BATCH_LEN + 1).as_bytes()), Err(Error::Protocol(_))));
+    }
+
     #[test]
     fn invalid_command_test_01() {
         let data = Bytes::from_static(b"inavlid_bytes");
@@ -260,6 +1125,346 @@ mod tests {
         }
     }
 
+    #[test]
+    fn invalid_command_test_07() {
+        let data = Bytes::from_static(b"#foo num_of_segments\n");
+
+        let cmd = Command::from(data);
+
+        if let Command::InvalidCommand = cmd {
+            // pass
+        } else {
+            panic!("command should have been parsed as Command::InvalidCommand");
+        }
+    }
+
+    #[test]
+    fn invalid_command_test_08() {
+        let data = Bytes::from_static(b"%foo\n"); // nothing to alter
+
+        let cmd = Command::from(data);
+
+        if let Command::InvalidCommand = cmd {
+            // pass
+        } else {
+            panic!("command should have been parsed as Command::InvalidCommand");
+        }
+    }
+
+    #[test]
+    fn invalid_command_test_09() {
+        for data in [&b"*0\n"[..], b"*2\nhello\n", b"*1\nhello\nworld\n", b"*x\nhello\n", b"*1\nhello"] {
+            let cmd = Command::from(Bytes::copy_from_slice(data));
+
+            if let Command::InvalidCommand = cmd {
+                // pass
+            } else {
+                panic!("command should have been parsed as Command::InvalidCommand");
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_topic_name_test_01() {
+        for data in [&b"#../../etc\n"[..], b"!/etc\n", b"@..\n", b"@__internal\n", b"%foo/bar retention_ms=1\n"] {
+            let cmd = Command::from(Bytes::copy_from_slice(data));
+
+            if let Command::InvalidTopicName(_) = cmd {
+                // pass
+            } else {
+                panic!("command should have been parsed as Command::InvalidTopicName");
+            }
+        }
+    }
+
+    #[test]
+    fn hello_command_from_bytes_test() {
+        let data = Bytes::from_static(b"?1 my-client topic_config\n");
+        let cmd = Command::from(data);
+
+        if let Command::Hello(hello) = cmd {
+            assert_eq!(hello, Hello::new(1, "my-client".to_owned(), vec!["topic_config".to_owned()]));
+        } else {
+            panic!("command should have been parsed as Command::Hello");
+        }
+    }
+
+    #[tokio::test]
+    async fn connection_handshake_test_01() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let mut connection = Connection::new(listener.accept().await.unwrap().0);
+
+        client.write_all(b"?1 my-client topic_config,foo\n<\n?1 my-client\n").await.unwrap();
+
+        assert!(matches!(connection.read_command().await, Ok(Some(Command::ReadMessage))));
+        assert_eq!(connection.handshake().unwrap().capabilities(), &["topic_config".to_owned()]);
+
+        client.shutdown().await.unwrap();
+        assert!(matches!(connection.read_command().await, Ok(None)));
+
+        let mut responses = String::new();
+        BufReader::new(client).read_line(&mut responses).await.unwrap();
+        assert_eq!(responses, format!("+1 {}\n", CAPABILITIES.join(",")));
+    }
+
+    #[tokio::test]
+    async fn connection_capabilities_test_01() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let mut connection = Connection::new(listener.accept().await.unwrap().0);
+
+        // capabilities not negotiated in handshake are rejected without reaching the caller
+        client.write_all(b"?1 my-client topic_config,fetch
+&0 1 1
+^3 <
+'1000
+.
+<
+").await.unwrap();
+        assert!(matches!(connection.read_command().await, Ok(Some(Command::Fetch(0, 1, 1)))));
+        connection.write_response(Response::Messages(vec![])).await.unwrap();
+        assert!(matches!(connection.read_command().await, Ok(Some(Command::ReadMessage))));
+        connection.write_response(Response::Negative("None".to_owned())).await.unwrap();
+
+        let mut client = BufReader::new(client);
+        let mut responses = String::new();
+        for _ in 0..6 {
+            client.read_line(&mut responses).await.unwrap();
+        }
+        assert_eq!(
+            responses,
+            format!(
+                "+1 {}\n*0\n-ProtocolError protocol error: capability `request_ids` was not negotiated\n\
+                 -ProtocolError protocol error: capability `timestamps` was not negotiated\n\
+                 -ProtocolError protocol error: capability `ping` was not negotiated\n-None\n",
+                CAPABILITIES.join(",")
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn connection_ping_test_01() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let mut connection = Connection::new(listener.accept().await.unwrap().0);
+        let metrics = Arc::new(Metrics::new());
+        connection.set_metrics(metrics.clone());
+
+        // ping neither reaches the caller nor counts as first command of connection
+        client.write_all(b".\n^7 .\n?1 my-client\n<\n").await.unwrap();
+        assert!(matches!(connection.read_command().await, Ok(Some(Command::ReadMessage))));
+        connection.write_response(Response::Negative("None".to_owned())).await.unwrap();
+
+        let mut client = BufReader::new(client);
+        let mut responses = String::new();
+        for _ in 0..4 {
+            client.read_line(&mut responses).await.unwrap();
+        }
+        assert_eq!(responses, format!("+PONG\n^7 +PONG\n+1 {}\n-None\n", CAPABILITIES.join(",")));
+        // commands answered by connection itself are counted as well
+        let rendered = metrics.render();
+        assert!(rendered.contains("stream_relay_commands_total{command=\"Ping\"} 2\n"));
+        assert!(rendered.contains("stream_relay_commands_total{command=\"Hello\"} 1\n"));
+        assert_eq!(Command::Ping.as_vec_of_u8().unwrap(), b".\n");
+    }
+
+    #[tokio::test]
+    async fn connection_idle_timeout_test_01() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let mut connection = Connection::new(listener.accept().await.unwrap().0);
+        connection.set_idle_timeout(Some(Duration::from_millis(100)));
+
+        client.write_all(b"<\n").await.unwrap();
+        assert!(matches!(connection.read_command().await, Ok(Some(Command::ReadMessage))));
+
+        match connection.read_command().await {
+            Err(Error::Connection(e)) => assert_eq!(e.kind(), std::io::ErrorKind::TimedOut),
+            _ => panic!("read_command should have timed out"),
+        }
+    }
+
+    #[tokio::test]
+    async fn connection_without_handshake_test_01() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let mut connection = Connection::new(listener.accept().await.unwrap().0);
+
+        client.write_all(b"<\n?1 my-client\n").await.unwrap();
+        client.shutdown().await.unwrap();
+
+        assert!(matches!(connection.read_command().await, Ok(Some(Command::ReadMessage))));
+        assert!(matches!(connection.read_command().await, Ok(None)));
+        assert!(connection.handshake().is_none());
+
+        let mut responses = String::new();
+        BufReader::new(client).read_line(&mut responses).await.unwrap();
+        assert!(responses.starts_with("-ProtocolError "));
+    }
+
+    #[test]
+    fn parse_request_test_01() {
+        let (request_id, cmd) = parse_request(Bytes::from_static(b"^42 >hello world\n"));
+
+        assert_eq!(request_id, Some(42));
+        if let Command::PublishMessage(msg) = cmd {
+            assert_eq!(msg, Message::from(Bytes::from_static(b"hello world\n")));
+        } else {
+            panic!("command should have been parsed as Command::PublishMessage");
+        }
+    }
+
+    #[test]
+    fn parse_request_test_02() {
+        for data in [&b"^x <\n"[..], b"^<\n", b"^ <\n", b"^-1 <\n"] {
+            let (request_id, cmd) = parse_request(Bytes::copy_from_slice(data));
+
+            assert_eq!(request_id, None);
+            assert!(matches!(cmd, Command::InvalidCommand));
+        }
+    }
+
+    #[tokio::test]
+    async fn connection_pipelining_test_01() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let mut connection = Connection::new(listener.accept().await.unwrap().0);
+
+        client.write_all(b"^1 $5\n<\n^3 <\n").await.unwrap();
+        client.shutdown().await.unwrap();
+
+        for data in ["5", "hello\n", "world\n"] {
+            connection.read_command().await.unwrap().unwrap();
+            connection.write_response(Response::Positive(data.to_owned())).await.unwrap();
+        }
+        assert!(matches!(connection.read_command().await, Ok(None)));
+        drop(connection);
+
+        let mut responses = String::new();
+        tokio::io::AsyncReadExt::read_to_string(&mut client, &mut responses).await.unwrap();
+        assert_eq!(responses, "^1 +5\n+hello\n^3 +world\n");
+    }
+
+    #[tokio::test]
+    async fn connection_frame_too_large_test_01() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let mut connection = Connection::with_max_frame_size(listener.accept().await.unwrap().0, 16);
+
+        // second frame never ends, it must be rejected without being buffered
+        client.write_all(b">hello\n").await.unwrap();
+        client.write_all(&[b'a'; 64]).await.unwrap();
+
+        assert!(matches!(connection.read_command().await, Ok(Some(Command::PublishMessage(_)))));
+        connection.write_response(Response::Positive("0".to_owned())).await.unwrap();
+        assert!(matches!(connection.read_command().await, Err(Error::FrameTooLarge(16))));
+        drop(connection);
+
+        let mut responses = String::new();
+        tokio::io::AsyncReadExt::read_to_string(&mut client, &mut responses).await.unwrap();
+        assert_eq!(responses, "+0\n-FrameTooLarge frame exceeds max size of 16 bytes\n");
+    }
+
+    #[tokio::test]
+    async fn connection_frame_too_large_test_02() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let mut connection = Connection::with_max_frame_size(listener.accept().await.unwrap().0, 16);
+
+        // limit applies to whole `PublishBatch` frame, not to each of its lines
+        client.write_all(b"*3\nhello\nworld\nagain\n").await.unwrap();
+
+        assert!(matches!(connection.read_command().await, Err(Error::FrameTooLarge(16))));
+    }
+
+    #[tokio::test]
+    async fn connection_invalid_batch_header_test_01() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let mut connection = Connection::new(listener.accept().await.unwrap().0);
+
+        // payload of a batch with an invalid header must never be run as commands
+        client.write_all(b"*0\n!orders\n").await.unwrap();
+
+        assert!(matches!(connection.read_command().await, Err(Error::Protocol(_))));
+        drop(connection);
+
+        let mut responses = String::new();
+        tokio::io::AsyncReadExt::read_to_string(&mut client, &mut responses).await.unwrap();
+        assert_eq!(responses, "-ProtocolError protocol error: invalid PublishBatch header, expected 1 to 4096 messages\n");
+    }
+
+    #[tokio::test]
+    async fn connection_non_utf8_test_01() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let mut connection = Connection::new(listener.accept().await.unwrap().0);
+
+        client.write_all(b">\xff\xfe\n@\xff\n").await.unwrap();
+        client.shutdown().await.unwrap();
+
+        match connection.read_command().await {
+            Ok(Some(Command::PublishMessage(msg))) => assert_eq!(msg.value(), &Bytes::from_static(b"\xff\xfe\n")),
+            _ => panic!("command should have been parsed as Command::PublishMessage"),
+        }
+        assert!(matches!(connection.read_command().await, Ok(Some(Command::InvalidTopicName(_)))));
+        assert!(matches!(connection.read_command().await, Ok(None)));
+    }
+
+    mod proptests {
+        use super::*;
+        use proptest::prelude::*;
+
+        proptest! {
+            #[test]
+            fn command_from_arbitrary_bytes_test(data in prop::collection::vec(any::<u8>(), 0..512)) {
+                let _ = Command::from(Bytes::from(data.clone()));
+                let _ = parse_request(Bytes::from(data.clone()));
+                let _ = continuation_lines(&data);
+            }
+
+            #[test]
+            fn command_from_arbitrary_line_test(action_byte: u8, data in "[^\n]{0,256}") {
+                let mut line = vec![action_byte];
+                line.extend_from_slice(data.as_bytes());
+                line.push(b'\n');
+
+                let _ = Command::from(Bytes::from(line));
+            }
+
+            #[test]
+            fn publish_message_round_trip_test(data in prop::collection::vec(any::<u8>().prop_filter("no newline", |byte| *byte != b'\n'), 0..256)) {
+                let mut line = vec![b'>'];
+                line.extend_from_slice(&data);
+                line.push(b'\n');
+
+                match Command::from(Bytes::from(line.clone())) {
+                    Command::PublishMessage(msg) => prop_assert_eq!(&msg.value()[..], &line[1..]),
+                    _ => prop_assert!(false, "command should have been parsed as Command::PublishMessage"),
+                }
+            }
+
+            #[test]
+            fn fetch_round_trip_test(offset: usize, max_messages: usize, max_bytes: usize) {
+                let cmd = Command::from(Bytes::from(format!("&{offset} {max_messages} {max_bytes}\n")));
+
+                match cmd {
+                    Command::Fetch(a, b, c) => prop_assert_eq!((a, b, c), (offset, max_messages, max_bytes)),
+                    _ => prop_assert!(false, "command should have been parsed as Command::Fetch"),
+                }
+            }
+
+            #[test]
+            fn parse_request_round_trip_test(request_id: u64, offset: usize) {
+                let (parsed_request_id, cmd) = parse_request(Bytes::from(format!("^{request_id} ${offset}\n")));
+
+                prop_assert_eq!(parsed_request_id, Some(request_id));
+                prop_assert!(matches!(cmd, Command::SetReadOffset(parsed_offset) if parsed_offset == offset));
+            }
+        }
+    }
+
     #[test]
     fn test_positive_response() {
         let res = Response::Positive("hello".to_owned());
@@ -271,4 +1476,32 @@ mod tests {
         let res = Response::Negative("hello".to_owned());
         assert_eq!(res.as_vec_of_u8(), b"-hello\n");
     }
+
+    #[test]
+    fn test_messages_response() {
+        let res = Response::Messages(vec![
+            Message::new(Bytes::from_static(b"hello\n"), Some(5)),
+            Message::new(Bytes::from_static(b"world\n"), Some(6)),
+        ]);
+        assert_eq!(res.as_vec_of_u8(), b"*2 5\nhello\nworld\n");
+
+        let res = Response::Messages(vec![]);
+        assert_eq!(res.as_vec_of_u8(), b"*0\n");
+    }
+
+    #[test]
+    fn test_error_response() {
+        let res = Response::from(Error::AlreadyExists("foo".parse().unwrap()));
+        assert_eq!(res.as_vec_of_u8(), b"-AlreadyExists topic `foo` already exists\n");
+
+        let res = Response::from(Error::NotSupported);
+        assert_eq!(res.as_vec_of_u8(), b"-NotSupported not supported\n");
+
+        let res = Response::from(Error::DuplicateSequence { sequence: 42, offset: 1001 });
+        assert_eq!(res.as_vec_of_u8(), b"-DuplicateSequence:1001 sequence 42 was already written at offset 1001\n");
+
+        let e = crate::types::TopicConfigError::NotSupported { key: "retention_ms".to_owned(), value: "1000".to_owned() };
+        let res = Response::from(Error::InvalidTopicConfig(e));
+        assert_eq!(res.as_vec_of_u8(), b"-NotSupported value `1000` for config key `retention_ms` is not supported yet\n");
+    }
 }
@@ -1,12 +1,11 @@
-use std::error::Error;
-
 use async_trait::async_trait;
 use tokio::fs::DirBuilder;
 use tokio::io::AsyncWriteExt;
 
+use crate::error::Error;
 use crate::types::TopicMetaData;
 
-#[async_trait(?Send)]
+#[async_trait]
 pub trait TopicCreator {
     type Error;
 
@@ -21,9 +20,15 @@ impl SimpleDiskTopicCreator {
     }
 }
 
-#[async_trait(?Send)]
+impl Default for SimpleDiskTopicCreator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
 impl TopicCreator for SimpleDiskTopicCreator {
-    type Error = Box<dyn Error>;
+    type Error = Error;
 
     async fn create_topic(&self, metadata: TopicMetaData) -> Result<(), Self::Error> {
         let mut builder = DirBuilder::new();
@@ -58,7 +63,7 @@ mod tests {
     async fn simple_disk_topic_creator_test_01() {
         let root_path = "./simple_disk_topic_creator_test_01";
 
-        let topic = Topic::new("foo".to_owned(), root_path);
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
         let topic_metadata = TopicMetaData::new_with_few_defaults(topic);
 
         let simple_disk_topic_creator = SimpleDiskTopicCreator::new();
@@ -1,13 +1,20 @@
 mod creator;
 mod reader;
+mod registry;
+mod transaction;
 mod writer;
 
 pub use self::creator::{SimpleDiskTopicCreator, TopicCreator};
-pub use self::reader::{SimpleDiskTopicReader, TopicReader};
+pub use self::reader::{IsolationLevel, SimpleDiskTopicReader, TopicReader};
+pub use self::registry::TopicRegistry;
+pub use self::transaction::{ControlRecord, Transaction, TransactionState, CONTROL_RECORD_PREFIX, MAX_TRANSACTION_LEN};
 pub use self::writer::{SimpleDiskTopicWriter, TopicWriter};
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use tokio::io::AsyncWriteExt;
+
+use crate::error::Error;
 use crate::types::TopicMetaData;
 
 pub(crate) fn offset_to_file_path(topic_metadata: &TopicMetaData, offset: &usize) -> Box<Path> {
@@ -25,8 +32,31 @@ pub(crate) fn offset_to_file_path(topic_metadata: &TopicMetaData, offset: &usize
         .into_boxed_path()
 }
 
+/// replaces content of file at `path` with `data` through a temporary file,
+/// so that a crash leaves either old or new content behind and never a
+/// partial one. with `sync` both are forced to stable storage before returning.
+pub(crate) async fn write_atomically(path: &Path, data: &[u8], sync: bool) -> Result<(), Error> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut file = tokio::fs::File::create(&tmp_path).await?;
+    file.write_all(data).await?;
+    if sync {
+        file.sync_all().await?;
+    }
+    drop(file);
+
+    tokio::fs::rename(&tmp_path, path).await?;
+    if let (true, Some(dir_path)) = (sync, path.parent()) {
+        tokio::fs::File::open(dir_path).await?.sync_all().await?;
+    }
+
+    Ok(())
+}
+
 #[allow(dead_code)] // used in testcases
-struct TempTopicCreator<T: AsRef<Path>>(T);
+pub(crate) struct TempTopicCreator<T: AsRef<Path>>(pub(crate) T);
 
 #[allow(dead_code)] // used in testcases
 impl<T: AsRef<Path>> TempTopicCreator<T> {
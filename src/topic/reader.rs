@@ -1,19 +1,47 @@
-use std::{
-    error::Error,
-    time::{Duration, SystemTime},
-};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use serde::{Deserialize, Serialize};
 use tokio::io::AsyncBufReadExt;
+use tracing::{debug, instrument, trace};
 
+use crate::error::Error;
 use crate::types::{Message, TopicMetaData};
 
+use super::transaction::{ControlRecord, TransactionLog, TransactionState, MAX_TRANSACTION_LEN};
+
 #[async_trait]
 pub trait TopicReader {
     type Error;
 
+    /// reads first message visible to reader at or after `offset`, returned
+    /// message carries its actual offset which is greater than `offset` if
+    /// there were control records or hidden messages in between.
     async fn read(&mut self, offset: usize) -> Result<Option<Message>, Self::Error>;
+
+    /// reads consecutive messages starting at first message visible at or
+    /// after `offset`, stops after `max_messages` messages, before exceeding
+    /// `max_bytes` or before a gap in offsets whichever comes first. first
+    /// message is always returned even if it alone is larger than `max_bytes`.
+    /// returns an empty vec if there is no visible message at or after `offset`.
+    async fn read_range(&mut self, offset: usize, max_messages: usize, max_bytes: usize) -> Result<Vec<Message>, Self::Error>;
+}
+
+/// which messages written as part of a transaction are visible to a reader,
+/// messages written outside of transactions are always visible
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum IsolationLevel {
+    /// messages are visible as soon as they are flushed, even if their
+    /// transaction is later aborted
+    #[default]
+    ReadUncommitted,
+    /// messages are visible only once their transaction commits, messages
+    /// of aborted transactions are never visible. reading stops at the first
+    /// transaction that is still ongoing.
+    ReadCommitted,
 }
 
 pub struct SimpleDiskTopicReader {
@@ -22,23 +50,57 @@ pub struct SimpleDiskTopicReader {
     update_interval: Duration,
     min_time_for_next_update: SystemTime, // stored here as well to increase reader perf
     last_flushed_offset: Option<usize>,           // stored here as well to increase reader perf
+    isolation_level: IsolationLevel,
+    transaction_log: TransactionLog,
+    decided_transactions: HashMap<u64, TransactionState>,
+    resume_at: Option<(usize, usize)>, // offset where last scan stopped and hidden msgs remaining from there
 }
 
-pub struct CachedDiskTopicReader {}
+/// max time file modification times may lag behind the time they were written at
+const MODIFIED_TIME_SLACK: Duration = Duration::from_millis(20);
 
 impl SimpleDiskTopicReader {
     pub fn new(topic_metadata: TopicMetaData, last_updated: SystemTime, update_interval: Duration) -> Self {
         let last_flushed_offset = *topic_metadata.last_flushed_offset();
+        let root_path = topic_metadata.topic().path().parent().unwrap_or(std::path::Path::new(""));
+        let transaction_log = TransactionLog::new(root_path);
+
         Self {
             topic_metadata,
             last_updated,
             update_interval,
             min_time_for_next_update: last_updated + update_interval,
             last_flushed_offset,
+            isolation_level: IsolationLevel::default(),
+            transaction_log,
+            decided_transactions: HashMap::new(),
+            resume_at: None,
+        }
+    }
+
+    /// as of the last time topic's metadata was refreshed
+    pub fn last_flushed_offset(&self) -> Option<usize> {
+        self.last_flushed_offset
+    }
+
+    /// makes messages upto `last_flushed_offset` readable right away, e.g.
+    /// once told of a flush, instead of at next refresh of topic's metadata
+    pub fn observe_flush(&mut self, last_flushed_offset: Option<usize>) {
+        if last_flushed_offset > self.last_flushed_offset {
+            self.last_flushed_offset = last_flushed_offset;
         }
     }
 
-    pub async fn update_topics_metadata_info_if_changed(&mut self) -> Result<(), Box<dyn Error>> {
+    pub fn isolation_level(&self) -> IsolationLevel {
+        self.isolation_level
+    }
+
+    pub fn set_isolation_level(&mut self, isolation_level: IsolationLevel) {
+        self.isolation_level = isolation_level;
+        self.resume_at = None;
+    }
+
+    pub async fn update_topics_metadata_info_if_changed(&mut self) -> Result<(), Error> {
         let metadata_path = self.topic_metadata.topic().metadata_path();
         let last_metadata_modification_time = tokio::fs::metadata(metadata_path)
             .await?
@@ -53,53 +115,225 @@ impl SimpleDiskTopicReader {
             self.last_updated = last_metadata_modification_time;
             self.min_time_for_next_update = last_metadata_modification_time + self.update_interval;
             self.last_flushed_offset = *self.topic_metadata.last_flushed_offset();
+            trace!(topic = %self.topic_metadata.topic().name(), last_flushed_offset = ?self.last_flushed_offset, "refreshed topic metadata");
         }
 
         Ok(())
     }
-}
-
-#[async_trait]
-impl TopicReader for SimpleDiskTopicReader {
-    type Error = Box<dyn Error>;
 
-    async fn read(&mut self, offset: usize) -> Result<Option<Message>, Self::Error> {
+    /// looks for newly flushed messages if `offset` is beyond what reader knows about
+    async fn update_if_behind(&mut self, offset: usize) {
         if self.last_flushed_offset.is_none() || offset > self.last_flushed_offset.unwrap() && self.min_time_for_next_update < SystemTime::now() {
-            let _result = self.update_topics_metadata_info_if_changed().await; // ignore result
+            if let Err(e) = self.update_topics_metadata_info_if_changed().await {
+                debug!(topic = %self.topic_metadata.topic().name(), error = %e, "failed to refresh topic metadata");
+            }
+        }
+    }
+
+    /// first offset of the earliest segment file last written at or after
+    /// `timestamp`, reading from there never misses a message flushed at or
+    /// after `timestamp` but may return upto `num_of_msg_per_file - 1` older
+    /// ones. offset past last flushed message if there is no such file.
+    pub async fn offset_for_timestamp(&mut self, timestamp: SystemTime) -> Result<usize, Error> {
+        self.update_topics_metadata_info_if_changed().await?;
+
+        let last_flushed_offset = match self.last_flushed_offset {
+            Some(last_flushed_offset) => last_flushed_offset,
+            None => return Ok(0),
+        };
+
+        // file modification times lag behind `SystemTime::now()` a little
+        let timestamp = timestamp.checked_sub(MODIFIED_TIME_SLACK).unwrap_or(SystemTime::UNIX_EPOCH);
+
+        // files are written one after another, so their modification times never decrease
+        let num_of_msg_per_file = *self.topic_metadata.num_of_msg_per_file();
+        let (mut low, mut high) = (0, last_flushed_offset / num_of_msg_per_file + 1);
+        while low < high {
+            let file_number = (low + high) / 2;
+            let filepath = super::offset_to_file_path(&self.topic_metadata, &(file_number * num_of_msg_per_file));
+            let modified = match tokio::fs::metadata(filepath).await {
+                Ok(metadata) => metadata.modified()?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => SystemTime::UNIX_EPOCH,
+                Err(e) => return Err(e.into()),
+            };
+
+            match modified >= timestamp {
+                true => high = file_number,
+                false => low = file_number + 1,
+            }
         }
 
-        if self.last_flushed_offset.is_none() || offset > self.last_flushed_offset.unwrap() {
-            Ok(None)
-        } else {
-            let filepath = super::offset_to_file_path(&self.topic_metadata, &offset);
-            let line_number = offset % self.topic_metadata.num_of_msg_per_file();
+        Ok((low * num_of_msg_per_file).min(last_flushed_offset + 1))
+    }
+
+    /// opens file containing `offset` and skips lines upto `offset`
+    async fn open_at(&self, offset: usize) -> Result<tokio::io::BufReader<tokio::fs::File>, Error> {
+        let filepath = super::offset_to_file_path(&self.topic_metadata, &offset);
+        let line_number = offset % self.topic_metadata.num_of_msg_per_file();
+
+        let file = tokio::fs::File::open(filepath).await?;
+        let mut reader = tokio::io::BufReader::new(file);
+
+        let mut line = vec![];
+        for _ in 0..line_number {
+            reader.read_until(b'\n', &mut line).await?;
+            line.clear();
+        }
+
+        Ok(reader)
+    }
 
-            let file = tokio::fs::File::open(filepath).await?;
-            let mut reader = tokio::io::BufReader::new(file);
+    /// `None` if transaction's outcome is not yet decided
+    async fn transaction_outcome(&mut self, txn_id: u64) -> Result<Option<TransactionState>, Error> {
+        if let Some(state) = self.decided_transactions.get(&txn_id) {
+            return Ok(Some(*state));
+        }
 
-            let mut line = String::new();
-            for _ in 0..line_number {
-                let _ = reader.read_line(&mut line).await; // ignore result
-                line.clear();
+        match self.transaction_log.state(txn_id).await? {
+            TransactionState::Ongoing => Ok(None),
+            state => {
+                self.decided_transactions.insert(txn_id, state);
+                Ok(Some(state))
+            }
+        }
+    }
+
+    /// number of messages, starting at `offset`, hidden from a `read_committed`
+    /// reader because `offset` lies inside an aborted transaction. `None` if it
+    /// lies inside an undecided one.
+    ///
+    /// transactions are contiguous runs of atmost `MAX_TRANSACTION_LEN` messages,
+    /// so `BEGIN` record of a transaction enclosing `offset` is never further back.
+    async fn hidden_msgs_at(&mut self, offset: usize) -> Result<Option<usize>, Error> {
+        if let Some((resume_offset, hidden_msgs)) = self.resume_at {
+            if resume_offset == offset {
+                return Ok(Some(hidden_msgs));
+            }
+        }
+
+        let start = offset.saturating_sub(MAX_TRANSACTION_LEN);
+        let mut enclosing = None; // transaction and its remaining msgs at current offset
+        let mut reader = self.open_at(start).await?;
+
+        for current_offset in start..offset {
+            if current_offset != start && current_offset.is_multiple_of(*self.topic_metadata.num_of_msg_per_file()) {
+                reader = self.open_at(current_offset).await?;
             }
 
-            let _ = reader.read_line(&mut line).await; // ignore result
+            let mut line = vec![];
+            if reader.read_until(b'\n', &mut line).await? == 0 {
+                return Ok(Some(0));
+            }
 
-            let msg = Message::new(Bytes::from(line), Some(offset));
+            enclosing = match (ControlRecord::parse(&line), enclosing) {
+                (Some(ControlRecord::Begin { txn_id, count }), _) => Some((txn_id, count)),
+                (_, Some((txn_id, remaining))) if remaining > 1 => Some((txn_id, remaining - 1)),
+                _ => None,
+            };
+        }
 
-            Ok(Some(msg))
+        match enclosing {
+            Some((txn_id, remaining)) => match self.transaction_outcome(txn_id).await? {
+                Some(TransactionState::Aborted) => Ok(Some(remaining)),
+                Some(_) => Ok(Some(0)),
+                None => Ok(None),
+            },
+            None => Ok(Some(0)),
         }
     }
-}
 
-impl CachedDiskTopicReader {}
+    /// shared implementation of `read` and `read_range`, reads segment files
+    /// sequentially skipping over control records and hidden messages
+    #[instrument(level = "trace", skip(self), fields(topic = %self.topic_metadata.topic().name()))]
+    async fn scan(&mut self, offset: usize, max_messages: usize, max_bytes: usize) -> Result<Vec<Message>, Error> {
+        self.update_if_behind(offset).await;
+
+        let last_flushed_offset = match self.last_flushed_offset {
+            Some(last_flushed_offset) if offset <= last_flushed_offset && max_messages > 0 => last_flushed_offset,
+            _ => return Ok(vec![]),
+        };
+
+        // remaining msgs of an aborted transaction
+        let mut hidden_msgs = match self.isolation_level {
+            IsolationLevel::ReadCommitted => match self.hidden_msgs_at(offset).await? {
+                Some(hidden_msgs) => hidden_msgs,
+                None => return Ok(vec![]),
+            },
+            IsolationLevel::ReadUncommitted => 0,
+        };
+
+        let mut msgs = vec![];
+        let mut total_bytes = 0;
+        let mut next_offset = offset;
+        let mut reader = self.open_at(offset).await?;
+
+        for current_offset in offset..=last_flushed_offset {
+            if msgs.len() == max_messages {
+                break;
+            }
+
+            if current_offset != offset && current_offset.is_multiple_of(*self.topic_metadata.num_of_msg_per_file()) {
+                reader = self.open_at(current_offset).await?;
+            }
+
+            let mut line = vec![];
+            if reader.read_until(b'\n', &mut line).await? == 0 {
+                break;
+            }
+
+            if hidden_msgs > 0 {
+                hidden_msgs -= 1;
+                next_offset = current_offset + 1;
+                continue;
+            }
+
+            if let Some(record) = ControlRecord::parse(&line) {
+                if !msgs.is_empty() {
+                    break; // returned msgs must have consecutive offsets
+                }
+
+                if let (ControlRecord::Begin { txn_id, count }, IsolationLevel::ReadCommitted) = (record, self.isolation_level) {
+                    match self.transaction_outcome(txn_id).await? {
+                        Some(TransactionState::Aborted) => hidden_msgs = count,
+                        Some(_) => (),
+                        None => break, // nothing after an undecided transaction is visible yet
+                    }
+                }
+
+                next_offset = current_offset + 1;
+                continue;
+            }
+
+            total_bytes += line.len();
+            if !msgs.is_empty() && total_bytes > max_bytes {
+                break;
+            }
+
+            msgs.push(Message::new(Bytes::from(line), Some(current_offset)));
+            next_offset = current_offset + 1;
+        }
+
+        // consumers usually continue from where last read stopped, saves looking back for `BEGIN`
+        if self.isolation_level == IsolationLevel::ReadCommitted {
+            self.resume_at = Some((next_offset, hidden_msgs));
+        }
+
+        trace!(msgs = msgs.len(), total_bytes, "read messages");
+
+        Ok(msgs)
+    }
+}
 
 #[async_trait]
-impl TopicReader for CachedDiskTopicReader {
-    type Error = Box<dyn Error>;
+impl TopicReader for SimpleDiskTopicReader {
+    type Error = Error;
 
-    async fn read(&mut self, _offset: usize) -> Result<Option<Message>, Self::Error> {
-        todo!()
+    async fn read(&mut self, offset: usize) -> Result<Option<Message>, Self::Error> {
+        Ok(self.scan(offset, 1, usize::MAX).await?.pop())
+    }
+
+    async fn read_range(&mut self, offset: usize, max_messages: usize, max_bytes: usize) -> Result<Vec<Message>, Self::Error> {
+        self.scan(offset, max_messages, max_bytes).await
     }
 }
 
@@ -113,7 +347,7 @@ mod tests {
     async fn simple_disk_topic_reader_test_boiler_plate(root_path: &str, topic_metadata: TopicMetaData, num_of_msgs: usize) {
         let _temp_topic = TempTopicCreator::new(root_path, topic_metadata.clone()).await;
 
-        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await;
+        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await.unwrap();
         let mut simple_disk_topic_reader = SimpleDiskTopicReader::new(topic_metadata.clone(), SystemTime::now(), Duration::from_nanos(0));
 
         let mut written_msg = vec![];
@@ -140,7 +374,7 @@ mod tests {
     async fn simple_disk_topic_reader_test_01() {
         let root_path = "./simple_disk_topic_reader_test_01";
 
-        let topic = Topic::new("foo".to_owned(), root_path);
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
         let topic_metadata = TopicMetaData::new(topic, 3, None, 4);
 
         simple_disk_topic_reader_test_boiler_plate(root_path, topic_metadata, 1).await;
@@ -150,7 +384,7 @@ mod tests {
     async fn simple_disk_topic_reader_test_02() {
         let root_path = "./simple_disk_topic_reader_test_02";
 
-        let topic = Topic::new("foo".to_owned(), root_path);
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
         let topic_metadata = TopicMetaData::new(topic, 3, None, 4);
 
         simple_disk_topic_reader_test_boiler_plate(root_path, topic_metadata, 2).await;
@@ -160,7 +394,7 @@ mod tests {
     async fn simple_disk_topic_reader_test_03() {
         let root_path = "./simple_disk_topic_reader_test_03";
 
-        let topic = Topic::new("foo".to_owned(), root_path);
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
         let topic_metadata = TopicMetaData::new(topic, 3, None, 4);
 
         simple_disk_topic_reader_test_boiler_plate(root_path, topic_metadata, 3).await;
@@ -170,9 +404,169 @@ mod tests {
     async fn simple_disk_topic_reader_test_04() {
         let root_path = "./simple_disk_topic_reader_test_04";
 
-        let topic = Topic::new("foo".to_owned(), root_path);
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
         let topic_metadata = TopicMetaData::new(topic, 3, None, 4);
 
         simple_disk_topic_reader_test_boiler_plate(root_path, topic_metadata, 3).await;
     }
+
+    #[test]
+    async fn simple_disk_topic_reader_dot_read_range_test_01() {
+        let root_path = "./simple_disk_topic_reader_dot_read_range_test_01";
+
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
+        let topic_metadata = TopicMetaData::new(topic, 3, None, 4);
+        let _temp_topic = TempTopicCreator::new(root_path, topic_metadata.clone()).await;
+
+        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await.unwrap();
+        let mut simple_disk_topic_reader = SimpleDiskTopicReader::new(topic_metadata.clone(), SystemTime::now(), Duration::from_nanos(0));
+
+        let msgs: Vec<Message> = (0..8).map(|i| Message::new(Bytes::from(format!("hello{i}\n")), None)).collect();
+        simple_disk_topic_writer.write_batch(msgs.clone()).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(10)).await; // inorder to account for non-monotonic nature of `std::time::SystemTime``
+        simple_disk_topic_writer.flush_topic_metadata().await.unwrap();
+
+        // crosses file boundaries at offsets 3 and 6
+        let read_msgs = simple_disk_topic_reader.read_range(1, 100, 1024).await.unwrap();
+        assert_eq!(read_msgs.len(), 7);
+        for (i, read_msg) in read_msgs.iter().enumerate() {
+            assert_eq!(read_msg.value(), msgs[i + 1].value());
+            assert_eq!(read_msg.offset(), Some(&(i + 1)));
+        }
+
+        let read_msgs = simple_disk_topic_reader.read_range(2, 3, 1024).await.unwrap();
+        assert_eq!(read_msgs.iter().map(|msg| *msg.offset().unwrap()).collect::<Vec<_>>(), vec![2, 3, 4]);
+
+        // every msg is 7 bytes long
+        let read_msgs = simple_disk_topic_reader.read_range(0, 100, 20).await.unwrap();
+        assert_eq!(read_msgs.len(), 2);
+
+        let read_msgs = simple_disk_topic_reader.read_range(0, 100, 1).await.unwrap();
+        assert_eq!(read_msgs.len(), 1);
+
+        assert!(simple_disk_topic_reader.read_range(8, 100, 1024).await.unwrap().is_empty());
+    }
+
+    #[test]
+    async fn simple_disk_topic_reader_offset_for_timestamp_test_01() {
+        let root_path = "./simple_disk_topic_reader_offset_for_timestamp_test_01";
+
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
+        let topic_metadata = TopicMetaData::new(topic, 3, None, 4);
+        let _temp_topic = TempTopicCreator::new(root_path, topic_metadata.clone()).await;
+
+        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await.unwrap();
+        let mut simple_disk_topic_reader = SimpleDiskTopicReader::new(topic_metadata.clone(), SystemTime::UNIX_EPOCH, Duration::from_nanos(0));
+        assert_eq!(simple_disk_topic_reader.offset_for_timestamp(SystemTime::now()).await.unwrap(), 0);
+
+        let msgs: Vec<Message> = (0..7).map(|i| Message::new(Bytes::from(format!("hello{i}\n")), None)).collect();
+        simple_disk_topic_writer.write_batch(msgs).await.unwrap();
+        simple_disk_topic_writer.flush_topic_metadata().await.unwrap();
+
+        // files holding offsets 0-2, 3-5 and 6 were last written an hour apart
+        let start = SystemTime::now() - Duration::from_secs(3 * 3600);
+        for (file_number, hours) in [(0, 0), (1, 1), (2, 2)] {
+            let filepath = crate::topic::offset_to_file_path(&topic_metadata, &(file_number * 3));
+            let file = std::fs::File::options().write(true).open(filepath).unwrap();
+            file.set_modified(start + Duration::from_secs(hours * 3600)).unwrap();
+        }
+
+        let offset_for = |minutes: u64| start + Duration::from_secs(minutes * 60);
+        assert_eq!(simple_disk_topic_reader.offset_for_timestamp(offset_for(0)).await.unwrap(), 0);
+        assert_eq!(simple_disk_topic_reader.offset_for_timestamp(offset_for(30)).await.unwrap(), 3);
+        assert_eq!(simple_disk_topic_reader.offset_for_timestamp(offset_for(60)).await.unwrap(), 3);
+        assert_eq!(simple_disk_topic_reader.offset_for_timestamp(offset_for(90)).await.unwrap(), 6);
+        assert_eq!(simple_disk_topic_reader.offset_for_timestamp(offset_for(150)).await.unwrap(), 7);
+    }
+
+    #[test]
+    async fn simple_disk_topic_reader_isolation_level_test_01() {
+        let root_path = "./simple_disk_topic_reader_isolation_level_test_01";
+
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
+        let topic_metadata = TopicMetaData::new(topic, 3, None, 4);
+        let _temp_topic = TempTopicCreator::new(root_path, topic_metadata.clone()).await;
+
+        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await.unwrap();
+        let mut simple_disk_topic_reader = SimpleDiskTopicReader::new(topic_metadata.clone(), SystemTime::now(), Duration::from_nanos(0));
+        let transaction_log = TransactionLog::new(root_path);
+
+        let msg = |value: &'static str| Message::new(Bytes::from(value), None);
+
+        // offsets: 0 => a, 1 => BEGIN 1, 2 => b, 3 => c, 4 => BEGIN 2, 5 => d, 6 => e
+        simple_disk_topic_writer.write(msg("a\n")).await.unwrap();
+        simple_disk_topic_writer.write_transaction(1, vec![msg("b\n"), msg("c\n")]).await.unwrap();
+        simple_disk_topic_writer.write_transaction(2, vec![msg("d\n")]).await.unwrap();
+        simple_disk_topic_writer.write(msg("e\n")).await.unwrap();
+        transaction_log.set_state(1, TransactionState::Aborted).await.unwrap();
+        transaction_log.set_state(2, TransactionState::Ongoing).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(10)).await; // inorder to account for non-monotonic nature of `std::time::SystemTime``
+        simple_disk_topic_writer.flush_topic_metadata().await.unwrap();
+
+        // control records are never returned and runs stop before them
+        let read_msgs = simple_disk_topic_reader.read_range(0, 100, 1024).await.unwrap();
+        assert_eq!(read_msgs.iter().map(|msg| *msg.offset().unwrap()).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(simple_disk_topic_reader.read(1).await.unwrap().unwrap().offset(), Some(&2));
+
+        simple_disk_topic_reader.set_isolation_level(IsolationLevel::ReadCommitted);
+        assert!(simple_disk_topic_reader.read(1).await.unwrap().is_none());
+
+        transaction_log.set_state(2, TransactionState::Committed).await.unwrap();
+        let read_msgs = simple_disk_topic_reader.read_range(1, 100, 1024).await.unwrap();
+        assert_eq!(read_msgs.iter().map(|msg| *msg.offset().unwrap()).collect::<Vec<_>>(), vec![5, 6]);
+        assert_eq!(read_msgs[0].value(), &Bytes::from_static(b"d\n"));
+    }
+
+    #[test]
+    async fn simple_disk_topic_reader_isolation_level_test_02() {
+        let root_path = "./simple_disk_topic_reader_isolation_level_test_02";
+
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
+        let topic_metadata = TopicMetaData::new(topic, 3, None, 4);
+        let _temp_topic = TempTopicCreator::new(root_path, topic_metadata.clone()).await;
+
+        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await.unwrap();
+        let transaction_log = TransactionLog::new(root_path);
+
+        let msg = |value: &'static str| Message::new(Bytes::from(value), None);
+
+        // offsets: 0 => a, 1 => BEGIN 1, 2 => b, 3 => c, 4 => d, 5 => BEGIN 2, 6 => f, 7 => g, 8 => h
+        simple_disk_topic_writer.write(msg("a\n")).await.unwrap();
+        transaction_log.set_state(1, TransactionState::Ongoing).await.unwrap();
+        simple_disk_topic_writer.write_transaction(1, vec![msg("b\n"), msg("c\n"), msg("d\n")]).await.unwrap();
+        transaction_log.set_state(2, TransactionState::Ongoing).await.unwrap();
+        simple_disk_topic_writer.write_transaction(2, vec![msg("f\n"), msg("g\n")]).await.unwrap();
+        simple_disk_topic_writer.write(msg("h\n")).await.unwrap();
+        transaction_log.set_state(1, TransactionState::Aborted).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(10)).await; // inorder to account for non-monotonic nature of `std::time::SystemTime``
+        simple_disk_topic_writer.flush_topic_metadata().await.unwrap();
+
+        let reader = || {
+            let mut reader = SimpleDiskTopicReader::new(topic_metadata.clone(), SystemTime::UNIX_EPOCH, Duration::from_nanos(0));
+            reader.set_isolation_level(IsolationLevel::ReadCommitted);
+            reader
+        };
+        let offsets = |msgs: Vec<Message>| msgs.iter().map(|msg| *msg.offset().unwrap()).collect::<Vec<_>>();
+
+        // reads starting inside a transaction, even in an earlier file than the read offset, honour its outcome
+        assert!(reader().read(3).await.unwrap().is_none());
+        assert!(reader().read_range(7, 100, 1024).await.unwrap().is_empty());
+
+        transaction_log.set_state(2, TransactionState::Committed).await.unwrap();
+        assert_eq!(offsets(reader().read_range(3, 100, 1024).await.unwrap()), vec![6, 7, 8]);
+        assert_eq!(offsets(reader().read_range(4, 100, 1024).await.unwrap()), vec![6, 7, 8]);
+        assert_eq!(reader().read(7).await.unwrap().unwrap().value(), &Bytes::from_static(b"g\n"));
+
+        // reads continuing from where last one stopped
+        let mut reader = reader();
+        assert_eq!(offsets(reader.read_range(0, 1, 1024).await.unwrap()), vec![0]);
+        assert_eq!(offsets(reader.read_range(1, 1, 1024).await.unwrap()), vec![6]);
+        assert_eq!(offsets(reader.read_range(7, 100, 1024).await.unwrap()), vec![7, 8]);
+
+        reader.set_isolation_level(IsolationLevel::ReadUncommitted);
+        assert_eq!(reader.read(3).await.unwrap().unwrap().value(), &Bytes::from_static(b"c\n"));
+    }
 }
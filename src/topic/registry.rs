@@ -0,0 +1,526 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::{watch, Mutex};
+use tracing::warn;
+
+use crate::error::Error;
+use crate::types::{Durability, Topic, TopicMetaData, TopicName};
+
+use super::transaction::{ControlRecord, Transaction, TransactionLog, TransactionState};
+use super::{SimpleDiskTopicCreator, SimpleDiskTopicReader, SimpleDiskTopicWriter, TopicCreator};
+
+/// interval after which readers handed out by registry look for newly flushed messages
+const READER_UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// prefix of directories of deleted topics not yet removed from disk
+const DELETED_TOPIC_PREFIX: &str = "__deleted.";
+
+/// single entry point to topics stored under a data root, shared by all
+/// connection handlers.
+///
+/// there must be only one `SimpleDiskTopicWriter` per topic, so registry
+/// hands out shared writers instead of letting every publisher create its own.
+pub struct TopicRegistry {
+    root_path: Box<Path>,
+    writers: Mutex<HashMap<TopicName, Arc<Mutex<SimpleDiskTopicWriter>>>>,
+    /// tell watchers of a topic its `last_flushed_offset` after every flush
+    flush_notifiers: Mutex<HashMap<TopicName, watch::Sender<Option<usize>>>>,
+    transaction_log: TransactionLog,
+}
+
+impl TopicRegistry {
+    pub fn new<T: AsRef<Path>>(root_path: T) -> Self {
+        Self {
+            root_path: root_path.as_ref().into(),
+            writers: Mutex::new(HashMap::new()),
+            flush_notifiers: Mutex::new(HashMap::new()),
+            transaction_log: TransactionLog::new(&root_path),
+        }
+    }
+
+    /// aborts transactions left undecided by a previous run and removes
+    /// topics it didn't finish deleting, must be called before serving any client
+    pub async fn recover(&self) -> Result<(), Error> {
+        self.transaction_log.abort_ongoing().await?;
+
+        let mut entries = match tokio::fs::read_dir(&self.root_path).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_str().is_some_and(|name| name.starts_with(DELETED_TOPIC_PREFIX)) {
+                tokio::fs::remove_dir_all(entry.path()).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn root_path(&self) -> &Path {
+        &self.root_path
+    }
+
+    pub fn topic(&self, topic_name: TopicName) -> Topic {
+        Topic::new(topic_name, &self.root_path)
+    }
+
+    /// reads topic's metadata from disk
+    pub async fn metadata(&self, topic_name: &TopicName) -> Result<TopicMetaData, Error> {
+        let topic = self.topic(topic_name.clone());
+
+        let metadata = match tokio::fs::read_to_string(topic.metadata_path()).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(Error::NotFound(topic_name.clone())),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(toml::from_str(&metadata)?)
+    }
+
+    pub async fn create_topic(&self, topic_metadata: TopicMetaData) -> Result<(), Error> {
+        let topic = topic_metadata.topic().clone();
+
+        // creating topic's directory claims the name, of concurrent creators only one succeeds
+        tokio::fs::create_dir_all(&self.root_path).await?;
+        match tokio::fs::create_dir(topic.path()).await {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Err(Error::AlreadyExists(topic.name().clone())),
+            Err(e) => return Err(e.into()),
+        }
+
+        let result = SimpleDiskTopicCreator::new().create_topic(topic_metadata).await;
+        if result.is_err() {
+            let _ = tokio::fs::remove_dir_all(topic.path()).await; // ignore result, name stays claimed if it fails
+        }
+
+        result
+    }
+
+    /// removes topic along with every message of it. publishers of the topic
+    /// get `NoSuchTopicExists` from then on, subscribers stop seeing new messages.
+    ///
+    /// topic's directory is first renamed to a reserved name, so that once map
+    /// of writers is unlocked neither `writer` nor `reader` can find the topic.
+    pub async fn delete_topic(&self, topic_name: &TopicName) -> Result<(), Error> {
+        let topic = self.topic(topic_name.clone());
+        let tombstone_path = self.root_path.join(format!(
+            "{DELETED_TOPIC_PREFIX}{}.{}",
+            topic_name.as_str(),
+            SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos()
+        ));
+
+        let mut writers = self.writers.lock().await;
+        if !tokio::fs::try_exists(topic.metadata_path()).await? {
+            return Err(Error::NotFound(topic_name.clone()));
+        }
+
+        // writer is held until its files are renamed, a write in progress can't recreate them
+        let writer = writers.remove(topic_name);
+        let writer_guard = match writer {
+            Some(ref writer) => {
+                let mut writer = writer.lock().await;
+                writer.mark_deleted();
+                Some(writer)
+            }
+            None => None,
+        };
+
+        tokio::fs::rename(topic.path(), &tombstone_path).await?;
+        drop(writer_guard);
+        drop(writers);
+
+        // watchers are told topic is gone by their channel closing
+        self.flush_notifiers.lock().await.remove(topic_name);
+
+        tokio::fs::remove_dir_all(&tombstone_path).await?;
+
+        Ok(())
+    }
+
+    /// name of every topic, sorted
+    pub async fn topics(&self) -> Result<Vec<TopicName>, Error> {
+        let mut entries = match tokio::fs::read_dir(&self.root_path).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut topic_names = vec![];
+        while let Some(entry) = entries.next_entry().await? {
+            // reserved names (e.g. transaction log) are never topics
+            let topic_name = match entry.file_name().to_str().map(TopicName::try_from) {
+                Some(Ok(topic_name)) => topic_name,
+                _ => continue,
+            };
+
+            if tokio::fs::try_exists(self.topic(topic_name.clone()).metadata_path()).await? {
+                topic_names.push(topic_name);
+            }
+        }
+
+        topic_names.sort();
+
+        Ok(topic_names)
+    }
+
+    /// validates and applies `options` that can be altered on an existing topic
+    pub async fn alter_topic_config(&self, topic_name: &TopicName, options: &[(String, String)]) -> Result<(), Error> {
+        // hold writer (if any) so that it can't flush stale config in between, map
+        // stays locked so that neither a new writer nor another alter can either
+        let writers = self.writers.lock().await;
+        let writer = writers.get(topic_name).cloned();
+        let _writer = match writer {
+            Some(ref writer) => Some(writer.lock().await),
+            None => None,
+        };
+
+        let mut topic_metadata = self.metadata(topic_name).await?;
+        for (key, value) in options.iter() {
+            topic_metadata.set_config_option(key, value, true)?;
+        }
+
+        // readers don't lock writer, they must never see a partially written file
+        let metadata = toml::to_string(&topic_metadata)?;
+        let sync = *topic_metadata.durability() == Durability::Fsync;
+        super::write_atomically(topic_metadata.topic().metadata_path(), metadata.as_bytes(), sync).await?;
+
+        Ok(())
+    }
+
+    /// returns the writer shared by every publisher of topic
+    pub async fn writer(&self, topic_name: &TopicName) -> Result<Arc<Mutex<SimpleDiskTopicWriter>>, Error> {
+        let mut writers = self.writers.lock().await;
+
+        if let Some(writer) = writers.get(topic_name) {
+            return Ok(writer.clone());
+        }
+
+        let topic_metadata = self.metadata(topic_name).await?;
+        let mut writer = SimpleDiskTopicWriter::new(topic_metadata).await?;
+        writer.set_flush_notifier(self.flush_notifier(topic_name).await);
+        let writer = Arc::new(Mutex::new(writer));
+        writers.insert(topic_name.clone(), writer.clone());
+
+        Ok(writer)
+    }
+
+    /// receives `last_flushed_offset` of topic after every flush, channel is
+    /// closed once topic is deleted. initial value is `None` rather than
+    /// topic's current `last_flushed_offset`.
+    pub async fn watch_flushes(&self, topic_name: &TopicName) -> Result<watch::Receiver<Option<usize>>, Error> {
+        let mut flush_notifiers = self.flush_notifiers.lock().await;
+
+        // checked under lock, channel of a topic deleted in between would never be closed
+        if !tokio::fs::try_exists(self.topic(topic_name.clone()).metadata_path()).await? {
+            return Err(Error::NotFound(topic_name.clone()));
+        }
+
+        Ok(flush_notifiers.entry(topic_name.clone()).or_insert_with(|| watch::channel(None).0).subscribe())
+    }
+
+    async fn flush_notifier(&self, topic_name: &TopicName) -> watch::Sender<Option<usize>> {
+        let mut flush_notifiers = self.flush_notifiers.lock().await;
+        flush_notifiers.entry(topic_name.clone()).or_insert_with(|| watch::channel(None).0).clone()
+    }
+
+    /// persists metadata of every open writer, called once publishers are
+    /// done while shutting down
+    pub async fn flush(&self) -> Result<(), Error> {
+        let writers: Vec<_> = self.writers.lock().await.values().cloned().collect();
+        for writer in writers {
+            writer.lock().await.flush_topic_metadata().await?;
+        }
+
+        Ok(())
+    }
+
+    /// atomically writes every message of `transaction`, either all of them
+    /// become visible to `read_committed` readers or none of them do.
+    ///
+    /// messages of a topic are written as one contiguous run, preceded by a
+    /// `BEGIN` control record. transaction commits once its record is removed
+    /// from transaction log, `COMMIT` records written afterwards are only a hint.
+    pub async fn commit_transaction(&self, transaction: Transaction) -> Result<(), Error> {
+        let txn_id = transaction.txn_id();
+
+        let mut writers = vec![];
+        for (topic_name, msgs) in transaction.into_msgs() {
+            writers.push((self.writer(&topic_name).await?, msgs));
+        }
+
+        self.transaction_log.set_state(txn_id, TransactionState::Ongoing).await?;
+
+        let mut begun = vec![];
+        for (writer, msgs) in writers {
+            let mut guard = writer.lock().await;
+            let result = match guard.write_transaction(txn_id, msgs).await {
+                Ok(_) => guard.flush_topic_metadata().await,
+                Err(e) => Err(e),
+            };
+            drop(guard);
+
+            begun.push(writer);
+            if let Err(e) = result {
+                self.transaction_log.set_state(txn_id, TransactionState::Aborted).await?;
+                Self::write_control_records(&begun, ControlRecord::Abort { txn_id }).await;
+                return Err(e);
+            }
+        }
+
+        self.transaction_log.set_state(txn_id, TransactionState::Committed).await?;
+        Self::write_control_records(&begun, ControlRecord::Commit { txn_id }).await;
+
+        Ok(())
+    }
+
+    /// best effort, outcome of transaction is already durable in transaction log
+    async fn write_control_records(writers: &[Arc<Mutex<SimpleDiskTopicWriter>>], record: ControlRecord) {
+        for writer in writers {
+            let mut writer = writer.lock().await;
+            let result = match writer.write_control_record(record).await {
+                Ok(_) => writer.flush_topic_metadata().await,
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = result {
+                warn!(?record, error = %e, "failed to write control record");
+            }
+        }
+    }
+
+    /// returns a new reader, readers are cheap and are never shared
+    pub async fn reader(&self, topic_name: &TopicName) -> Result<SimpleDiskTopicReader, Error> {
+        let topic_metadata = self.metadata(topic_name).await?;
+
+        // file modification times are coarser than `SystemTime::now()`, a flush right after
+        // creating reader could otherwise look older than reader and never be picked up
+        Ok(SimpleDiskTopicReader::new(topic_metadata, SystemTime::UNIX_EPOCH, READER_UPDATE_INTERVAL))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topic::{TopicReader, TopicWriter};
+    use crate::types::Message;
+    use bytes::Bytes;
+    use tokio::test;
+
+    #[test]
+    async fn topic_registry_test_01() {
+        let root_path = "./topic_registry_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = TopicRegistry::new(root_path);
+        let topic_name: TopicName = "foo".parse().unwrap();
+
+        assert!(matches!(registry.writer(&topic_name).await, Err(Error::NotFound(_))));
+
+        let topic_metadata = TopicMetaData::new_with_few_defaults(registry.topic(topic_name.clone()));
+        registry.create_topic(topic_metadata.clone()).await.unwrap();
+        assert!(matches!(registry.create_topic(topic_metadata).await, Err(Error::AlreadyExists(_))));
+
+        // of concurrent creators of the same topic exactly one wins
+        let registry = Arc::new(registry);
+        let bar_metadata = TopicMetaData::new_with_few_defaults(registry.topic("bar".parse().unwrap()));
+        let creators: Vec<_> = (0..8)
+            .map(|_| {
+                let (registry, bar_metadata) = (registry.clone(), bar_metadata.clone());
+                tokio::spawn(async move { registry.create_topic(bar_metadata).await })
+            })
+            .collect();
+        let mut created = 0;
+        for creator in creators {
+            match creator.await.unwrap() {
+                Ok(()) => created += 1,
+                Err(e) => assert!(matches!(e, Error::AlreadyExists(_))),
+            }
+        }
+        assert_eq!(created, 1);
+
+        let writer = registry.writer(&topic_name).await.unwrap();
+        assert!(Arc::ptr_eq(&writer, &registry.writer(&topic_name).await.unwrap()));
+
+        let mut writer = writer.lock().await;
+        writer.write(Message::new(Bytes::from_static(b"hello\n"), None)).await.unwrap();
+        writer.flush_topic_metadata().await.unwrap();
+        drop(writer);
+
+        let mut reader = registry.reader(&topic_name).await.unwrap();
+        let msg = reader.read(0).await.unwrap().unwrap();
+        assert_eq!(msg.value(), &Bytes::from_static(b"hello\n"));
+    }
+
+    #[test]
+    async fn topic_registry_reader_test_01() {
+        let root_path = "./topic_registry_reader_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = TopicRegistry::new(root_path);
+        let topic_name: TopicName = "foo".parse().unwrap();
+        registry.create_topic(TopicMetaData::new_with_few_defaults(registry.topic(topic_name.clone()))).await.unwrap();
+
+        // metadata flushed right after reader was created, within granularity
+        // of file modification times, is picked up by reader's first read
+        let mut reader = registry.reader(&topic_name).await.unwrap();
+        let writer = registry.writer(&topic_name).await.unwrap();
+        let mut writer = writer.lock().await;
+        writer.write(Message::new(Bytes::from_static(b"hello\n"), None)).await.unwrap();
+        writer.flush_topic_metadata().await.unwrap();
+
+        let msg = reader.read(0).await.unwrap().unwrap();
+        assert_eq!(msg.value(), &Bytes::from_static(b"hello\n"));
+
+        // watchers are told of every flush, and of deletion by channel closing
+        let mut flushes = registry.watch_flushes(&topic_name).await.unwrap();
+        writer.write(Message::new(Bytes::from_static(b"world\n"), None)).await.unwrap();
+        writer.flush_topic_metadata().await.unwrap();
+        drop(writer);
+        flushes.changed().await.unwrap();
+        assert_eq!(*flushes.borrow_and_update(), Some(1));
+
+        registry.delete_topic(&topic_name).await.unwrap();
+        assert!(flushes.changed().await.is_err());
+        assert!(matches!(registry.watch_flushes(&topic_name).await, Err(Error::NotFound(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn topic_registry_concurrent_flush_test_01() {
+        let root_path = "./topic_registry_concurrent_flush_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = Arc::new(TopicRegistry::new(root_path));
+        let topic_name: TopicName = "foo".parse().unwrap();
+        registry.create_topic(TopicMetaData::new_with_few_defaults(registry.topic(topic_name.clone()))).await.unwrap();
+
+        let writer = registry.writer(&topic_name).await.unwrap();
+        let publisher = tokio::spawn(async move {
+            for _ in 0..500 {
+                let mut writer = writer.lock().await;
+                writer.write(Message::new(Bytes::from_static(b"hello\n"), None)).await.unwrap();
+                writer.flush_topic_metadata().await.unwrap();
+            }
+        });
+
+        // readers don't lock writer, yet never see a partially written metadata file
+        while !publisher.is_finished() {
+            registry.metadata(&topic_name).await.unwrap();
+            registry.reader(&topic_name).await.unwrap();
+        }
+        publisher.await.unwrap();
+        assert_eq!(*registry.metadata(&topic_name).await.unwrap().last_flushed_offset(), Some(499));
+    }
+
+    #[test]
+    async fn topic_registry_alter_topic_config_test_01() {
+        let root_path = "./topic_registry_alter_topic_config_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = TopicRegistry::new(root_path);
+        let topic_name: TopicName = "foo".parse().unwrap();
+        let topic_metadata = TopicMetaData::new_with_few_defaults(registry.topic(topic_name.clone()));
+        registry.create_topic(topic_metadata).await.unwrap();
+
+        let options = vec![("durability".to_owned(), "fsync".to_owned())];
+        registry.alter_topic_config(&topic_name, &options).await.unwrap();
+        assert_eq!(*registry.metadata(&topic_name).await.unwrap().durability(), Durability::Fsync);
+
+        // writer must not clobber altered config while flushing
+        let writer = registry.writer(&topic_name).await.unwrap();
+        writer.lock().await.flush_topic_metadata().await.unwrap();
+        assert_eq!(*registry.metadata(&topic_name).await.unwrap().durability(), Durability::Fsync);
+
+        let options = vec![("num_of_segments".to_owned(), "2".to_owned())];
+        assert!(matches!(
+            registry.alter_topic_config(&topic_name, &options).await,
+            Err(Error::InvalidTopicConfig(_))
+        ));
+    }
+
+    #[test]
+    async fn topic_registry_commit_transaction_test_01() {
+        let root_path = "./topic_registry_commit_transaction_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = TopicRegistry::new(root_path);
+        let topic_names: Vec<TopicName> = vec!["foo".parse().unwrap(), "bar".parse().unwrap()];
+        for topic_name in topic_names.iter() {
+            let topic_metadata = TopicMetaData::new_with_few_defaults(registry.topic(topic_name.clone()));
+            registry.create_topic(topic_metadata).await.unwrap();
+        }
+
+        let mut transaction = Transaction::begin();
+        for topic_name in topic_names.iter() {
+            transaction.add(topic_name.clone(), Message::new(Bytes::from_static(b"hello\n"), None)).unwrap();
+        }
+        registry.commit_transaction(transaction).await.unwrap();
+
+        for topic_name in topic_names.iter() {
+            let mut reader = registry.reader(topic_name).await.unwrap();
+            reader.set_isolation_level(crate::topic::IsolationLevel::ReadCommitted);
+
+            let msgs = reader.read_range(0, 100, 1024).await.unwrap();
+            assert_eq!(msgs.len(), 1);
+            assert_eq!(msgs[0].value(), &Bytes::from_static(b"hello\n"));
+            assert_eq!(msgs[0].offset(), Some(&1)); // offset 0 is BEGIN control record
+        }
+
+        // transaction touching a missing topic is rejected before anything is written
+        let mut transaction = Transaction::begin();
+        transaction.add("baz".parse().unwrap(), Message::new(Bytes::from_static(b"hello\n"), None)).unwrap();
+        assert!(matches!(registry.commit_transaction(transaction).await, Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    async fn topic_registry_delete_topic_test_01() {
+        let root_path = "./topic_registry_delete_topic_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = TopicRegistry::new(root_path);
+        assert_eq!(registry.topics().await.unwrap(), vec![]);
+
+        for topic_name in ["foo", "bar"] {
+            let topic_metadata = TopicMetaData::new_with_few_defaults(registry.topic(topic_name.parse().unwrap()));
+            registry.create_topic(topic_metadata).await.unwrap();
+        }
+        // transaction log dir isn't a topic
+        registry.transaction_log.set_state(1, TransactionState::Aborted).await.unwrap();
+        let topic_name: TopicName = "foo".parse().unwrap();
+        assert_eq!(registry.topics().await.unwrap(), vec!["bar".parse::<TopicName>().unwrap(), topic_name.clone()]);
+
+        let writer = registry.writer(&topic_name).await.unwrap();
+        registry.delete_topic(&topic_name).await.unwrap();
+        assert_eq!(registry.topics().await.unwrap(), vec!["bar".parse::<TopicName>().unwrap()]);
+        assert!(matches!(registry.delete_topic(&topic_name).await, Err(Error::NotFound(_))));
+        assert!(matches!(registry.writer(&topic_name).await, Err(Error::NotFound(_))));
+        assert!(matches!(registry.reader(&topic_name).await, Err(Error::NotFound(_))));
+
+        // publishers holding old writer can't write into a topic recreated under the same name
+        let topic_metadata = TopicMetaData::new_with_few_defaults(registry.topic(topic_name.clone()));
+        registry.create_topic(topic_metadata).await.unwrap();
+        let msg = Message::new(Bytes::from_static(b"hello\n"), None);
+        assert!(matches!(writer.lock().await.write(msg.clone()).await, Err(Error::NotFound(_))));
+        assert_eq!(registry.writer(&topic_name).await.unwrap().lock().await.write(msg).await.unwrap(), 0);
+    }
+
+    #[test]
+    async fn topic_registry_recover_test_01() {
+        let root_path = "./topic_registry_recover_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let registry = TopicRegistry::new(root_path);
+        registry.transaction_log.set_state(1, TransactionState::Ongoing).await.unwrap();
+        // topic whose deletion was interrupted by a crash
+        let tombstone_path = Path::new(root_path).join(format!("{DELETED_TOPIC_PREFIX}foo.1"));
+        tokio::fs::create_dir_all(&tombstone_path).await.unwrap();
+        registry.recover().await.unwrap();
+
+        assert_eq!(registry.transaction_log.state(1).await.unwrap(), TransactionState::Aborted);
+        assert!(!tombstone_path.exists());
+    }
+}
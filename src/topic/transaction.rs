@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use bytes::Bytes;
+
+use crate::error::Error;
+use crate::types::{Message, TopicName};
+
+/// first byte of every control record, messages starting with it are rejected by writers
+pub const CONTROL_RECORD_PREFIX: u8 = 0x1e;
+
+/// name of directory, under data root, holding state of every transaction
+pub(crate) const TRANSACTIONS_DIR_NAME: &str = "__transactions";
+
+/// max number of messages, across all topics, in a single transaction
+pub const MAX_TRANSACTION_LEN: usize = 4096;
+
+/// records written to segment files alongside messages to delimit transactions,
+/// they occupy an offset like any other message but are never handed to readers.
+///
+/// * `\x1eBEGIN <txn_id> <count>` is immediately followed by `count` messages of transaction.
+/// * `\x1eCOMMIT <txn_id>` / `\x1eABORT <txn_id>` are written once outcome is decided.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlRecord {
+    Begin { txn_id: u64, count: usize },
+    Commit { txn_id: u64 },
+    Abort { txn_id: u64 },
+}
+
+/// state of a transaction as recorded in transaction log
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransactionState {
+    /// messages are (being) written, outcome not yet decided
+    Ongoing,
+    Committed,
+    Aborted,
+}
+
+/// durable record of transactions' state, one file per undecided or aborted
+/// transaction under `<root_path>/__transactions`. committed transactions have
+/// no file, removing file of a transaction is its commit point.
+pub(crate) struct TransactionLog {
+    dir_path: PathBuf,
+}
+
+/// messages of a transaction buffered in memory until it is committed
+#[derive(Debug)]
+pub struct Transaction {
+    txn_id: u64,
+    msgs: BTreeMap<TopicName, Vec<Message>>,
+    len: usize,
+}
+
+impl ControlRecord {
+    /// `None` if `line` is not a control record
+    pub fn parse(line: &[u8]) -> Option<Self> {
+        let line = line.strip_prefix(&[CONTROL_RECORD_PREFIX])?;
+        let line = std::str::from_utf8(line).ok()?.trim_end_matches('\n');
+        let mut tokens = line.split(' ');
+
+        let record = match (tokens.next()?, tokens.next()?.parse().ok()?) {
+            ("BEGIN", txn_id) => Self::Begin {
+                txn_id,
+                count: tokens.next()?.parse().ok()?,
+            },
+            ("COMMIT", txn_id) => Self::Commit { txn_id },
+            ("ABORT", txn_id) => Self::Abort { txn_id },
+            _ => return None,
+        };
+
+        tokens.next().is_none().then_some(record)
+    }
+
+    pub fn to_message(&self) -> Message {
+        let prefix = CONTROL_RECORD_PREFIX as char;
+        let record = match self {
+            Self::Begin { txn_id, count } => format!("{prefix}BEGIN {txn_id} {count}\n"),
+            Self::Commit { txn_id } => format!("{prefix}COMMIT {txn_id}\n"),
+            Self::Abort { txn_id } => format!("{prefix}ABORT {txn_id}\n"),
+        };
+
+        Message::from(Bytes::from(record))
+    }
+}
+
+impl TransactionState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ongoing => "ongoing",
+            Self::Committed => "committed",
+            Self::Aborted => "aborted",
+        }
+    }
+}
+
+impl TransactionLog {
+    pub(crate) fn new<T: AsRef<Path>>(root_path: T) -> Self {
+        Self {
+            dir_path: root_path.as_ref().join(TRANSACTIONS_DIR_NAME),
+        }
+    }
+
+    /// transactions with no record are committed, `BEGIN` records are only
+    /// written after transaction is recorded as `Ongoing`
+    pub(crate) async fn state(&self, txn_id: u64) -> Result<TransactionState, Error> {
+        let state = match tokio::fs::read_to_string(self.dir_path.join(txn_id.to_string())).await {
+            Ok(state) => state,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(TransactionState::Committed),
+            Err(e) => return Err(e.into()),
+        };
+
+        match state.as_str() {
+            "ongoing" => Ok(TransactionState::Ongoing),
+            "aborted" => Ok(TransactionState::Aborted),
+            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("invalid state of transaction {txn_id}")).into()),
+        }
+    }
+
+    /// durably records `state` of transaction, state files are replaced
+    /// atomically so a crash never leaves a partially written one behind.
+    /// aborted transactions are kept for as long as their messages are.
+    pub(crate) async fn set_state(&self, txn_id: u64, state: TransactionState) -> Result<(), Error> {
+        tokio::fs::create_dir_all(&self.dir_path).await?;
+
+        let path = self.dir_path.join(txn_id.to_string());
+        if state != TransactionState::Committed {
+            return super::write_atomically(&path, state.as_str().as_bytes(), true).await;
+        }
+
+        match tokio::fs::remove_file(&path).await {
+            Ok(_) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+        tokio::fs::File::open(&self.dir_path).await?.sync_all().await?;
+
+        Ok(())
+    }
+
+    /// marks every transaction left `Ongoing` (e.g. by a crash) as `Aborted`
+    pub(crate) async fn abort_ongoing(&self) -> Result<(), Error> {
+        let mut entries = match tokio::fs::read_dir(&self.dir_path).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let txn_id = match entry.file_name().to_str().and_then(|name| name.parse().ok()) {
+                Some(txn_id) => txn_id,
+                None => continue,
+            };
+
+            if self.state(txn_id).await? == TransactionState::Ongoing {
+                self.set_state(txn_id, TransactionState::Aborted).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Transaction {
+    /// starts a transaction with an id unique across server restarts
+    pub fn begin() -> Self {
+        static NEXT_TXN_ID: AtomicU64 = AtomicU64::new(0);
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |now| now.as_nanos() as u64);
+        // ids are monotonic within a process and start from current time in nanos
+        let _ = NEXT_TXN_ID.fetch_max(now, Ordering::Relaxed);
+        let txn_id = NEXT_TXN_ID.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            txn_id,
+            msgs: BTreeMap::new(),
+            len: 0,
+        }
+    }
+
+    pub fn txn_id(&self) -> u64 {
+        self.txn_id
+    }
+
+    /// buffers `msg` to be written to `topic_name` on commit
+    pub fn add(&mut self, topic_name: TopicName, msg: Message) -> Result<(), Error> {
+        if self.len == MAX_TRANSACTION_LEN {
+            return Err(Error::InvalidArgument(format!("transaction can't have more than {MAX_TRANSACTION_LEN} messages")));
+        }
+
+        check_not_control_record(&msg)?;
+
+        self.msgs.entry(topic_name).or_default().push(msg);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// buffered messages grouped by topic, topics in sorted order
+    pub(crate) fn into_msgs(self) -> BTreeMap<TopicName, Vec<Message>> {
+        self.msgs
+    }
+}
+
+/// messages from clients must never be mistaken for control records
+pub(crate) fn check_not_control_record(msg: &Message) -> Result<(), Error> {
+    match msg.value().first() {
+        Some(&CONTROL_RECORD_PREFIX) => Err(Error::InvalidArgument(format!(
+            "message can not start with byte {CONTROL_RECORD_PREFIX:#04x}"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::test;
+
+    #[test]
+    async fn control_record_test_01() {
+        let records = [
+            ControlRecord::Begin { txn_id: 42, count: 3 },
+            ControlRecord::Commit { txn_id: 42 },
+            ControlRecord::Abort { txn_id: 42 },
+        ];
+
+        for record in records {
+            assert_eq!(ControlRecord::parse(record.to_message().value()), Some(record));
+        }
+
+        assert_eq!(ControlRecord::parse(b"BEGIN 42 3\n"), None);
+        assert_eq!(ControlRecord::parse(b"\x1eBEGIN 42\n"), None);
+        assert_eq!(ControlRecord::parse(b"\x1eCOMMIT 42 3\n"), None);
+    }
+
+    #[test]
+    async fn transaction_log_test_01() {
+        let root_path = "./transaction_log_test_01";
+        let _cleanup = crate::topic::TempTopicCreator(root_path);
+
+        let transaction_log = TransactionLog::new(root_path);
+        assert_eq!(transaction_log.state(1).await.unwrap(), TransactionState::Committed);
+
+        transaction_log.set_state(1, TransactionState::Ongoing).await.unwrap();
+        transaction_log.set_state(2, TransactionState::Ongoing).await.unwrap();
+        transaction_log.set_state(2, TransactionState::Committed).await.unwrap();
+        transaction_log.abort_ongoing().await.unwrap();
+
+        assert_eq!(transaction_log.state(1).await.unwrap(), TransactionState::Aborted);
+        assert_eq!(transaction_log.state(2).await.unwrap(), TransactionState::Committed);
+
+        // committed transactions leave nothing behind, not even temporary files
+        let mut entries = tokio::fs::read_dir(Path::new(root_path).join(TRANSACTIONS_DIR_NAME)).await.unwrap();
+        let mut names = vec![];
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            names.push(entry.file_name().into_string().unwrap());
+        }
+        assert_eq!(names, vec!["1"]);
+    }
+
+    #[test]
+    async fn transaction_test_01() {
+        let first = Transaction::begin();
+        let mut second = Transaction::begin();
+        assert!(second.txn_id() > first.txn_id());
+
+        let msg = Message::from(Bytes::from_static(b"\x1eCOMMIT 1\n"));
+        assert!(matches!(second.add("foo".parse().unwrap(), msg), Err(Error::InvalidArgument(_))));
+    }
+}
@@ -1,88 +1,342 @@
-use std::error::Error;
+use std::collections::{HashMap, VecDeque};
 use std::path::Path;
 
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+use tracing::{instrument, trace, warn};
 
-use crate::types::{Message, TopicMetaData};
+use crate::error::Error;
+use crate::types::{Durability, Message, TopicMetaData};
+
+use super::transaction::{check_not_control_record, ControlRecord};
 
 #[async_trait]
 pub trait TopicWriter {
     type Error;
 
-    async fn write(&mut self, msg: Message) -> Result<(), Self::Error>;
+    /// returns offset assigned to `msg`
+    async fn write(&mut self, msg: Message) -> Result<usize, Self::Error>;
+
+    /// writes all of `msgs` or none of them, returns offset assigned to
+    /// first msg of the batch, rest of msgs get consecutive offsets.
+    async fn write_batch(&mut self, msgs: Vec<Message>) -> Result<usize, Self::Error>;
+
+    /// writes `msg` only if `sequence` is the next one expected from
+    /// `producer_id`, so that retries of an already written msg are rejected
+    /// instead of being written twice. returns offset assigned to `msg`.
+    async fn write_sequenced(&mut self, producer_id: u64, sequence: u64, msg: Message) -> Result<usize, Self::Error>;
+}
+
+/// number of most recent sequences remembered per producer, retries of older
+/// sequences can't be deduplicated and are rejected as out of order
+pub const PRODUCER_SEQUENCE_WINDOW: usize = 5;
+
+/// max number of producers remembered per topic, the one that wrote least
+/// recently is forgotten to make room for a new one
+pub const MAX_PRODUCERS_PER_TOPIC: usize = 1024;
+
+/// name of file, inside topic's directory, holding producers' dedup state
+const PRODUCERS_FILE_NAME: &str = ".producers.toml";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+struct ProducerState {
+    /// `(sequence, offset)` of most recent writes, oldest first
+    recent: VecDeque<(u64, usize)>,
 }
 
 pub struct SimpleDiskTopicWriter {
     data_insertion_file_path: Box<Path>,
     writer_offset: usize,
     topic_metadata: TopicMetaData,
+    producers: HashMap<u64, ProducerState>,
+    /// set once topic is deleted, every write fails from then on
+    deleted: bool,
+    /// told `last_flushed_offset` after every flush
+    flush_notifier: Option<watch::Sender<Option<usize>>>,
 }
 
 impl SimpleDiskTopicWriter {
-    pub async fn new(topic_metadata: TopicMetaData) -> Self {
+    /// fails if producers' dedup state exists but can't be read, starting
+    /// without it would write retries of producers twice
+    pub async fn new(topic_metadata: TopicMetaData) -> Result<Self, Error> {
         let topic_path = topic_metadata.topic().path();
 
+        let flushed_writer_offset = topic_metadata.last_flushed_offset().map_or(0, |offset| offset + 1);
         let writer_offset: usize = tokio::fs::read_to_string(topic_path.join(".writer_offset.txt"))
             .await
-            .unwrap_or("0".to_owned())
-            .parse()
-            .unwrap_or(0);
+            .ok()
+            .and_then(|offset| offset.parse().ok())
+            .unwrap_or(flushed_writer_offset);
 
         let data_insertion_file_path = super::offset_to_file_path(&topic_metadata, &writer_offset);
 
-        Self {
+        let producers = match tokio::fs::read_to_string(topic_path.join(PRODUCERS_FILE_NAME)).await {
+            Ok(producers) => toml::from_str::<HashMap<String, ProducerState>>(&producers)?
+                .into_iter()
+                .map(|(producer_id, state)| match producer_id.parse() {
+                    Ok(producer_id) => Ok((producer_id, state)),
+                    Err(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("invalid producer id `{producer_id}`"))),
+                })
+                .collect::<Result<_, _>>()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
             data_insertion_file_path,
             writer_offset,
             topic_metadata,
+            producers,
+            deleted: false,
+            flush_notifier: None,
+        })
+    }
+
+    pub(crate) fn set_flush_notifier(&mut self, flush_notifier: watch::Sender<Option<usize>>) {
+        self.flush_notifier = Some(flush_notifier);
+    }
+
+    /// makes every following write fail, so that publishers still holding
+    /// the writer can't write into a topic recreated under the same name
+    pub(crate) fn mark_deleted(&mut self) {
+        self.deleted = true;
+        self.flush_notifier = None;
+    }
+
+    fn check_not_deleted(&self) -> Result<(), Error> {
+        match self.deleted {
+            true => Err(Error::NotFound(self.topic_metadata.topic().name().clone())),
+            false => Ok(()),
         }
     }
 
-    pub async fn flush_topic_metadata(&mut self) -> Result<(), Box<dyn Error>> {
-        let mut file = tokio::fs::File::create(self.topic_metadata.topic().metadata_path()).await?;
-        
+    /// persists `last_flushed_offset` to topic's metadata file, options
+    /// altered on disk since writer was created are picked up in the process
+    #[instrument(level = "trace", skip_all, fields(topic = %self.topic_metadata.topic().name()))]
+    pub async fn flush_topic_metadata(&mut self) -> Result<(), Error> {
+        self.check_not_deleted()?;
+        let metadata_path = self.topic_metadata.topic().metadata_path().to_owned();
+
+        if let Ok(on_disk_metadata) = tokio::fs::read_to_string(&metadata_path).await {
+            if let Ok(on_disk_metadata) = toml::from_str::<TopicMetaData>(&on_disk_metadata) {
+                self.topic_metadata.copy_alterable_config(&on_disk_metadata);
+            }
+        }
+
         unsafe {
             let new_offset = if self.writer_offset == 0 { None } else { Some(self.writer_offset - 1) };
             self.topic_metadata.set_last_flushed_offset(new_offset);
         }
 
+        // readers don't lock writer, they must never see a partially written file
         let metadata = toml::to_string(&self.topic_metadata)?;
-        file.write_all(metadata.as_bytes()).await?;
+        let sync = *self.topic_metadata.durability() == Durability::Fsync;
+        super::write_atomically(&metadata_path, metadata.as_bytes(), sync).await?;
+
+        trace!(last_flushed_offset = ?self.topic_metadata.last_flushed_offset(), "flushed topic metadata");
+        if let Some(ref flush_notifier) = self.flush_notifier {
+            flush_notifier.send_replace(*self.topic_metadata.last_flushed_offset());
+        }
 
         Ok(())
     }
+
+    /// remembers that `sequence` of `producer_id` was written at `offset`
+    fn record_sequence(&mut self, producer_id: u64, sequence: u64, offset: usize) {
+        if !self.producers.contains_key(&producer_id) && self.producers.len() >= MAX_PRODUCERS_PER_TOPIC {
+            let least_recent = self
+                .producers
+                .iter()
+                .min_by_key(|(_, state)| state.recent.back().map(|(_, offset)| *offset))
+                .map(|(producer_id, _)| *producer_id);
+
+            if let Some(least_recent) = least_recent {
+                self.producers.remove(&least_recent);
+            }
+        }
+
+        let state = self.producers.entry(producer_id).or_default();
+        state.recent.push_back((sequence, offset));
+        if state.recent.len() > PRODUCER_SEQUENCE_WINDOW {
+            state.recent.pop_front();
+        }
+    }
+
+    /// atomically replaces producers' dedup state on disk
+    async fn persist_producers(&self) -> Result<(), Error> {
+        let producers: HashMap<String, &ProducerState> = self
+            .producers
+            .iter()
+            .map(|(producer_id, state)| (producer_id.to_string(), state))
+            .collect();
+
+        let producers_path = self.topic_metadata.topic().path().join(PRODUCERS_FILE_NAME);
+        let sync = *self.topic_metadata.durability() == Durability::Fsync;
+        super::write_atomically(&producers_path, toml::to_string(&producers)?.as_bytes(), sync).await
+    }
+
+    /// checks whether `sequence` is the next one expected from `producer_id`
+    fn check_sequence(&self, producer_id: u64, sequence: u64) -> Result<(), Error> {
+        let last_sequence = match self.producers.get(&producer_id).and_then(|state| state.recent.back()) {
+            Some((last_sequence, _)) => *last_sequence,
+            None => return Ok(()), // first msg of a new producer can start from any sequence
+        };
+
+        if sequence == last_sequence.wrapping_add(1) {
+            return Ok(());
+        }
+
+        let written = self.producers[&producer_id]
+            .recent
+            .iter()
+            .find(|(written_sequence, _)| *written_sequence == sequence);
+
+        match written {
+            Some((_, offset)) => Err(Error::DuplicateSequence { sequence, offset: *offset }),
+            None => Err(Error::OutOfOrderSequence {
+                sequence,
+                expected: last_sequence.wrapping_add(1),
+            }),
+        }
+    }
+
+    async fn open_for_append(path: &Path) -> Result<tokio::fs::File, Error> {
+        match tokio::fs::File::options().append(true).open(path).await {
+            Ok(file) => Ok(file),
+            Err(e) if e.kind() == tokio::io::ErrorKind::NotFound => Ok(tokio::fs::File::create(path).await?),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// appends `msgs` file by file, length of every file touched before
+    /// appending to it is recorded in `touched_files`
+    async fn append_batch(&self, msgs: &[Message], touched_files: &mut Vec<(Box<Path>, u64)>) -> Result<(), Error> {
+        let num_of_msg_per_file = *self.topic_metadata.num_of_msg_per_file();
+        let mut offset = self.writer_offset;
+        let mut msgs = msgs;
+
+        while !msgs.is_empty() {
+            let chunk_len = msgs.len().min(num_of_msg_per_file - offset % num_of_msg_per_file);
+            let (chunk, rest) = msgs.split_at(chunk_len);
+
+            let path = super::offset_to_file_path(&self.topic_metadata, &offset);
+            let mut file = Self::open_for_append(&path).await?;
+            touched_files.push((path, file.metadata().await?.len()));
+
+            let data: Vec<u8> = chunk.iter().flat_map(|msg| msg.value().iter().copied()).collect();
+            file.write_all(&data).await?;
+
+            if *self.topic_metadata.durability() == Durability::Fsync {
+                file.sync_data().await?;
+            }
+
+            offset += chunk_len;
+            msgs = rest;
+        }
+
+        Ok(())
+    }
+
+    /// writes `records` without checking whether they are control records
+    #[instrument(level = "trace", skip_all, fields(topic = %self.topic_metadata.topic().name(), base_offset = self.writer_offset, records = records.len()))]
+    async fn write_records(&mut self, records: Vec<Message>) -> Result<usize, Error> {
+        self.check_not_deleted()?;
+        let base_offset = self.writer_offset;
+        let mut touched_files = vec![];
+
+        if let Err(e) = self.append_batch(&records, &mut touched_files).await {
+            warn!(error = %e, "failed to append records, truncating touched files");
+            for (path, len) in touched_files {
+                if let Ok(file) = tokio::fs::File::options().write(true).open(&path).await {
+                    let _ = file.set_len(len).await; // ignore result, nothing more can be done
+                }
+            }
+
+            return Err(e);
+        }
+
+        self.writer_offset += records.len();
+        self.data_insertion_file_path = super::offset_to_file_path(&self.topic_metadata, &self.writer_offset);
+        trace!("appended records");
+
+        Ok(base_offset)
+    }
+
+    /// atomically writes `BEGIN` control record immediately followed by `msgs`,
+    /// returns offset of `BEGIN` record
+    pub async fn write_transaction(&mut self, txn_id: u64, msgs: Vec<Message>) -> Result<usize, Error> {
+        for msg in msgs.iter() {
+            check_not_control_record(msg)?;
+        }
+
+        let mut records = Vec::with_capacity(msgs.len() + 1);
+        records.push(ControlRecord::Begin { txn_id, count: msgs.len() }.to_message());
+        records.extend(msgs);
+
+        self.write_records(records).await
+    }
+
+    pub async fn write_control_record(&mut self, record: ControlRecord) -> Result<usize, Error> {
+        self.write_records(vec![record.to_message()]).await
+    }
 }
 
 #[async_trait]
 impl TopicWriter for SimpleDiskTopicWriter {
-    type Error = std::io::Error;
+    type Error = Error;
 
     /// appends msg.value to the end of approperiate file
     /// assumes msg.value contains \n at the end of msg
     /// data (value).
-    async fn write(&mut self, msg: Message) -> Result<(), Self::Error> {
-        let mut file = match tokio::fs::File::options()
-            .append(true)
-            .open(&self.data_insertion_file_path)
-            .await
-        {
-            Ok(file) => file,
-            Err(e) if e.kind() == tokio::io::ErrorKind::NotFound => {
-                tokio::fs::File::create(&self.data_insertion_file_path).await?
-            }
-            Err(e) => return Err(e),
-        };
+    #[instrument(level = "trace", skip_all, fields(topic = %self.topic_metadata.topic().name(), offset = self.writer_offset))]
+    async fn write(&mut self, msg: Message) -> Result<usize, Self::Error> {
+        self.check_not_deleted()?;
+        check_not_control_record(&msg)?;
+
+        let mut file = Self::open_for_append(&self.data_insertion_file_path).await?;
 
         file.write_all(msg.value()).await?;
 
+        if *self.topic_metadata.durability() == Durability::Fsync {
+            file.sync_data().await?;
+        }
+
+        let offset = self.writer_offset;
         self.writer_offset += 1;
 
-        if self.writer_offset % self.topic_metadata.num_of_msg_per_file() == 0 {
+        if self.writer_offset.is_multiple_of(*self.topic_metadata.num_of_msg_per_file()) {
             self.data_insertion_file_path =
                 super::offset_to_file_path(&self.topic_metadata, &self.writer_offset);
         }
 
-        Ok(())
+        trace!("appended message");
+
+        Ok(offset)
+    }
+
+    /// a batch may span multiple files, if appending to any of them fails
+    /// every file touched is truncated back to its length before the batch.
+    async fn write_batch(&mut self, msgs: Vec<Message>) -> Result<usize, Self::Error> {
+        for msg in msgs.iter() {
+            check_not_control_record(msg)?;
+        }
+
+        self.write_records(msgs).await
+    }
+
+    /// dedup state is persisted before returning, so that the msg is never
+    /// acknowledged without it. if persisting fails msg stays written and a
+    /// retry is answered with `DuplicateSequence` as long as writer lives.
+    async fn write_sequenced(&mut self, producer_id: u64, sequence: u64, msg: Message) -> Result<usize, Self::Error> {
+        self.check_sequence(producer_id, sequence)?;
+
+        let offset = self.write(msg).await?;
+        self.record_sequence(producer_id, sequence, offset);
+        self.persist_producers().await?;
+
+        Ok(offset)
     }
 }
 
@@ -98,12 +352,12 @@ mod tests {
     async fn simple_disk_topic_writer_dot_flush_topic_metadata_test_01() {
         let root_path = "./simple_disk_topic_writer_dot_flush_topic_metadata_test_01";
 
-        let topic = Topic::new("foo".to_owned(), root_path);
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
         let topic_metadata = TopicMetaData::new_with_few_defaults(topic);
 
         let _temp_topic = TempTopicCreator::new(root_path, topic_metadata.clone()).await;
 
-        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await;
+        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await.unwrap();
         simple_disk_topic_writer
             .flush_topic_metadata()
             .await
@@ -121,12 +375,12 @@ mod tests {
     async fn simple_disk_topic_writer_dot_write_test_produce_single_msg_01() {
         let root_path = "./simple_disk_topic_writer_dot_write_test_produce_single_msg_01";
 
-        let topic = Topic::new("foo".to_owned(), root_path);
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
         let topic_metadata = TopicMetaData::new_with_few_defaults(topic);
 
         let _temp_topic = TempTopicCreator::new(root_path, topic_metadata.clone()).await;
 
-        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await;
+        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await.unwrap();
 
         let msg_to_be_written = "hello\n";
         let msg = Message::new(Bytes::from(msg_to_be_written), None);
@@ -142,12 +396,12 @@ mod tests {
     async fn simple_disk_topic_writer_dot_write_test_produce_multi_msg_01() {
         let root_path = "./simple_disk_topic_writer_dot_write_test_produce_multi_msg_01";
 
-        let topic = Topic::new("foo".to_owned(), root_path);
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
         let topic_metadata = TopicMetaData::new_with_few_defaults(topic);
 
         let _temp_topic = TempTopicCreator::new(root_path, topic_metadata.clone()).await;
 
-        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await;
+        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await.unwrap();
 
         let mut written_msg_content_should_be = String::new();
         for i in 0..(*topic_metadata.num_of_msg_per_file()) {
@@ -169,12 +423,12 @@ mod tests {
     async fn simple_disk_topic_writer_dot_write_test_produce_multi_msg_02() {
         let root_path = "./simple_disk_topic_writer_dot_write_test_produce_multi_msg_02";
 
-        let topic = Topic::new("foo".to_owned(), root_path);
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
         let topic_metadata = TopicMetaData::new_with_few_defaults(topic);
 
         let _temp_topic = TempTopicCreator::new(root_path, topic_metadata.clone()).await;
 
-        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await;
+        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await.unwrap();
 
         let mut written_msg_content_should_be = String::new();
         for i in 0..(*topic_metadata.num_of_msg_per_file()) {
@@ -201,4 +455,110 @@ mod tests {
         assert_eq!(written_msg.as_str(), msg_val);
     }
 
+    #[test]
+    async fn simple_disk_topic_writer_dot_write_batch_test_01() {
+        let root_path = "./simple_disk_topic_writer_dot_write_batch_test_01";
+
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
+        let topic_metadata = TopicMetaData::new(topic, 3, None, 4);
+
+        let _temp_topic = TempTopicCreator::new(root_path, topic_metadata.clone()).await;
+
+        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await.unwrap();
+
+        let msg = Message::new(Bytes::from_static(b"first\n"), None);
+        assert_eq!(simple_disk_topic_writer.write(msg).await.unwrap(), 0);
+
+        // spans 3 files: offsets [1, 2], [3, 4, 5] and [6]
+        let msgs: Vec<Message> = (1..7).map(|i| Message::new(Bytes::from(format!("hello{i}\n")), None)).collect();
+        let base_offset = simple_disk_topic_writer.write_batch(msgs).await.unwrap();
+        assert_eq!(base_offset, 1);
+
+        let written_msg = tokio::fs::read_to_string(offset_to_file_path(&topic_metadata, &0)).await.unwrap();
+        assert_eq!(written_msg, "first\nhello1\nhello2\n");
+
+        let written_msg = tokio::fs::read_to_string(offset_to_file_path(&topic_metadata, &3)).await.unwrap();
+        assert_eq!(written_msg, "hello3\nhello4\nhello5\n");
+
+        let msg = Message::new(Bytes::from_static(b"last\n"), None);
+        assert_eq!(simple_disk_topic_writer.write(msg).await.unwrap(), 7);
+
+        let written_msg = tokio::fs::read_to_string(offset_to_file_path(&topic_metadata, &6)).await.unwrap();
+        assert_eq!(written_msg, "hello6\nlast\n");
+    }
+
+    #[test]
+    async fn simple_disk_topic_writer_dot_write_sequenced_test_01() {
+        let root_path = "./simple_disk_topic_writer_dot_write_sequenced_test_01";
+
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
+        let topic_metadata = TopicMetaData::new_with_few_defaults(topic);
+
+        let _temp_topic = TempTopicCreator::new(root_path, topic_metadata.clone()).await;
+
+        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await.unwrap();
+        let msg = || Message::new(Bytes::from_static(b"hello\n"), None);
+
+        assert_eq!(simple_disk_topic_writer.write_sequenced(7, 10, msg()).await.unwrap(), 0);
+        assert_eq!(simple_disk_topic_writer.write_sequenced(8, 0, msg()).await.unwrap(), 1);
+        assert_eq!(simple_disk_topic_writer.write_sequenced(7, 11, msg()).await.unwrap(), 2);
+
+        assert!(matches!(
+            simple_disk_topic_writer.write_sequenced(7, 10, msg()).await,
+            Err(Error::DuplicateSequence { sequence: 10, offset: 0 })
+        ));
+        assert!(matches!(
+            simple_disk_topic_writer.write_sequenced(7, 13, msg()).await,
+            Err(Error::OutOfOrderSequence { sequence: 13, expected: 12 })
+        ));
+
+        simple_disk_topic_writer.flush_topic_metadata().await.unwrap();
+
+        // dedup state survives writer restarts
+        let topic_metadata: TopicMetaData = toml::from_str(
+            &tokio::fs::read_to_string(topic_metadata.topic().metadata_path()).await.unwrap()
+        ).unwrap();
+        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await.unwrap();
+
+        assert!(matches!(
+            simple_disk_topic_writer.write_sequenced(7, 11, msg()).await,
+            Err(Error::DuplicateSequence { sequence: 11, offset: 2 })
+        ));
+        assert_eq!(simple_disk_topic_writer.write_sequenced(7, 12, msg()).await.unwrap(), 3);
+
+        // even without a metadata flush, state is persisted before write is acknowledged
+        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await.unwrap();
+        assert!(matches!(
+            simple_disk_topic_writer.write_sequenced(7, 12, msg()).await,
+            Err(Error::DuplicateSequence { sequence: 12, offset: 3 })
+        ));
+
+        // corrupt state must not silently reset every producer
+        let producers_path = topic_metadata.topic().path().join(PRODUCERS_FILE_NAME);
+        tokio::fs::write(&producers_path, "[7]\nrecent = [[12,").await.unwrap();
+        assert!(matches!(SimpleDiskTopicWriter::new(topic_metadata).await, Err(Error::Storage(_))));
+    }
+
+    #[test]
+    async fn simple_disk_topic_writer_dot_write_sequenced_test_02() {
+        let root_path = "./simple_disk_topic_writer_dot_write_sequenced_test_02";
+
+        let topic = Topic::new("foo".parse().unwrap(), root_path);
+        let topic_metadata = TopicMetaData::new_with_few_defaults(topic);
+
+        let _temp_topic = TempTopicCreator::new(root_path, topic_metadata.clone()).await;
+
+        let mut simple_disk_topic_writer = SimpleDiskTopicWriter::new(topic_metadata.clone()).await.unwrap();
+        for producer_id in 0..MAX_PRODUCERS_PER_TOPIC as u64 {
+            simple_disk_topic_writer.record_sequence(producer_id, 0, producer_id as usize);
+        }
+        simple_disk_topic_writer.record_sequence(0, 1, MAX_PRODUCERS_PER_TOPIC);
+
+        // producer that wrote least recently makes room for a new one
+        let msg = Message::new(Bytes::from_static(b"hello\n"), None);
+        simple_disk_topic_writer.write_sequenced(u64::MAX, 0, msg).await.unwrap();
+        assert_eq!(simple_disk_topic_writer.producers.len(), MAX_PRODUCERS_PER_TOPIC);
+        assert!(simple_disk_topic_writer.producers.contains_key(&0));
+        assert!(!simple_disk_topic_writer.producers.contains_key(&1));
+    }
 }
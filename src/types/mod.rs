@@ -1,3 +1,4 @@
+use std::fmt;
 use std::path::Path;
 
 use bytes::Bytes;
@@ -9,9 +10,28 @@ pub struct Message {
     offset: Option<usize>,
 }
 
+/// name of a topic, guaranteed to be safe for use as a single path component
+/// under the data root.
+///
+/// a valid name is 1 to `TopicName::MAX_LEN` bytes long, only contains ascii
+/// alphanumerics, `.`, `_` and `-`, doesn't start with `.` and doesn't start
+/// with `__` which is reserved for topics used internally by stream-relay.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct TopicName(String);
+
+/// reason a topic name was rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum TopicNameError {
+    Empty,
+    TooLong(usize),
+    InvalidCharacter(char),
+    Reserved,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Topic {
-    name: String,
+    name: TopicName,
     path: Box<Path>,
     metadata_path: Box<Path>,
 }
@@ -28,6 +48,42 @@ pub struct TopicMetaData {
     num_of_msg_per_file: usize,
     last_flushed_offset: Option<usize>,
     num_of_segments: usize,
+    #[serde(default)]
+    retention_ms: Option<u64>,
+    #[serde(default)]
+    durability: Durability,
+    #[serde(default)]
+    storage_format: StorageFormat,
+}
+
+/// controls when written messages are forced to stable storage
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Durability {
+    /// leave it to the OS to eventually write page cache back to disk
+    #[default]
+    Buffered,
+    /// `fsync` segment file after every write
+    Fsync,
+}
+
+/// on disk layout of messages inside a segment file
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageFormat {
+    /// one message per line, messages are terminated by `\n`
+    #[default]
+    Text,
+}
+
+/// error returned when a `key=value` topic configuration option is rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum TopicConfigError {
+    UnknownKey(String),
+    InvalidValue { key: String, value: String },
+    NotAlterable(String),
+    /// option is recognised but its value is not yet acted upon by server
+    NotSupported { key: String, value: String },
 }
 
 impl Message {
@@ -40,10 +96,7 @@ impl Message {
     }
 
     pub fn offset(&self) -> Option<&usize> {
-        match self.offset {
-            Some(ref offset) => Some(offset),
-            None => None,
-        }
+        self.offset.as_ref()
     }
 
     pub fn set_offset(&mut self, offset: usize) {
@@ -57,9 +110,102 @@ impl From<Bytes> for Message {
     }
 }
 
+impl TopicName {
+    pub const MAX_LEN: usize = 249;
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for TopicName {
+    type Error = TopicNameError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            return Err(TopicNameError::Empty);
+        }
+
+        if value.len() > Self::MAX_LEN {
+            return Err(TopicNameError::TooLong(value.len()));
+        }
+
+        if let Some(c) = value.chars().find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))) {
+            return Err(TopicNameError::InvalidCharacter(c));
+        }
+
+        if value.starts_with('.') || value.starts_with("__") {
+            return Err(TopicNameError::Reserved);
+        }
+
+        Ok(Self(value))
+    }
+}
+
+impl TryFrom<&str> for TopicName {
+    type Error = TopicNameError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::try_from(value.to_owned())
+    }
+}
+
+impl TryFrom<&[u8]> for TopicName {
+    type Error = TopicNameError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        match std::str::from_utf8(value) {
+            Ok(value) => Self::try_from(value),
+            Err(_) => {
+                let c = String::from_utf8_lossy(value).chars().find(|c| !c.is_ascii()).unwrap_or(char::REPLACEMENT_CHARACTER);
+                Err(TopicNameError::InvalidCharacter(c))
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for TopicName {
+    type Err = TopicNameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(s)
+    }
+}
+
+impl From<TopicName> for String {
+    fn from(value: TopicName) -> Self {
+        value.0
+    }
+}
+
+impl AsRef<str> for TopicName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for TopicName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for TopicNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "topic name can not be empty"),
+            Self::TooLong(len) => write!(f, "topic name is {len} bytes long, max allowed is {}", TopicName::MAX_LEN),
+            Self::InvalidCharacter(c) => write!(f, "topic name can not contain {c:?}"),
+            Self::Reserved => write!(f, "topic names starting with `.` or `__` are reserved"),
+        }
+    }
+}
+
+impl std::error::Error for TopicNameError {}
+
 impl Topic {
-    pub fn new<T: AsRef<Path>>(name: String, root_path: T) -> Self {
-        let path = root_path.as_ref().join(&name).into_boxed_path();
+    pub fn new<T: AsRef<Path>>(name: TopicName, root_path: T) -> Self {
+        let path = root_path.as_ref().join(name.as_str()).into_boxed_path();
         let metadata_path = path.join("metadata.toml").into_boxed_path();
 
         Self {
@@ -69,7 +215,7 @@ impl Topic {
         }
     }
 
-    pub fn name(&self) -> &str {
+    pub fn name(&self) -> &TopicName {
         &self.name
     }
 
@@ -104,16 +250,14 @@ impl TopicMetaData {
             num_of_msg_per_file,
             last_flushed_offset,
             num_of_segments,
+            retention_ms: None,
+            durability: Durability::default(),
+            storage_format: StorageFormat::default(),
         }
     }
 
     pub fn new_with_few_defaults(topic: Topic) -> Self {
-        Self {
-            topic,
-            num_of_msg_per_file: 32,
-            last_flushed_offset: None,
-            num_of_segments: 64,
-        }
+        Self::new(topic, 32, None, 64)
     }
 
     pub fn topic(&self) -> &Topic {
@@ -130,6 +274,9 @@ impl TopicMetaData {
 
     /// this function should only be used by those who implement `stream_relay::topic::TopicWriter`
     /// trait and marked unsafe because unintentional updates might lead to cascading failure
+    ///
+    /// # Safety
+    /// caller must ensure that every message upto `new_offset` has actually been written to disk
     pub unsafe fn set_last_flushed_offset(&mut self, new_offset: Option<usize>) {
         self.last_flushed_offset = new_offset;
     }
@@ -137,10 +284,125 @@ impl TopicMetaData {
     pub fn num_of_segments(&self) -> &usize {
         &self.num_of_segments
     }
+
+    pub fn retention_ms(&self) -> &Option<u64> {
+        &self.retention_ms
+    }
+
+    pub fn durability(&self) -> &Durability {
+        &self.durability
+    }
+
+    pub fn storage_format(&self) -> &StorageFormat {
+        &self.storage_format
+    }
+
+    /// validates and applies a single `key=value` configuration option.
+    ///
+    /// when `alter` is true only options that are safe to change for a topic
+    /// that already has data on disk are accepted, options that decide the
+    /// on disk layout (segment sizing, storage format) are rejected.
+    ///
+    /// supported options:
+    /// * `num_of_msg_per_file=<positive integer>`
+    /// * `num_of_segments=<positive integer>`
+    /// * `retention_ms=<none>`, nothing deletes old segments yet so a positive
+    ///   value is rejected rather than silently ignored
+    /// * `durability=<buffered | fsync>`
+    /// * `storage_format=<text>`
+    pub fn set_config_option(&mut self, key: &str, value: &str, alter: bool) -> Result<(), TopicConfigError> {
+        let invalid_value = || TopicConfigError::InvalidValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        };
+
+        match key {
+            "num_of_msg_per_file" | "num_of_segments" | "storage_format" if alter => {
+                return Err(TopicConfigError::NotAlterable(key.to_owned()));
+            }
+            "num_of_msg_per_file" => {
+                self.num_of_msg_per_file = parse_positive(value).ok_or_else(invalid_value)?;
+            }
+            "num_of_segments" => {
+                self.num_of_segments = parse_positive(value).ok_or_else(invalid_value)?;
+            }
+            "retention_ms" => {
+                self.retention_ms = match value {
+                    "none" => None,
+                    _ => {
+                        parse_positive::<u64>(value).ok_or_else(invalid_value)?;
+                        return Err(TopicConfigError::NotSupported {
+                            key: key.to_owned(),
+                            value: value.to_owned(),
+                        });
+                    }
+                };
+            }
+            "durability" => {
+                self.durability = match value {
+                    "buffered" => Durability::Buffered,
+                    "fsync" => Durability::Fsync,
+                    _ => return Err(invalid_value()),
+                };
+            }
+            "storage_format" => {
+                self.storage_format = match value {
+                    "text" => StorageFormat::Text,
+                    _ => return Err(invalid_value()),
+                };
+            }
+            _ => return Err(TopicConfigError::UnknownKey(key.to_owned())),
+        }
+
+        Ok(())
+    }
+
+    /// every option accepted by `set_config_option` along with its current value
+    pub fn config_options(&self) -> Vec<(String, String)> {
+        let durability = match self.durability {
+            Durability::Buffered => "buffered",
+            Durability::Fsync => "fsync",
+        };
+        let storage_format = match self.storage_format {
+            StorageFormat::Text => "text",
+        };
+
+        vec![
+            ("num_of_msg_per_file".to_owned(), self.num_of_msg_per_file.to_string()),
+            ("num_of_segments".to_owned(), self.num_of_segments.to_string()),
+            ("retention_ms".to_owned(), self.retention_ms.map_or("none".to_owned(), |retention_ms| retention_ms.to_string())),
+            ("durability".to_owned(), durability.to_owned()),
+            ("storage_format".to_owned(), storage_format.to_owned()),
+        ]
+    }
+
+    /// copies over every option that can be changed through `set_config_option`
+    /// with `alter = true` from `other`
+    pub fn copy_alterable_config(&mut self, other: &TopicMetaData) {
+        self.retention_ms = other.retention_ms;
+        self.durability = other.durability;
+    }
+}
+
+fn parse_positive<T: std::str::FromStr + Default + PartialOrd>(value: &str) -> Option<T> {
+    value.parse().ok().filter(|value| *value > T::default())
+}
+
+impl fmt::Display for TopicConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownKey(key) => write!(f, "unknown config key `{key}`"),
+            Self::InvalidValue { key, value } => write!(f, "invalid value `{value}` for config key `{key}`"),
+            Self::NotAlterable(key) => write!(f, "config key `{key}` can not be altered after topic creation"),
+            Self::NotSupported { key, value } => write!(f, "value `{value}` for config key `{key}` is not supported yet"),
+        }
+    }
 }
 
+impl std::error::Error for TopicConfigError {}
+
 impl TryFrom<Topic> for TopicMetaData {
-    type Error = Box<dyn std::error::Error>;
+    type Error = crate::error::Error;
 
     /// [BLOCKING I/O Used] tries to read metadata about topic from disk
     /// and deserialize it into `Self`
@@ -148,11 +410,104 @@ impl TryFrom<Topic> for TopicMetaData {
     /// # Error:
     /// if topic doesn't exist on disk OR invalid data in topic's metadata
     /// file to be deserialized.
-
     fn try_from(value: Topic) -> Result<Self, Self::Error> {
-        let metadata = std::fs::read_to_string(value.metadata_path())?;
+        let metadata = match std::fs::read_to_string(value.metadata_path()) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(crate::error::Error::NotFound(value.name().clone()))
+            }
+            Err(e) => return Err(e.into()),
+        };
         let metadata: TopicMetaData = toml::from_str(&metadata)?;
 
         Ok(metadata)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topic_metadata_set_config_option_test_01() {
+        let topic = Topic::new("foo".parse().unwrap(), "./topic_metadata_set_config_option_test_01");
+        let mut topic_metadata = TopicMetaData::new_with_few_defaults(topic);
+
+        topic_metadata.set_config_option("num_of_msg_per_file", "128", false).unwrap();
+        topic_metadata.set_config_option("num_of_segments", "8", false).unwrap();
+        topic_metadata.set_config_option("durability", "fsync", false).unwrap();
+        topic_metadata.set_config_option("storage_format", "text", false).unwrap();
+
+        assert_eq!(*topic_metadata.num_of_msg_per_file(), 128);
+        assert_eq!(*topic_metadata.num_of_segments(), 8);
+        assert_eq!(*topic_metadata.durability(), Durability::Fsync);
+        assert_eq!(*topic_metadata.storage_format(), StorageFormat::Text);
+
+        // every option reported can be applied back to a topic
+        let mut copy = TopicMetaData::new_with_few_defaults(topic_metadata.topic().clone());
+        for (key, value) in topic_metadata.config_options() {
+            copy.set_config_option(&key, &value, false).unwrap();
+        }
+        assert_eq!(copy, topic_metadata);
+    }
+
+    #[test]
+    fn topic_metadata_set_config_option_test_02() {
+        let topic = Topic::new("foo".parse().unwrap(), "./topic_metadata_set_config_option_test_02");
+        let mut topic_metadata = TopicMetaData::new_with_few_defaults(topic);
+
+        assert_eq!(
+            topic_metadata.set_config_option("num_of_segments", "0", false),
+            Err(TopicConfigError::InvalidValue { key: "num_of_segments".to_owned(), value: "0".to_owned() })
+        );
+        assert_eq!(
+            topic_metadata.set_config_option("no_such_key", "1", false),
+            Err(TopicConfigError::UnknownKey("no_such_key".to_owned()))
+        );
+        assert_eq!(
+            topic_metadata.set_config_option("num_of_msg_per_file", "64", true),
+            Err(TopicConfigError::NotAlterable("num_of_msg_per_file".to_owned()))
+        );
+
+        topic_metadata.set_config_option("retention_ms", "none", true).unwrap();
+        assert_eq!(*topic_metadata.retention_ms(), None);
+
+        // retention is not enforced, so it can't be turned on
+        assert_eq!(
+            topic_metadata.set_config_option("retention_ms", "60000", true),
+            Err(TopicConfigError::NotSupported { key: "retention_ms".to_owned(), value: "60000".to_owned() })
+        );
+        assert_eq!(
+            topic_metadata.set_config_option("retention_ms", "0", false),
+            Err(TopicConfigError::InvalidValue { key: "retention_ms".to_owned(), value: "0".to_owned() })
+        );
+        assert_eq!(*topic_metadata.retention_ms(), None);
+    }
+
+    #[test]
+    fn topic_name_test_01() {
+        for name in ["foo", "foo.bar", "foo_bar-01", "_foo", "a"] {
+            assert_eq!(TopicName::try_from(name).unwrap().as_str(), name);
+        }
+    }
+
+    #[test]
+    fn topic_name_test_02() {
+        assert_eq!(TopicName::try_from(""), Err(TopicNameError::Empty));
+        assert_eq!(TopicName::try_from("../../etc"), Err(TopicNameError::InvalidCharacter('/')));
+        assert_eq!(TopicName::try_from("/etc"), Err(TopicNameError::InvalidCharacter('/')));
+        assert_eq!(TopicName::try_from("foo\\bar"), Err(TopicNameError::InvalidCharacter('\\')));
+        assert_eq!(TopicName::try_from("foo bar"), Err(TopicNameError::InvalidCharacter(' ')));
+        assert_eq!(TopicName::try_from(".."), Err(TopicNameError::Reserved));
+        assert_eq!(TopicName::try_from(".hidden"), Err(TopicNameError::Reserved));
+        assert_eq!(TopicName::try_from("__internal"), Err(TopicNameError::Reserved));
+        assert_eq!(TopicName::try_from("a".repeat(TopicName::MAX_LEN + 1)), Err(TopicNameError::TooLong(TopicName::MAX_LEN + 1)));
+        assert_eq!(TopicName::try_from(&b"foo\xff"[..]), Err(TopicNameError::InvalidCharacter(char::REPLACEMENT_CHARACTER)));
+    }
+
+    #[test]
+    fn topic_name_deserialize_test() {
+        let topic: Result<Topic, _> = toml::from_str("name = \"../etc\"\npath = \"../etc\"\nmetadata_path = \"../etc/metadata.toml\"\n");
+        assert!(topic.is_err());
+    }
+}